@@ -0,0 +1,46 @@
+//! Time opening a large generated workbook with and without `--lazy`.
+//!
+//! `cargo run --release --example open_time [sheets] [rows]` writes a workbook of `sheets`
+//! sheets (default 8) with `rows` rows of 10 columns each (default 20000) to the temp
+//! directory, then prints its size and how long `App::new` takes to open it both ways.
+
+use std::time::Instant;
+use term_xlsx::App;
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1).map(|arg| arg.parse::<u32>());
+    let sheets = args.next().transpose()?.unwrap_or(8);
+    let rows = args.next().transpose()?.unwrap_or(20_000);
+
+    let path = std::env::temp_dir().join(format!("term-xlsx-open-time-{}x{}.xlsx", sheets, rows));
+    let mut book = umya_spreadsheet::new_file();
+    for index in 0..sheets {
+        let sheet = if index == 0 {
+            book.get_sheet_mut(&0).expect("a new file has one sheet")
+        } else {
+            book.new_sheet(format!("Sheet{}", index + 1)).map_err(anyhow::Error::msg)?
+        };
+        for row in 1..=rows {
+            for col in 1..=10 {
+                let cell = sheet.get_cell_mut((col, row));
+                if col % 2 == 0 {
+                    cell.set_value_number(row as f64 * col as f64);
+                } else {
+                    cell.set_value(format!("r{}c{}", row, col));
+                }
+            }
+        }
+    }
+    umya_spreadsheet::writer::xlsx::write(&book, &path).map_err(|e| anyhow::anyhow!("Failed to write {:?}: {}", path, e))?;
+    let size = std::fs::metadata(&path)?.len();
+    println!("{:?}: {} sheet(s) x {} row(s), {:.1} MB", path, sheets, rows, size as f64 / 1_048_576.0);
+
+    for lazy in [false, true] {
+        let started = Instant::now();
+        let app = App::new(path.clone(), lazy)?;
+        println!("{:<8} opened in {:>8.0} ms", if lazy { "--lazy" } else { "default" }, started.elapsed().as_secs_f64() * 1000.0);
+        drop(app);
+    }
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
@@ -1,23 +1,110 @@
 use anyhow::Result;
-use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
-use std::collections::HashMap;
-use std::path::PathBuf;
-use tui_textarea::TextArea;
-use umya_spreadsheet::{Color, NumberingFormat, PatternValues, Spreadsheet, helper::number_format::to_formatted_string};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+use crate::grid::{self, GridLayout};
+use crate::import::{self, FileFormat};
+use crate::search::SearchOptions;
+use tui_textarea::{CursorMove, TextArea};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+use umya_spreadsheet::{Cell, Color, Comment, NumberingFormat, PatternValues, Spreadsheet, Style, Worksheet, helper::number_format::to_formatted_string};
 
+// Defaults for --col-width, --col-step and --max-col-width
 pub const DEFAULT_COLUMN_WIDTH: u16 = 10;
 pub const COLUMN_WIDTH_STEP: u16 = 2;
 pub const MAX_COLUMN_WIDTH: u16 = 50;
+pub const MIN_COLUMN_WIDTH: u16 = 3;
+pub const COLUMN_WIDTH_LIMIT: u16 = 255; // Excel's own maximum; no setting may exceed it
+pub const ROW_NUMBER_WIDTH: u16 = 6; // grows for row numbers past 99999
+pub const MOUSE_SCROLL_ROWS: i32 = 3;
+pub const SHEET_LIST_ROWS: usize = 13; // sheets the selector shows at once; PageUp/PageDown move this far
+pub const NEWLINE_MARKER: &str = "⏎";
+pub const MAX_ROW_DISPLAY_HEIGHT: u16 = 4;
+pub const MESSAGE_LOG_SIZE: usize = 100;
+pub const UNDO_STEPS: usize = 50; // oldest undo steps are forgotten past this many
+/// Excel measures column width in characters of the default font plus ~0.71 of cell padding
+/// (its default 8.43 fits 8 digits). A terminal column holds one character, so widths convert
+/// as `excel = columns + EXCEL_WIDTH_PADDING`.
+pub const EXCEL_WIDTH_PADDING: f64 = 0.71;
 
-// Old Excel limits (XLS format)
-pub const MAX_COLUMNS: u32 = 256;   // A to IV
-pub const MAX_ROWS: u32 = 65536;
+/// Number formats offered by the format popup: (label, format code)
+pub const NUMBER_FORMATS: [(&str, &str); 6] = [
+    ("General", NumberingFormat::FORMAT_GENERAL),
+    ("0.00", "0.00"),
+    ("#,##0", "#,##0"),
+    ("0%", "0%"),
+    ("yyyy-mm-dd", "yyyy-mm-dd"),
+    ("$#,##0.00", "\"$\"#,##0.00"),
+];
 
+/// Colors offered by the color picker, selected with 0-9/A-F: (label, ARGB).
+/// White is saved as Excel's indexed white; the others are not in the indexed palette and stay explicit ARGB.
+pub const COLOR_PALETTE: [(&str, u32); 16] = [
+    ("White", WHITE),
+    ("Light gray", 0xFFD9D9D9),
+    ("Gray", 0xFF7F7F7F),
+    ("Dark gray", 0xFF404040),
+    ("Red", 0xFFE53935),
+    ("Orange", 0xFFFB8C00),
+    ("Amber", 0xFFFFC107),
+    ("Yellow", 0xFFFFEB3B),
+    ("Lime", 0xFFC0CA33),
+    ("Green", 0xFF43A047),
+    ("Teal", 0xFF00897B),
+    ("Cyan", 0xFF00ACC1),
+    ("Blue", 0xFF1E88E5),
+    ("Indigo", 0xFF3949AB),
+    ("Purple", 0xFF8E24AA),
+    ("Pink", 0xFFD81B60),
+];
+
+/// Excel's indexed color palette (the `indexed` attribute of a color), as ARGB.
+/// Entries 0-7 repeat 8-15 for compatibility with old files.
+pub const EXCEL_INDEXED_COLORS: [u32; 64] = [
+    0xFF000000, 0xFFFFFFFF, 0xFFFF0000, 0xFF00FF00, 0xFF0000FF, 0xFFFFFF00, 0xFFFF00FF, 0xFF00FFFF,
+    0xFF000000, 0xFFFFFFFF, 0xFFFF0000, 0xFF00FF00, 0xFF0000FF, 0xFFFFFF00, 0xFFFF00FF, 0xFF00FFFF,
+    0xFF800000, 0xFF008000, 0xFF000080, 0xFF808000, 0xFF800080, 0xFF008080, 0xFFC0C0C0, 0xFF808080,
+    0xFF9999FF, 0xFF993366, 0xFFFFFFCC, 0xFFCCFFFF, 0xFF660066, 0xFFFF8080, 0xFF0066CC, 0xFFCCCCFF,
+    0xFF000080, 0xFFFF00FF, 0xFFFFFF00, 0xFF00FFFF, 0xFF800080, 0xFF800000, 0xFF008080, 0xFF0000FF,
+    0xFF00CCFF, 0xFFCCFFFF, 0xFFCCFFCC, 0xFFFFFF99, 0xFF99CCFF, 0xFFFF99CC, 0xFFCC99FF, 0xFFFFCC99,
+    0xFF3366FF, 0xFF33CCCC, 0xFF99CC00, 0xFFFFCC00, 0xFFFF9900, 0xFFFF6600, 0xFF666699, 0xFF969696,
+    0xFF003366, 0xFF339966, 0xFF003300, 0xFF333300, 0xFF993300, 0xFF993366, 0xFF333399, 0xFF333333,
+];
+
+// True colors written for the built-in marks
+const MARK_YELLOW: u32 = 0xFFFFFF00;
+const MARK_RED: u32 = 0xFFFF0000;
+const MARK_GREEN: u32 = 0xFF008000;
+const MARK_BLUE: u32 = 0xFF0000FF;
+const MARK_MAGENTA: u32 = 0xFFFF00FF;
+const BLACK: u32 = 0xFF000000;
+const WHITE: u32 = 0xFFFFFFFF;
+
+// Excel limits (XLSX format)
+pub const MAX_COLUMNS: u32 = 16384; // A to XFD
+pub const MAX_ROWS: u32 = 1_048_576;
+
+/// What the keyboard is currently driving: the grid, a prompt or a popup
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     View,
     Edit,
     SheetSelect,
+    ConfirmQuit,
+    SaveAs,
+    ConfirmOverwrite,
+    ConfirmDiskChange,
+    Format,
+    Command,
+    Replace,
+    Help,
+    Comment,
+    ColorPick,
+    MessageLog,
+    Move,
 }
 
 /// Selection range: (start_row, start_col, end_row, end_col) all 1-based
@@ -54,11 +141,91 @@ impl Selection {
     }
 }
 
+/// Cells of one sheet as they were before a fill or replace-all, put back by Ctrl+Z
+#[derive(Debug, Clone)]
+pub struct UndoStep {
+    pub label: &'static str, // the change undone, for the status message
+    pub sheet: usize,
+    pub cells: Vec<((u32, u32), Option<Cell>)>, // (col, row) -> the cell before, None where there was none
+}
+
+/// Cursor, selection and scroll position remembered for a sheet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SheetViewState {
+    pub cursor: (u32, u32),
+    pub selection: Selection,
+    pub scroll: (u32, u32),
+}
+
+impl Default for SheetViewState {
+    fn default() -> Self {
+        Self {
+            cursor: (1, 1),
+            selection: Selection::single(1, 1),
+            scroll: (0, 0),
+        }
+    }
+}
+
 /// Clipboard for copy/paste
 #[derive(Debug, Clone, Default)]
 pub struct Clipboard {
     /// 2D array of cell values: clipboard[row][col]
     pub data: Vec<Vec<String>>,
+    /// Formatting and formulas for each copied cell, same shape as `data`
+    pub cells: Vec<Vec<ClipboardCell>>,
+    /// Merged blocks inside the copied range: (top-left, bottom-right) as (row, col) offsets
+    pub merges: Vec<((u32, u32), (u32, u32))>,
+    /// Top-left (row, col) of the copied range, so pasted formulas can shift their references
+    pub origin: (u32, u32),
+}
+
+/// Per-cell extras captured on copy and restored by "paste with formatting"
+#[derive(Debug, Clone, Default)]
+pub struct ClipboardCell {
+    pub number_format: Option<String>, // None for General
+    pub mark: CellMark,
+    pub formula: Option<String>,
+}
+
+/// Aggregates over the cells of a selection
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SelectionStats {
+    pub count: usize,         // non-empty cells
+    pub numeric_count: usize, // cells holding a number
+    pub sum: f64,
+}
+
+impl SelectionStats {
+    pub fn average(&self) -> Option<f64> {
+        (self.numeric_count > 0).then(|| self.sum / self.numeric_count as f64)
+    }
+}
+
+/// Which input of the find & replace prompt is being edited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplaceField {
+    #[default]
+    Find,
+    Replace,
+}
+
+/// A cell's content as laid out in the grid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CellDisplay {
+    pub text: String,
+    pub truncated: bool, // the trailing `~` is the overflow marker, not part of the value
+    pub color: Option<u32>, // ARGB text color from the number format, e.g. `[Red]` on negatives
+}
+
+/// Find & replace prompt state, kept between invocations
+#[derive(Debug, Clone, Default)]
+pub struct ReplaceState {
+    pub query: String,
+    pub replacement: String,
+    pub field: ReplaceField,
+    pub options: SearchOptions,
+    pub scope: Option<Selection>, // None = whole sheet
 }
 
 /// Cell marking style
@@ -71,160 +238,824 @@ pub enum CellMark {
     GreenText,   // 4: Green text - OK/done
     BlueBg,      // 5: Blue background - category A
     MagentaText, // 6: Magenta text - category B
+    Custom { argb: u32, is_bg: bool }, // Color picker or any other color read from the file
 }
 
-pub struct App<'a> {
+/// Font emphasis that can be toggled on a selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emphasis {
+    Bold,
+    Italic,
+    Underline,
+}
+
+/// Font emphasis currently applied to a cell
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FontEmphasis {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl FontEmphasis {
+    fn has(&self, emphasis: Emphasis) -> bool {
+        match emphasis {
+            Emphasis::Bold => self.bold,
+            Emphasis::Italic => self.italic,
+            Emphasis::Underline => self.underline,
+        }
+    }
+}
+
+/// The most recent mutating action, re-applied at the cursor/selection by `.`
+#[derive(Debug, Clone, PartialEq)]
+pub enum LastAction {
+    SetValue(String),
+    Mark(CellMark),
+    Clear,
+    Fill { down: bool, copy_only: bool },
+    NumberFormat { label: String, code: String },
+    Decimals(i32),
+    Emphasis(Emphasis),
+}
+
+/// What kind of value a cell holds, as shown in the status bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellType {
+    Empty,
+    Text,
+    Number,
+    Date,
+    Boolean,
+    Error,
+    Formula,
+}
+
+impl CellType {
+    pub fn label(self) -> &'static str {
+        match self {
+            CellType::Empty => "Empty",
+            CellType::Text => "Text",
+            CellType::Number => "Number",
+            CellType::Date => "Date",
+            CellType::Boolean => "Boolean",
+            CellType::Error => "Error",
+            CellType::Formula => "Formula",
+        }
+    }
+}
+
+/// How date and time cells are shown (set with --date-format)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum DateDisplay {
+    #[default]
+    Iso, // 2024-03-31
+    Us, // 03/31/2024
+    Eu, // 31/03/2024
+    Preserve, // the cell's own format code
+}
+
+/// Where the cursor goes after an edit is committed with Enter or Tab (set with --enter-moves / --tab-moves)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum EntryMove {
+    Down,
+    Up,
+    Right,
+    Left,
+    Stay,
+}
+
+impl EntryMove {
+    /// (dx, dy) cursor step
+    fn delta(self) -> (i32, i32) {
+        match self {
+            EntryMove::Down => (0, 1),
+            EntryMove::Up => (0, -1),
+            EntryMove::Right => (1, 0),
+            EntryMove::Left => (-1, 0),
+            EntryMove::Stay => (0, 0),
+        }
+    }
+}
+
+/// What typed cell input is stored as (see `parse_input_to_cell_value`)
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputValue {
+    Number(f64),
+    Bool(bool),
+    Error(String),
+    Text(String),
+}
+
+/// Error values Excel accepts as typed input
+const ERROR_LITERALS: &[&str] = &["#NULL!", "#DIV/0!", "#VALUE!", "#REF!", "#NAME?", "#NUM!", "#N/A", "#GETTING_DATA"];
+
+/// Text clean-up applied by the `:trim`, `:squeeze`, `:upper` and `:lower` commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextTransform {
+    Trim, // strip leading/trailing whitespace
+    Squeeze, // trim and collapse internal runs of whitespace to one space
+    Upper,
+    Lower,
+}
+
+impl TextTransform {
+    fn apply(self, text: &str) -> String {
+        match self {
+            TextTransform::Trim => text.trim().to_string(),
+            TextTransform::Squeeze => text.split_whitespace().collect::<Vec<_>>().join(" "),
+            TextTransform::Upper => text.to_uppercase(),
+            TextTransform::Lower => text.to_lowercase(),
+        }
+    }
+}
+
+/// How the grid area is divided when split
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitDirection {
+    Vertical, // panes side by side
+    Horizontal, // panes stacked
+}
+
+/// A second pane on the current sheet; the active workbook's cursor/selection/scroll are the focused pane's
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitView {
+    pub direction: SplitDirection,
+    pub other: SheetViewState, // view of the unfocused pane
+}
+
+/// An open workbook with its view: the active one is `App::workbook`, the others are parked
+pub struct Workbook {
     pub path: PathBuf,
+    pub format: FileFormat, // format the workbook was read from; always saved as XLSX
     pub spreadsheet: Spreadsheet,
     pub current_sheet_index: usize,
     pub cursor: (u32, u32), // (row, col) 1-based
     pub selection: Selection,
-    pub mode: Mode,
-    pub scroll: (u32, u32), // (row_offset, col_offset) 0-based
-    pub textarea: TextArea<'a>,
-    pub should_quit: bool,
-    pub column_widths: HashMap<u32, u16>, // col index -> width
-    pub clipboard: Clipboard,
-    pub status_message: Option<String>,
-    pub viewport_size: (u16, u16), // (rows, cols) visible in grid
+    pub scroll: (u32, u32), // (row_offset, col_offset) 0-based, relative to the first unfrozen line
+    pub frozen_rows: u32, // rows 1..=frozen_rows always stay visible
+    pub frozen_cols: u32, // cols 1..=frozen_cols always stay visible
+    pub dirty: bool, // unsaved changes since last save
+    pub disk_mtime: Option<SystemTime>, // modified time of the file when it was read or last saved
+    pub imported_from: Option<PathBuf>, // ODS/XLS/XLSB file the values came from, until first saved as XLSX
+    pub column_widths: HashMap<(usize, u32), u16>, // (sheet_index, col) -> width
     pub cell_marks: HashMap<(usize, u32, u32), CellMark>, // (sheet_index, row, col) -> mark
-    pub sheet_select_index: usize, // cursor position in sheet select mode
+    pub sheet_views: HashMap<usize, SheetViewState>, // sheet index -> view to restore on return
+    pub undo: Vec<UndoStep>, // most recent last
 }
 
-impl<'a> App<'a> {
-    pub fn new(path: PathBuf) -> Result<Self> {
-        let spreadsheet = if path.exists() {
-            umya_spreadsheet::reader::xlsx::read(&path).map_err(|e| anyhow::anyhow!("Failed to read file: {}", e))?
+impl Workbook {
+    /// Read the file at `path`, or start a new single-sheet workbook if it doesn't exist.
+    /// It opens on the first visible sheet. With `lazy`, only that sheet is parsed up front; the others are parsed on first visit.
+    /// ODS/XLS/XLSB files have their values imported and are saved next to the original as `.xlsx`.
+    pub fn open(path: PathBuf, lazy: bool) -> Result<Self> {
+        let format = if path.exists() { FileFormat::detect(&path)? } else { FileFormat::Xlsx };
+        let (path, spreadsheet, imported_from) = if format != FileFormat::Xlsx {
+            let spreadsheet = import::import_values(&path)?;
+            (path.with_extension("xlsx"), spreadsheet, Some(path))
         } else {
-            let mut book = umya_spreadsheet::new_file();
-            let _ = book.new_sheet("Sheet1");
-            book
+            let spreadsheet = Self::read_xlsx(&path, lazy)?;
+            (path, spreadsheet, None)
         };
 
-        // Load existing cell marks from spreadsheet styles
-        let cell_marks = Self::load_cell_marks_from_spreadsheet(&spreadsheet);
+        // Load existing cell marks and column widths from the file
+        let cell_marks = App::load_cell_marks_from_spreadsheet(&spreadsheet);
+        let mut column_widths = HashMap::new();
+        for (sheet_idx, sheet) in spreadsheet.get_sheet_collection().iter().enumerate() {
+            App::load_column_widths_from_sheet(sheet_idx, sheet, &mut column_widths);
+        }
 
         Ok(Self {
+            // An .xlsx already next to an imported file isn't this workbook's, so saving over it asks first
+            disk_mtime: if imported_from.is_some() { None } else { modified_time(&path) },
+            imported_from,
             path,
+            format,
+            current_sheet_index: first_visible_sheet(&spreadsheet),
             spreadsheet,
-            current_sheet_index: 0,
             cursor: (1, 1),
             selection: Selection::single(1, 1),
-            mode: Mode::View,
             scroll: (0, 0),
+            frozen_rows: 0,
+            frozen_cols: 0,
+            dirty: false,
+            column_widths,
+            cell_marks,
+            sheet_views: HashMap::new(),
+            undo: Vec::new(),
+        })
+    }
+
+    fn read_xlsx(path: &Path, lazy: bool) -> Result<Spreadsheet> {
+        let spreadsheet = if path.exists() && lazy {
+            let mut book = umya_spreadsheet::reader::xlsx::lazy_read(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read file: {}", e))?;
+            if book.get_sheet_count() > 0 {
+                book.read_sheet(first_visible_sheet(&book));
+            }
+            book
+        } else if path.exists() {
+            umya_spreadsheet::reader::xlsx::read(path).map_err(|e| anyhow::anyhow!("Failed to read file: {}", e))?
+        } else {
+            let mut book = umya_spreadsheet::new_file();
+            let _ = book.new_sheet("Sheet1");
+            book
+        };
+        Ok(spreadsheet)
+    }
+
+    /// Status note for a workbook whose values were imported from another format
+    fn import_note(&self) -> Option<String> {
+        (self.format != FileFormat::Xlsx).then(|| format!(
+            "Imported values from {} file; saving writes {:?}", self.format.label(), self.path
+        ))
+    }
+}
+
+/// A save running on a background thread
+pub struct PendingSave {
+    pub path: PathBuf,
+    pub started: Instant,
+    handle: JoinHandle<Result<()>>,
+}
+
+/// The editor state: open workbooks, cursor, selection, clipboard and UI modes.
+/// Drive it with `on_key`/`on_mouse`/`on_tick` and render it with `ui::draw`.
+pub struct App<'a> {
+    pub workbook: Workbook, // the active workbook; other open ones wait in `workbooks`
+    pub mode: Mode,
+    pub textarea: TextArea<'a>,
+    pub should_quit: bool,
+    pub needs_redraw: bool, // something visible changed since the last frame
+    pub read_only: bool, // reject all edits and saves
+    pub show_formulas: bool, // display formula text instead of cached results
+    pub peek_formula: bool, // display the cursor cell's formula text, leaving other cells as results
+    pub default_column_width: u16, // width of columns the file doesn't size
+    pub date_display: DateDisplay, // how date/time cells are shown
+    pub column_width_step: u16, // change per widen/shrink key press
+    pub max_column_width: u16, // widest any column is shown
+    pub row_display_height: u16, // terminal lines per grid row; >1 wraps cell text
+    pub clipboard: Clipboard,
+    pub last_action: Option<LastAction>, // repeated by `.`
+    pub cut_source: Option<(usize, Selection)>, // (sheet_index, range) cleared by the next paste
+    pub moving: Option<Selection>, // block being moved with M; the selection is where it will land
+    pub status_message: Option<String>,
+    pub viewport_size: (u16, u16), // (rows, cols) visible in grid
+    pub grid_layout: GridLayout, // where the grid was last drawn, for mouse lookups
+    pub sheet_select_index: usize, // cursor position in sheet select mode
+    pub sheet_filter: String, // type-ahead text in sheet select mode
+    pub show_hidden_sheets: bool, // list hidden sheets in the selector and when cycling
+    pub color_pick_bg: bool, // color picker applies a background (true) or text color (false)
+    pub tab_color_sheet: Option<usize>, // sheet whose tab color the color picker sets, opened from the sheet selector
+    pub split: Option<SplitView>, // second pane showing another part of the sheet
+    pub focused_pane: usize, // 0 = left/top, 1 = right/bottom
+    pub pending_save_path: Option<PathBuf>, // save-as target awaiting overwrite confirmation
+    pub replace: ReplaceState,
+    pub help_scroll: u16, // first visible line of the help overlay
+    pub workbooks: VecDeque<Workbook>, // other open workbooks, next to activate first
+    pub workbook_index: usize, // position of the active workbook among all open ones
+    pub backup: bool, // copy the existing file to <path>.bak before overwriting it
+    pub autosave_interval: Option<Duration>, // save dirty workbooks this often
+    pub last_autosave: Instant,
+    pub wrap_navigation: bool, // left/right moves wrap to the previous/next row at the used range's edge
+    pub constrain_to_data: bool, // keep cursor moves within one row/column past the used range
+    pub enter_moves: EntryMove, // cursor step after committing an edit with Enter
+    pub tab_moves: EntryMove, // cursor step after committing an edit with Tab
+    pub return_to_entry_column: bool, // Enter after a run of Tabs goes back to where the run started
+    pub terminal_cursor: bool, // show the terminal's own cursor on the active cell
+    pub edit_popup: bool, // edit cells in a centered, word-wrapping popup instead of the status bar
+    pub edit_in_bar: bool, // edit cells in the formula/value bar above the grid instead of the status bar
+    pub right_to_left: bool, // lay the grid out right to left, column A on the right, for RTL data
+    pub r1c1: bool, // number the columns and show cell references as R1C1 instead of A1
+    pub protect_locked: bool, // refuse edits, pastes, fills and clears on locked cells
+    entry_anchor: Option<((u32, u32), (u32, u32))>, // (start of the Tab run, where the cursor should be now)
+    pub lazy: bool, // parse sheets only when they are first shown
+    pub pending_save: Option<PendingSave>,
+    pub zebra: bool, // shade even rows to make dense data easier to follow
+    pub column_footer: bool, // show sum/count of the cursor column below the grid
+    pub header_row: bool, // row 1 is a table header: styled distinctly (frozen by --header)
+    pub status_timeout: Option<Duration>, // keep status messages this long instead of clearing on the next key
+    pub status_set_at: Instant,
+    pub message_log: VecDeque<String>, // most recent status messages, oldest first
+}
+
+impl<'a> App<'a> {
+    pub fn new(path: PathBuf, lazy: bool) -> Result<Self> {
+        let workbook = Workbook::open(path, lazy)?;
+        let status_message = workbook.import_note();
+
+        Ok(Self {
+            workbook,
+            mode: Mode::View,
             textarea: TextArea::default(),
             should_quit: false,
-            column_widths: HashMap::new(),
+            needs_redraw: true,
+            read_only: false,
+            show_formulas: false,
+            peek_formula: false,
+            default_column_width: DEFAULT_COLUMN_WIDTH,
+            date_display: DateDisplay::default(),
+            column_width_step: COLUMN_WIDTH_STEP,
+            max_column_width: MAX_COLUMN_WIDTH,
+            row_display_height: 1,
             clipboard: Clipboard::default(),
-            status_message: None,
+            last_action: None,
+            cut_source: None,
+            moving: None,
+            status_message,
             viewport_size: (20, 10), // Default, will be updated by UI
-            cell_marks,
+            grid_layout: GridLayout::default(), // Will be updated by UI
             sheet_select_index: 0,
+            sheet_filter: String::new(),
+            show_hidden_sheets: false,
+            color_pick_bg: true,
+            tab_color_sheet: None,
+            split: None,
+            focused_pane: 0,
+            pending_save_path: None,
+            replace: ReplaceState::default(),
+            help_scroll: 0,
+            workbooks: VecDeque::new(),
+            workbook_index: 0,
+            backup: false,
+            autosave_interval: None,
+            last_autosave: Instant::now(),
+            wrap_navigation: false,
+            constrain_to_data: false,
+            enter_moves: EntryMove::Down,
+            tab_moves: EntryMove::Right,
+            return_to_entry_column: false,
+            terminal_cursor: false,
+            edit_popup: false,
+            edit_in_bar: false,
+            right_to_left: false,
+            r1c1: false,
+            protect_locked: false,
+            entry_anchor: None,
+            lazy,
+            pending_save: None,
+            zebra: false,
+            column_footer: false,
+            header_row: false,
+            status_timeout: None,
+            status_set_at: Instant::now(),
+            message_log: VecDeque::new(),
         })
     }
 
+    /// Fill the first sheet from CSV/TSV text (e.g. piped stdin), starting at A1
+    pub fn load_delimited_text(&mut self, text: &str, infer_types: bool) {
+        let delimiter = detect_delimiter(text);
+        let (rows, truncated) = parse_delimited(text, delimiter, MAX_ROWS as usize, MAX_COLUMNS as usize);
+        let column_types = if infer_types { infer_column_types(&rows) } else { Vec::new() };
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&0) else {
+            return;
+        };
+
+        let mut max_cols = 0;
+        for (r, fields) in rows.iter().enumerate() {
+            max_cols = max_cols.max(fields.len());
+            for (c, value) in fields.iter().enumerate() {
+                if value.is_empty() {
+                    continue;
+                }
+                let cell = sheet.get_cell_mut((c as u32 + 1, r as u32 + 1));
+                // Values that don't fit their column's type (such as a header) stay text
+                match column_types.get(c).copied().unwrap_or(ColumnType::Text) {
+                    ColumnType::Number if let Some(number) = parse_delimited_number(value) => {
+                        cell.set_value_number(number);
+                    }
+                    ColumnType::Date if let Some((serial, code)) = import::parse_iso_datetime(value.trim()) => {
+                        cell.set_value_number(serial);
+                        cell.get_style_mut().get_number_format_mut().set_format_code(code);
+                    }
+                    _ => {
+                        cell.set_value_string(value);
+                    }
+                }
+            }
+        }
+
+        self.workbook.dirty = true;
+        let kind = if delimiter == '\t' { "TSV" } else { "CSV" };
+        let count = |wanted: ColumnType| column_types.iter().filter(|&&t| t == wanted).count();
+        let typed = match (count(ColumnType::Number), count(ColumnType::Date)) {
+            (0, 0) => String::new(),
+            (numbers, dates) => format!("; {} number and {} date column(s)", numbers, dates),
+        };
+        let note = if truncated { " (input truncated)" } else { "" };
+        self.status_message = Some(format!("Loaded {} rows x {} columns of {} from stdin{}{}", rows.len(), max_cols, kind, typed, note));
+    }
+
+    /// Open another workbook behind the active one
+    pub fn open_workbook(&mut self, path: PathBuf) -> Result<()> {
+        let workbook = Workbook::open(path, self.lazy)?;
+        if let Some(note) = workbook.import_note() {
+            self.status_message = Some(note);
+        }
+        self.workbooks.push_back(workbook);
+        Ok(())
+    }
+
+    /// Warn once about content in the open workbooks that may be lost on save.
+    /// Sheets left unread by `--lazy` are parsed first, since their charts and pivot tables
+    /// only show up once they are.
+    pub fn warn_lossy_features(&mut self) {
+        for index in 0..self.workbook.spreadsheet.get_sheet_count() {
+            self.ensure_sheet_loaded(index);
+        }
+        for workbook in self.workbooks.iter_mut() {
+            for index in 0..workbook.spreadsheet.get_sheet_count() {
+                load_lazy_sheet(&mut workbook.spreadsheet, index, &mut workbook.cell_marks, &mut workbook.column_widths);
+            }
+        }
+        let books = std::iter::once((&self.workbook.path, &self.workbook.spreadsheet))
+            .chain(self.workbooks.iter().map(|w| (&w.path, &w.spreadsheet)));
+        let warnings: Vec<String> = books
+            .filter_map(|(path, spreadsheet)| {
+                let features = detect_lossy_features(spreadsheet);
+                if features.is_empty() {
+                    return None;
+                }
+                let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                Some(format!("{} contains {}", name, features.join(", ")))
+            })
+            .collect();
+        if warnings.is_empty() {
+            return;
+        }
+        self.status_message = Some(format!("Warning: {} which may not be preserved on save", warnings.join("; ")));
+        self.log_status();
+    }
+
+    /// Start on the named sheet and/or at an A1-style cell (from --sheet / --cell).
+    /// Anything that can't be found leaves the default in place with a warning.
+    pub fn open_at(&mut self, sheet: Option<&str>, cell: Option<&str>) {
+        let mut problems = Vec::new();
+        if let Some(name) = sheet {
+            match self.find_sheet_index(name) {
+                Some(index) => self.switch_to_sheet(index),
+                None => problems.push(format!("no sheet named {}", name)),
+            }
+        }
+        if let Some(reference) = cell {
+            match self.parse_user_ref(reference) {
+                Some((row, col)) => {
+                    self.workbook.cursor = (row, col);
+                    self.workbook.selection = Selection::single(row, col);
+                    self.adjust_scroll();
+                }
+                None => problems.push(format!("invalid cell reference {}", reference)),
+            }
+        }
+        if !problems.is_empty() {
+            self.status_message = Some(format!("Warning: {}", problems.join(", ")));
+            self.log_status();
+        }
+    }
+
+    /// Treat row 1 of every open workbook as a frozen table header
+    pub fn enable_header_row(&mut self) {
+        self.header_row = true;
+        self.workbook.frozen_rows = 1;
+        for workbook in &mut self.workbooks {
+            workbook.frozen_rows = 1;
+        }
+    }
+
+    pub fn workbook_count(&self) -> usize {
+        self.workbooks.len() + 1
+    }
+
+    /// Park the active workbook at the back of the queue and activate the next one
+    fn next_workbook(&mut self) {
+        let Some(mut next) = self.workbooks.pop_front() else {
+            self.status_message = Some("Only one workbook is open".to_string());
+            return;
+        };
+        let cut_pending = self.cut_source.is_some();
+        self.swap_workbook(&mut next);
+        self.workbooks.push_back(next);
+        self.workbook_index = (self.workbook_index + 1) % self.workbook_count();
+        let note = if cut_pending { " (pending cut cancelled; pasting copies)" } else { "" };
+        self.status_message = Some(format!("Switched to {:?}{}", self.workbook.path, note));
+    }
+
+    /// Exchange the active workbook with `other`. A pending cut names a range of the
+    /// workbook it was made in, so it is dropped rather than cleared from the wrong workbook.
+    fn swap_workbook(&mut self, other: &mut Workbook) {
+        self.cut_source = None;
+        std::mem::swap(&mut self.workbook, other);
+        self.reset_split_view();
+    }
+
+    /// Whether another program wrote the file since it was read or last saved here
+    pub fn changed_on_disk(&self) -> bool {
+        modified_time(&self.workbook.path).is_some_and(|time| Some(time) != self.workbook.disk_mtime)
+    }
+
+    /// Throw away the active workbook's changes and read its file again (for an import not yet
+    /// saved, the original ODS/XLS/XLSB), starting over at A1 of the same sheet. A file that has
+    /// gone missing leaves everything as it was.
+    fn reload_from_disk(&mut self) -> Result<()> {
+        // Never read a file a background save is still writing
+        self.wait_for_pending_save();
+        let source = self.workbook.imported_from.clone().unwrap_or_else(|| self.workbook.path.clone());
+        if !source.exists() {
+            anyhow::bail!("{:?} no longer exists on disk; nothing to reload", source);
+        }
+        let mut workbook = Workbook::open(source, self.lazy)?;
+        let last_sheet = workbook.spreadsheet.get_sheet_count().saturating_sub(1);
+        workbook.current_sheet_index = self.workbook.current_sheet_index.min(last_sheet);
+        workbook.frozen_rows = self.workbook.frozen_rows;
+        workbook.frozen_cols = self.workbook.frozen_cols;
+        self.swap_workbook(&mut workbook);
+        self.ensure_sheet_loaded(self.workbook.current_sheet_index);
+        Ok(())
+    }
+
+    /// Open a split in `direction` (both panes start at the current view), change its direction,
+    /// or close it when it already splits that way
+    fn toggle_split(&mut self, direction: SplitDirection) {
+        match self.split {
+            Some(split) if split.direction == direction => {
+                self.split = None;
+                self.focused_pane = 0;
+                self.status_message = Some("Split closed".to_string());
+            }
+            Some(ref mut split) => split.direction = direction,
+            None => {
+                self.split = Some(SplitView { direction, other: SheetViewState::default() });
+                self.reset_split_view();
+                self.focused_pane = 0;
+                self.status_message = Some("Split view (Ctrl+O switches pane)".to_string());
+            }
+        }
+    }
+
+    /// Point the unfocused pane at the current view, as its old position belongs to
+    /// another sheet or workbook once the one on screen changes
+    fn reset_split_view(&mut self) {
+        if let Some(split) = self.split.as_mut() {
+            split.other = SheetViewState { cursor: self.workbook.cursor, selection: self.workbook.selection, scroll: self.workbook.scroll };
+        }
+    }
+
+    /// Move focus to the other pane of a split
+    fn switch_pane(&mut self) {
+        if self.split.is_none() {
+            self.status_message = Some("No split view (| or _ to split)".to_string());
+            return;
+        }
+        self.swap_split_view();
+        self.focused_pane = 1 - self.focused_pane;
+    }
+
+    /// Exchange the live cursor/selection/scroll with the unfocused pane's.
+    /// The grid draws the unfocused pane between two calls of this.
+    pub fn swap_split_view(&mut self) {
+        let Some(split) = self.split.as_mut() else {
+            return;
+        };
+        std::mem::swap(&mut self.workbook.cursor, &mut split.other.cursor);
+        std::mem::swap(&mut self.workbook.selection, &mut split.other.selection);
+        std::mem::swap(&mut self.workbook.scroll, &mut split.other.scroll);
+    }
+
+    /// Whether the active workbook or any parked one has unsaved changes
+    fn any_dirty(&self) -> bool {
+        self.workbook.dirty || self.workbooks.iter().any(|w| w.dirty)
+    }
+
+    /// Save the active workbook and every parked workbook with unsaved changes
+    fn save_all(&mut self) -> Result<()> {
+        if self.read_only {
+            anyhow::bail!("Read-only mode");
+        }
+        // A background save may still be writing one of these files
+        self.wait_for_pending_save();
+        if self.workbook.dirty {
+            self.save_file()?;
+        }
+        for workbook in self.workbooks.iter_mut().filter(|w| w.dirty) {
+            if modified_time(&workbook.path).is_some_and(|time| Some(time) != workbook.disk_mtime) {
+                anyhow::bail!("{:?} changed on disk since it was read (F7 to switch to it)", workbook.path);
+            }
+            write_spreadsheet(&workbook.spreadsheet, &workbook.path, self.backup)?;
+            workbook.dirty = false;
+            workbook.disk_mtime = modified_time(&workbook.path);
+            workbook.imported_from = None;
+        }
+        Ok(())
+    }
+
     fn load_cell_marks_from_spreadsheet(spreadsheet: &Spreadsheet) -> HashMap<(usize, u32, u32), CellMark> {
         let mut marks = HashMap::new();
 
         for (sheet_idx, sheet) in spreadsheet.get_sheet_collection().iter().enumerate() {
-            for cell in sheet.get_cell_collection() {
-                let coord = cell.get_coordinate();
-                let col = *coord.get_col_num();
-                let row_num = *coord.get_row_num();
-
-                let style = cell.get_style();
-
-                // Check background color
-                if let Some(fill) = style.get_fill() {
-                    if let Some(pattern_fill) = fill.get_pattern_fill() {
-                        if let Some(fg_color) = pattern_fill.get_foreground_color() {
-                            let argb = fg_color.get_argb();
-                            if !argb.is_empty() {
-                                let mark = Self::argb_to_bg_mark(argb);
-                                if mark != CellMark::None {
-                                    marks.insert((sheet_idx, row_num, col), mark);
-                                    continue;
-                                }
-                            }
-                        }
-                    }
+            Self::load_cell_marks_from_sheet(sheet_idx, sheet, &mut marks);
+        }
+
+        marks
+    }
+
+    fn load_cell_marks_from_sheet(sheet_idx: usize, sheet: &Worksheet, marks: &mut HashMap<(usize, u32, u32), CellMark>) {
+        for cell in sheet.get_cell_collection() {
+            let coord = cell.get_coordinate();
+            let col = *coord.get_col_num();
+            let row_num = *coord.get_row_num();
+
+            let style = cell.get_style();
+
+            // Check background color
+            if let Some(fill) = style.get_fill()
+                && let Some(pattern_fill) = fill.get_pattern_fill()
+                && let Some(fg_color) = pattern_fill.get_foreground_color()
+                && let Some(argb) = color_to_argb(fg_color)
+            {
+                let mark = Self::argb_to_bg_mark(argb);
+                if mark != CellMark::None {
+                    marks.insert((sheet_idx, row_num, col), mark);
+                    continue;
                 }
+            }
 
-                // Check font color
-                if let Some(font) = style.get_font() {
-                    let argb = font.get_color().get_argb();
-                    if !argb.is_empty() {
-                        let mark = Self::argb_to_font_mark(argb);
-                        if mark != CellMark::None {
-                            marks.insert((sheet_idx, row_num, col), mark);
-                        }
-                    }
+            // Check font color
+            if let Some(font) = style.get_font()
+                && let Some(argb) = color_to_argb(font.get_color())
+            {
+                let mark = Self::argb_to_font_mark(argb);
+                if mark != CellMark::None {
+                    marks.insert((sheet_idx, row_num, col), mark);
                 }
             }
         }
+    }
 
-        marks
+    /// Read column widths stored in the file, converting Excel character widths to terminal columns
+    fn load_column_widths_from_sheet(sheet_idx: usize, sheet: &Worksheet, widths: &mut HashMap<(usize, u32), u16>) {
+        for column in sheet.get_column_dimensions() {
+            let width = *column.get_width();
+            if width > 0.0 {
+                let width = ((width - EXCEL_WIDTH_PADDING).round().max(0.0) as u16).clamp(MIN_COLUMN_WIDTH, COLUMN_WIDTH_LIMIT);
+                widths.insert((sheet_idx, *column.get_col_num()), width);
+            }
+        }
     }
 
-    fn argb_to_bg_mark(argb: &str) -> CellMark {
-        let argb_upper = argb.to_uppercase();
-        match argb_upper.as_str() {
-            // Yellow variations (including our adjusted FFFFEF00)
-            "FFFFFF00" | "FFFF00" | "FFFFEF00" => CellMark::YellowBg,
-            // Blue variations (including our adjusted FF0000FE)
-            "FF0000FF" | "0000FF" | "FF0000FE" | "FF00BFFF" | "00BFFF" => CellMark::BlueBg,
-            _ => CellMark::None,
+    // Files saved by earlier versions hold marks nudged off the indexed palette
+    // (FFFFEF00, FF0000FE, ...), so those are still recognized
+    fn argb_to_bg_mark(argb: u32) -> CellMark {
+        match argb {
+            MARK_YELLOW | 0xFFFFEF00 => CellMark::YellowBg,
+            MARK_BLUE | 0xFF0000FE | 0xFF00BFFF => CellMark::BlueBg,
+            // A white fill is a real fill (it hides the gridlines in Excel), so it stays a mark like any other color
+            WHITE => CellMark::Custom { argb: WHITE, is_bg: true },
+            _ => CellMark::Custom { argb, is_bg: true },
         }
     }
 
-    fn argb_to_font_mark(argb: &str) -> CellMark {
-        let argb_upper = argb.to_uppercase();
-        match argb_upper.as_str() {
-            // Red variations (including our adjusted FFFF0001)
-            "FFFF0000" | "FF0000" | "FFFF0001" => CellMark::RedText,
-            // Green variations (including our adjusted FF008001)
-            "FF008000" | "008000" | "FF008001" | "FF00FF00" | "00FF00" => CellMark::GreenText,
-            // Magenta variations (including our adjusted FFFF00FE)
-            "FFFF00FF" | "FF00FF" | "FFFF00FE" => CellMark::MagentaText,
-            _ => CellMark::None,
+    fn argb_to_font_mark(argb: u32) -> CellMark {
+        match argb {
+            MARK_RED | 0xFFFF0001 => CellMark::RedText,
+            MARK_GREEN | 0xFF008001 | 0xFF00FF00 => CellMark::GreenText,
+            MARK_MAGENTA | 0xFFFF00FE => CellMark::MagentaText,
+            // Black (including the FF000001 once written when clearing marks) is the default text color
+            BLACK | 0xFF000001 => CellMark::None,
+            _ => CellMark::Custom { argb, is_bg: false },
+        }
+    }
+
+    /// Called on every run-loop tick; saves all dirty workbooks once the auto-save interval has passed
+    pub fn on_tick(&mut self) {
+        let before = self.status_message.clone();
+        self.run_background_work();
+        if self.status_message.is_some() && self.status_message != before {
+            self.log_status();
+        } else if let Some(timeout) = self.status_timeout
+            && self.status_set_at.elapsed() >= timeout
+        {
+            self.status_message = None;
+        }
+        // The save spinner animates while a save runs
+        if self.pending_save.is_some() || self.status_message != before {
+            self.needs_redraw = true;
         }
     }
 
-    #[allow(dead_code)]
-    pub fn on_tick(&mut self) {}
+    /// Finish background saves and run auto-save when it is due
+    fn run_background_work(&mut self) {
+        if self.pending_save.as_ref().is_some_and(|save| save.handle.is_finished()) {
+            self.wait_for_pending_save();
+        }
+
+        let Some(interval) = self.autosave_interval else {
+            return;
+        };
+        if self.read_only || self.last_autosave.elapsed() < interval {
+            return;
+        }
+        self.last_autosave = Instant::now();
+        if !self.any_dirty() {
+            return;
+        }
+        match self.save_all() {
+            Ok(_) => self.status_message = Some(format!("Auto-saved: {:?}", self.workbook.path)),
+            Err(e) => self.status_message = Some(format!("Auto-save failed: {}", e)),
+        }
+    }
 
     pub fn on_key(&mut self, key: KeyEvent) {
         if key.kind != KeyEventKind::Press {
             return;
         }
+        self.needs_redraw = true;
+
+        // Any message set while handling the key is new; otherwise the old one is
+        // cleared, or kept until its timeout when one is configured
+        let before = self.status_message.take();
+        self.handle_key(key);
+        if self.status_message.is_some() {
+            self.log_status();
+        } else if self.status_timeout.is_some() {
+            self.status_message = before;
+        }
+    }
 
-        // Clear status message on any key press
-        self.status_message = None;
+    /// Remember the current status message in the message log and restart its timeout
+    fn log_status(&mut self) {
+        let Some(message) = &self.status_message else {
+            return;
+        };
+        if self.message_log.len() >= MESSAGE_LOG_SIZE {
+            self.message_log.pop_front();
+        }
+        self.message_log.push_back(message.clone());
+        self.status_set_at = Instant::now();
+    }
 
+    fn handle_key(&mut self, key: KeyEvent) {
+        // With the grid mirrored, left and right keys follow the screen rather than the column order
+        let key = if self.right_to_left && matches!(self.mode, Mode::View | Mode::Move) {
+            mirror_horizontal_key(key)
+        } else {
+            key
+        };
         match self.mode {
             Mode::View => {
                 let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
                 let shift = key.modifiers.contains(KeyModifiers::SHIFT);
 
                 match key.code {
-                    KeyCode::Char('w') if ctrl => self.should_quit = true,
-                    KeyCode::Char('s') if ctrl => {
-                        match self.save_file() {
-                            Ok(_) => self.status_message = Some(format!("Saved: {:?}", self.path)),
-                            Err(e) => self.status_message = Some(format!("Error: {}", e)),
-                        }
-                    }
+                    KeyCode::Char('w') if ctrl => self.request_quit(),
+                    KeyCode::Char('s') | KeyCode::Char('S') if ctrl && shift => self.enter_save_as_mode(),
+                    KeyCode::Char('s') if ctrl => self.save_and_report(),
+                    KeyCode::Delete => self.clear_selection_contents(),
+                    // Fill: Ctrl+D down, Ctrl+R right (with Alt, always copy instead of continuing a series)
+                    KeyCode::Char('z') if ctrl => self.undo(),
+                    KeyCode::Char('d') if ctrl => self.fill_down(key.modifiers.contains(KeyModifiers::ALT)),
+                    KeyCode::Char('r') if ctrl => self.fill_right(key.modifiers.contains(KeyModifiers::ALT)),
                     // Copy: C or F5
                     KeyCode::Char('c') if !ctrl => self.copy_selection(),
+                    KeyCode::Char('x') => self.cut_selection(),
+                    KeyCode::Char('m') if !ctrl => self.start_move(),
+                    KeyCode::Char('y') if !ctrl => self.copy_reference(),
+                    KeyCode::Char('l') if !ctrl => self.toggle_lock_selection(),
+                    KeyCode::Char('L') if !ctrl => self.toggle_protection(),
+                    // ( hides the selected rows, ) the selected columns; with Alt they are shown again
+                    KeyCode::Char('(') if key.modifiers.contains(KeyModifiers::ALT) => self.set_lines_hidden(false, false),
+                    KeyCode::Char(')') if key.modifiers.contains(KeyModifiers::ALT) => self.set_lines_hidden(true, false),
+                    KeyCode::Char('(') => self.set_lines_hidden(false, true),
+                    KeyCode::Char(')') => self.set_lines_hidden(true, true),
                     KeyCode::F(5) => self.copy_selection(),
                     // Paste: V or F6
                     KeyCode::Char('v') if !ctrl => self.paste_clipboard(),
+                    // Ctrl+V: Paste values only (no formats, marks or formulas)
+                    KeyCode::Char('v') if ctrl => self.paste_values(),
+                    // Shift+V: Paste transposed (rows become columns)
+                    KeyCode::Char('V') if !ctrl => self.paste_transposed(),
                     KeyCode::F(6) => self.paste_clipboard(),
                     // Column width: E to expand, R to shrink
                     KeyCode::Char('e') if !ctrl && !shift => self.widen_column(),
                     KeyCode::Char('r') if !ctrl && !shift => self.shrink_column(),
+                    // Auto-fit: Shift+E for the cursor column, Ctrl+E for all visible columns
+                    KeyCode::Char('E') if !ctrl => self.autofit_column(self.workbook.cursor.1),
+                    KeyCode::Char('e') if ctrl => self.autofit_all_columns(),
+                    KeyCode::Char('f') if !ctrl => self.toggle_freeze_panes(),
+                    KeyCode::Char('h') if !ctrl => self.cycle_row_display_height(),
+                    // Ctrl+` (plain ` for terminals that don't report Ctrl with it)
+                    KeyCode::Char('`') => self.toggle_show_formulas(),
+                    KeyCode::Char('~') => self.toggle_peek_formula(),
+                    KeyCode::Char('#') => self.toggle_r1c1(),
+                    KeyCode::Char('l') if ctrl => self.toggle_zebra(),
+                    KeyCode::Char('t') if ctrl => self.toggle_column_footer(),
+                    KeyCode::F(2) if shift => self.enter_comment_mode(),
                     KeyCode::F(2) => self.enter_edit_mode(),
-                    // WASD movement (FPS style) + Shift for selection
+                    // WASD movement (FPS style) + Shift for selection; terminals report Shift+w as 'W'
+                    KeyCode::Char('W') if !ctrl => self.move_cursor(0, -1, true),
+                    KeyCode::Char('S') if !ctrl => self.move_cursor(0, 1, true),
+                    KeyCode::Char('A') if !ctrl => self.move_cursor(-1, 0, true),
+                    KeyCode::Char('D') if !ctrl => self.move_cursor(1, 0, true),
                     KeyCode::Char('w') if !ctrl && shift => self.move_cursor(0, -1, true),
                     KeyCode::Char('s') if !ctrl && shift => self.move_cursor(0, 1, true),
                     KeyCode::Char('a') if !ctrl && shift => self.move_cursor(-1, 0, true),
@@ -234,8 +1065,14 @@ impl<'a> App<'a> {
                     KeyCode::Char('s') if !ctrl => self.move_cursor(0, 1, false),
                     KeyCode::Char('d') if !ctrl => self.move_cursor(1, 0, false),
                     // Arrow keys + Shift for selection
+                    KeyCode::Enter if ctrl => self.open_hyperlink(),
                     KeyCode::Enter if shift => self.move_cursor(0, -1, false),
                     KeyCode::Enter => self.move_cursor(0, 1, false),
+                    // Ctrl+Arrow jumps to the edge of the data block; with Shift it extends the selection
+                    KeyCode::Left if ctrl => self.jump_in_direction(-1, 0, shift),
+                    KeyCode::Right if ctrl => self.jump_in_direction(1, 0, shift),
+                    KeyCode::Up if ctrl => self.jump_in_direction(0, -1, shift),
+                    KeyCode::Down if ctrl => self.jump_in_direction(0, 1, shift),
                     KeyCode::Left if shift => self.move_cursor(-1, 0, true),
                     KeyCode::Right if shift => self.move_cursor(1, 0, true),
                     KeyCode::Up if shift => self.move_cursor(0, -1, true),
@@ -247,13 +1084,20 @@ impl<'a> App<'a> {
                     KeyCode::Tab if shift => self.move_cursor(-1, 0, false),
                     KeyCode::Tab => self.move_cursor(1, 0, false),
                     KeyCode::BackTab => self.move_cursor(-1, 0, false),
-                    KeyCode::PageUp => self.prev_sheet(),
-                    KeyCode::PageDown => self.next_sheet(),
-                    KeyCode::Home if ctrl => self.jump_to_start(),
-                    KeyCode::End if ctrl => self.jump_to_end(),
-                    KeyCode::Home => self.jump_to_row_start(),
-                    KeyCode::End => self.jump_to_row_end(),
+                    // PageUp/PageDown page through rows; with Ctrl they switch sheets
+                    KeyCode::PageUp if ctrl => self.prev_sheet(),
+                    KeyCode::PageDown if ctrl => self.next_sheet(),
+                    KeyCode::PageUp => self.page_rows(-1, shift),
+                    KeyCode::PageDown => self.page_rows(1, shift),
+                    // Home/End go to the row edges, with Ctrl to the sheet corners; Shift extends the selection
+                    KeyCode::Home if ctrl => self.jump_to_start(shift),
+                    KeyCode::End if ctrl => self.jump_to_end(shift),
+                    KeyCode::Home => self.jump_to_row_start(shift),
+                    KeyCode::End => self.jump_to_row_end(shift),
                     KeyCode::Esc => self.clear_selection(),
+                    // Whole-line selection: Shift+Space for the row, Ctrl+Space for the column
+                    KeyCode::Char(' ') if shift => self.select_row(),
+                    KeyCode::Char(' ') if ctrl => self.select_column(),
                     // Cell marking with number keys (1=clear, 2-6=colors)
                     KeyCode::Char('1') => self.set_mark_for_selection(CellMark::None),
                     KeyCode::Char('2') => self.set_mark_for_selection(CellMark::YellowBg),
@@ -261,432 +1105,3783 @@ impl<'a> App<'a> {
                     KeyCode::Char('4') => self.set_mark_for_selection(CellMark::GreenText),
                     KeyCode::Char('5') => self.set_mark_for_selection(CellMark::BlueBg),
                     KeyCode::Char('6') => self.set_mark_for_selection(CellMark::MagentaText),
+                    KeyCode::Char('p') if !ctrl => self.enter_color_pick_mode(),
+                    // Font emphasis: B bold, I italic, U underline
+                    KeyCode::Char('b') if !ctrl => self.toggle_emphasis(Emphasis::Bold),
+                    KeyCode::Char('i') if !ctrl => self.toggle_emphasis(Emphasis::Italic),
+                    KeyCode::Char('u') if !ctrl => self.toggle_emphasis(Emphasis::Underline),
+                    KeyCode::Char(':') => self.enter_command_mode(),
+                    // ; stamps today's date, Alt+; the current date and time
+                    KeyCode::Char(';') if key.modifiers.contains(KeyModifiers::ALT) => self.insert_now(true),
+                    KeyCode::Char(';') => self.insert_now(false),
+                    KeyCode::Char('f') if ctrl => self.enter_replace_mode(),
+                    // N: Choose a number format for the selection
+                    KeyCode::Char('n') if !ctrl => self.enter_format_mode(),
+                    KeyCode::Char('.') => self.repeat_last_action(),
+                    KeyCode::Char('>') => self.adjust_decimals(1),
+                    KeyCode::Char('<') => self.adjust_decimals(-1),
+                    // F1 or ?: Show keybinding help
+                    KeyCode::F(1) | KeyCode::Char('?') => {
+                        self.help_scroll = 0;
+                        self.mode = Mode::Help;
+                    }
                     // F4: Enter sheet selection mode
                     KeyCode::F(4) => self.enter_sheet_select_mode(),
+                    KeyCode::F(7) => self.next_workbook(),
+                    // Split view: | side by side, _ stacked, Ctrl+O switches pane
+                    KeyCode::Char('|') => self.toggle_split(SplitDirection::Vertical),
+                    KeyCode::Char('_') => self.toggle_split(SplitDirection::Horizontal),
+                    KeyCode::Char('o') if ctrl => self.switch_pane(),
+                    KeyCode::F(9) => self.mode = Mode::MessageLog,
                     _ => {}
                 }
             }
             Mode::SheetSelect => {
                 match key.code {
+                    // Esc clears the type-ahead filter first, then closes
+                    KeyCode::Esc if !self.sheet_filter.is_empty() => self.sheet_filter.clear(),
                     KeyCode::Esc => self.mode = Mode::View,
                     KeyCode::Enter => self.confirm_sheet_selection(),
-                    // Navigation: W/S or Up/Down
-                    KeyCode::Char('w') | KeyCode::Up => self.sheet_select_move(-1),
-                    KeyCode::Char('s') | KeyCode::Down => self.sheet_select_move(1),
+                    KeyCode::Up => self.sheet_select_move(-1),
+                    KeyCode::Down => self.sheet_select_move(1),
+                    KeyCode::PageUp => self.sheet_select_jump(-(SHEET_LIST_ROWS as i32)),
+                    KeyCode::PageDown => self.sheet_select_jump(SHEET_LIST_ROWS as i32),
+                    KeyCode::Home => self.sheet_select_jump(i32::MIN),
+                    KeyCode::End => self.sheet_select_jump(i32::MAX),
+                    KeyCode::Tab => self.toggle_show_hidden_sheets(),
+                    KeyCode::Char('v') if key.modifiers.contains(KeyModifiers::CONTROL) => self.toggle_sheet_hidden(),
+                    KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => self.enter_tab_color_mode(),
+                    KeyCode::Backspace => {
+                        self.sheet_filter.pop();
+                        self.jump_to_filtered_sheet();
+                    }
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.sheet_filter.push(c);
+                        self.jump_to_filtered_sheet();
+                    }
                     _ => {}
                 }
             }
-            Mode::Edit => match key.code {
-                KeyCode::Esc => self.mode = Mode::View,
-                KeyCode::Enter => {
-                    self.save_cell_value();
-                    self.mode = Mode::View;
-                    self.move_cursor(0, 1, false);
-                }
-                KeyCode::Tab => {
-                    self.save_cell_value();
-                    self.mode = Mode::View;
-                    self.move_cursor(1, 0, false);
-                }
-                _ => {
+            Mode::ConfirmQuit => {
+                match key.code {
+                    KeyCode::Char('y') => self.should_quit = true,
+                    // Read-only workbooks can't be saved, so only y and Esc are offered
+                    KeyCode::Char('s') if !self.read_only => {
+                        match self.save_all() {
+                            Ok(_) => self.should_quit = true,
+                            Err(e) => {
+                                self.status_message = Some(format!("Error: {}", e));
+                                self.mode = Mode::View;
+                            }
+                        }
+                    }
+                    KeyCode::Esc => self.mode = Mode::View,
+                    _ => {}
+                }
+            }
+            Mode::Format => match key.code {
+                KeyCode::Esc => self.mode = Mode::View,
+                KeyCode::Char(c) => {
+                    let choice = c.to_digit(10).map(|d| d as usize);
+                    if let Some(&(label, code)) = choice
+                        .filter(|&d| d >= 1)
+                        .and_then(|d| NUMBER_FORMATS.get(d - 1))
+                    {
+                        self.apply_number_format(label, code);
+                        self.mode = Mode::View;
+                    }
+                }
+                _ => {}
+            },
+            Mode::ColorPick if self.tab_color_sheet.is_some() => match key.code {
+                KeyCode::Esc => {
+                    self.tab_color_sheet = None;
+                    self.mode = Mode::SheetSelect;
+                }
+                KeyCode::Delete | KeyCode::Backspace => self.set_tab_color(None),
+                KeyCode::Char(c) => {
+                    if let Some(&(_, argb)) = c.to_digit(16).and_then(|d| COLOR_PALETTE.get(d as usize)) {
+                        self.set_tab_color(Some(argb));
+                    }
+                }
+                _ => {}
+            },
+            Mode::ColorPick => match key.code {
+                KeyCode::Esc => self.mode = Mode::View,
+                // Tab switches between background and text color
+                KeyCode::Tab => self.color_pick_bg = !self.color_pick_bg,
+                KeyCode::Char(c) => {
+                    if let Some(&(_, argb)) = c.to_digit(16).and_then(|d| COLOR_PALETTE.get(d as usize)) {
+                        self.set_mark_for_selection(CellMark::Custom { argb, is_bg: self.color_pick_bg });
+                        self.mode = Mode::View;
+                    }
+                }
+                _ => {}
+            },
+            // Any key closes the message log
+            Mode::MessageLog => self.mode = Mode::View,
+            Mode::Move => match key.code {
+                KeyCode::Esc => self.cancel_move(),
+                KeyCode::Enter => self.commit_move(),
+                KeyCode::Up | KeyCode::Char('w') => self.shift_move(0, -1),
+                KeyCode::Down | KeyCode::Char('s') => self.shift_move(0, 1),
+                KeyCode::Left | KeyCode::Char('a') => self.shift_move(-1, 0),
+                KeyCode::Right | KeyCode::Char('d') => self.shift_move(1, 0),
+                _ => {}
+            },
+            Mode::Help => match key.code {
+                // Arrow keys scroll, any other key closes
+                KeyCode::Up => self.help_scroll = self.help_scroll.saturating_sub(1),
+                KeyCode::Down => self.help_scroll = self.help_scroll.saturating_add(1),
+                KeyCode::PageUp => self.help_scroll = self.help_scroll.saturating_sub(10),
+                KeyCode::PageDown => self.help_scroll = self.help_scroll.saturating_add(10),
+                _ => self.mode = Mode::View,
+            },
+            Mode::Command => match key.code {
+                KeyCode::Esc => self.mode = Mode::View,
+                KeyCode::Enter => {
+                    let cmd = self.textarea.lines().join("");
+                    self.mode = Mode::View;
+                    self.status_message = Some(match self.execute_command(&cmd) {
+                        Ok(msg) => msg,
+                        Err(e) => e.to_string(),
+                    });
+                }
+                _ => {
+                    self.textarea.input(key);
+                }
+            },
+            Mode::Replace => {
+                let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+                let alt = key.modifiers.contains(KeyModifiers::ALT);
+                match key.code {
+                    KeyCode::Esc => {
+                        self.sync_replace_field();
+                        self.mode = Mode::View;
+                    }
+                    KeyCode::Tab => self.switch_replace_field(),
+                    KeyCode::Enter => self.replace_next(),
+                    KeyCode::Char('a') if ctrl => self.replace_all(),
+                    KeyCode::Char('c') if alt => {
+                        self.replace.options.case_sensitive = !self.replace.options.case_sensitive;
+                    }
+                    KeyCode::Char('w') if alt => {
+                        self.replace.options.whole_cell = !self.replace.options.whole_cell;
+                    }
+                    KeyCode::Char('a') if alt => {
+                        self.replace.options.ignore_accents = !self.replace.options.ignore_accents;
+                    }
+                    _ => {
+                        self.textarea.input(key);
+                    }
+                }
+            }
+            Mode::SaveAs => match key.code {
+                KeyCode::Esc => self.mode = Mode::View,
+                KeyCode::Enter => self.confirm_save_as(),
+                _ => {
+                    self.textarea.input(key);
+                }
+            },
+            Mode::ConfirmOverwrite => {
+                match key.code {
+                    KeyCode::Char('y') => {
+                        if let Some(path) = self.pending_save_path.take() {
+                            self.save_as(path);
+                        }
+                    }
+                    KeyCode::Char('n') | KeyCode::Esc => {
+                        self.pending_save_path = None;
+                        self.status_message = Some("Save cancelled".to_string());
+                        self.mode = Mode::View;
+                    }
+                    _ => {}
+                }
+            }
+            Mode::ConfirmDiskChange => match key.code {
+                KeyCode::Char('y') => {
+                    self.mode = Mode::View;
+                    self.start_background_save();
+                }
+                KeyCode::Char('r') => {
+                    self.mode = Mode::View;
+                    self.status_message = Some(match self.reload_from_disk() {
+                        Ok(_) => format!("Reloaded {:?}", self.workbook.path),
+                        Err(e) => format!("Error: {}", e),
+                    });
+                }
+                KeyCode::Char('n') | KeyCode::Esc => {
+                    self.status_message = Some("Save cancelled".to_string());
+                    self.mode = Mode::View;
+                }
+                _ => {}
+            },
+            Mode::Comment => match key.code {
+                KeyCode::Esc => self.mode = Mode::View,
+                KeyCode::Enter if key.modifiers.contains(KeyModifiers::ALT) => {
+                    self.textarea.insert_newline();
+                }
+                KeyCode::Enter => {
+                    self.save_comment();
+                    self.mode = Mode::View;
+                }
+                _ => {
                     self.textarea.input(key);
                 }
-            },
+            },
+            Mode::Edit => match key.code {
+                KeyCode::Esc => self.mode = Mode::View,
+                // Alt+Enter inserts a line break inside the cell
+                KeyCode::Enter if key.modifiers.contains(KeyModifiers::ALT) => {
+                    self.textarea.insert_newline();
+                }
+                KeyCode::Enter => self.commit_edit(false),
+                KeyCode::Tab => self.commit_edit(true),
+                _ => {
+                    self.textarea.input(key);
+                }
+            },
+        }
+    }
+
+    pub fn on_mouse(&mut self, mouse: MouseEvent) {
+        if self.mode != Mode::View {
+            return;
+        }
+        if mouse.kind != MouseEventKind::Moved {
+            self.needs_redraw = true;
+        }
+
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some((row, col)) = self.cell_at(mouse.column, mouse.row) {
+                    self.status_message = None;
+                    self.workbook.cursor = (row, col);
+                    self.workbook.selection = Selection::single(row, col);
+                    self.adjust_scroll();
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                // Extend selection from the anchor set by the initial click
+                if let Some((row, col)) = self.cell_at(mouse.column, mouse.row) {
+                    self.workbook.cursor = (row, col);
+                    self.workbook.selection.end = (row, col);
+                    self.adjust_scroll();
+                }
+            }
+            MouseEventKind::ScrollUp => self.scroll_rows(-MOUSE_SCROLL_ROWS),
+            MouseEventKind::ScrollDown => self.scroll_rows(MOUSE_SCROLL_ROWS),
+            _ => {}
+        }
+    }
+
+    /// Translate a screen position into the (row, col) of the grid cell under it
+    fn cell_at(&self, x: u16, y: u16) -> Option<(u32, u32)> {
+        grid::cell_at_screen(x, y, &self.grid_layout)
+            .filter(|&(row, col)| row <= MAX_ROWS && col <= MAX_COLUMNS)
+    }
+
+    /// Scroll the grid vertically without moving the cursor
+    fn scroll_rows(&mut self, delta: i32) {
+        let max_scroll = MAX_ROWS.saturating_sub(self.viewport_size.0 as u32) as i64;
+        self.workbook.scroll.0 = (self.workbook.scroll.0 as i64 + delta as i64).clamp(0, max_scroll) as u32;
+    }
+
+    /// Scroll a viewport of rows up (-1) or down (1), taking the cursor along
+    fn page_rows(&mut self, direction: i32, extend_selection: bool) {
+        let page = (self.viewport_size.0 as u32).saturating_sub(self.workbook.frozen_rows).max(1) as i32;
+        self.scroll_rows(direction * page);
+        self.move_cursor(0, direction * page, extend_selection);
+    }
+
+    /// Report and return true when the workbook must not be modified
+    fn reject_if_read_only(&mut self) -> bool {
+        if self.read_only {
+            self.status_message = Some("Read-only mode".to_string());
+        }
+        self.read_only
+    }
+
+    /// With protection on, refuse to change a range holding locked cells (reporting the first one)
+    fn reject_if_locked(&mut self, range: Selection) -> bool {
+        if !self.protect_locked {
+            return false;
+        }
+        let Some((row, col)) = self.locked_cells_in(range).into_iter().min() else {
+            return false;
+        };
+        self.status_message = Some(format!("{}{} is locked (L turns protection off)", number_to_column(col), row));
+        true
+    }
+
+    /// (row, col) of the locked cells inside a range, found among the cells that exist
+    fn locked_cells_in(&self, range: Selection) -> Vec<(u32, u32)> {
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) else {
+            return Vec::new();
+        };
+        sheet.get_cell_collection()
+            .into_iter()
+            .filter(|cell| cell_is_locked(cell))
+            .map(|cell| (*cell.get_coordinate().get_row_num(), *cell.get_coordinate().get_col_num()))
+            .filter(|&(row, col)| range.contains(row, col))
+            .collect()
+    }
+
+    /// Whether a cell is explicitly locked (cells without a protection setting are not)
+    pub fn is_cell_locked(&self, col: u32, row: u32) -> bool {
+        self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)
+            .and_then(|sheet| sheet.get_cell((col, row)))
+            .is_some_and(cell_is_locked)
+    }
+
+    /// Lock the selection, or unlock it when the cursor cell is already locked. The flag is
+    /// the cell's own protection setting, so it is saved with the file.
+    fn toggle_lock_selection(&mut self) {
+        if self.reject_if_read_only() {
+            return;
+        }
+        let lock = !self.is_cell_locked(self.workbook.cursor.1, self.workbook.cursor.0);
+        let (min_row, min_col, max_row, max_col) = self.workbook.selection.bounds();
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&self.workbook.current_sheet_index) else {
+            return;
+        };
+        for row in min_row..=max_row {
+            for col in min_col..=max_col {
+                // Unlocking only needs to touch cells that exist
+                if lock || sheet.get_cell((col, row)).is_some() {
+                    sheet.get_cell_mut((col, row)).get_style_mut().get_protection_mut().set_locked(lock);
+                }
+            }
+        }
+        self.workbook.dirty = true;
+        let cells = (max_row - min_row + 1) * (max_col - min_col + 1);
+        let protection = if self.protect_locked { "" } else { " (L turns protection on)" };
+        self.status_message = Some(format!("{} {} cell(s){}", if lock { "Locked" } else { "Unlocked" }, cells, protection));
+    }
+
+    fn toggle_protection(&mut self) {
+        self.protect_locked = !self.protect_locked;
+        self.status_message = Some(if self.protect_locked {
+            "Protection on: locked cells can't be changed".to_string()
+        } else {
+            "Protection off".to_string()
+        });
+    }
+
+    /// Quit immediately, or ask for confirmation when there are unsaved changes
+    fn request_quit(&mut self) {
+        if self.any_dirty() {
+            self.mode = Mode::ConfirmQuit;
+        } else {
+            self.should_quit = true;
+        }
+    }
+
+    fn move_cursor(&mut self, dx: i32, dy: i32, extend_selection: bool) {
+        let (row, col) = self.workbook.cursor;
+        let mut new_row = (row as i32 + dy).clamp(1, MAX_ROWS as i32) as u32;
+        let mut new_col = (col as i32 + dx).clamp(1, MAX_COLUMNS as i32) as u32;
+
+        // Wrap horizontal moves at the edges of the used range onto the next/previous row
+        if self.wrap_navigation && dy == 0 && dx != 0 && !extend_selection {
+            let last_col = self.last_used_col();
+            let target = col as i32 + dx;
+            if target > last_col as i32 && row < MAX_ROWS {
+                new_row = row + 1;
+                new_col = 1;
+            } else if target < 1 && row > 1 {
+                new_row = row - 1;
+                new_col = last_col;
+            }
+        }
+
+        // Stop one cell past the used range (a cursor already further out can still move back)
+        if self.constrain_to_data {
+            let max_row = (self.last_used_row() + 1).max(row);
+            let max_col = (self.last_used_col() + 1).max(col);
+            if new_row > max_row || new_col > max_col {
+                new_row = new_row.min(max_row);
+                new_col = new_col.min(max_col);
+                self.status_message = Some("Edge of data".to_string());
+            }
+        }
+
+        // Step over hidden rows and columns in the direction of travel
+        let new_row = first_visible(new_row, dy.signum(), MAX_ROWS, |r| self.is_row_hidden(r)).unwrap_or(row);
+        let new_col = first_visible(new_col, dx.signum(), MAX_COLUMNS, |c| self.is_column_hidden(c)).unwrap_or(col);
+        self.move_cursor_to((new_row, new_col), extend_selection);
+    }
+
+    /// Put the cursor on `pos`, either extending the selection from its anchor
+    /// or collapsing it to that cell, and scroll it into view
+    fn move_cursor_to(&mut self, pos: (u32, u32), extend_selection: bool) {
+        self.workbook.cursor = pos;
+        if extend_selection {
+            self.workbook.selection.end = pos;
+        } else {
+            self.workbook.selection = Selection::single(pos.0, pos.1);
+        }
+        self.adjust_scroll();
+    }
+
+    fn adjust_scroll(&mut self) {
+        let (row, col) = self.workbook.cursor;
+        let (view_rows, view_cols) = self.viewport_size;
+
+        // Frozen lines are always visible, so only the remaining area scrolls
+        // Adjust vertical scroll
+        if row > self.workbook.frozen_rows {
+            let row = row - self.workbook.frozen_rows;
+            let view_rows = (view_rows as u32).saturating_sub(self.workbook.frozen_rows).max(1);
+            if row <= self.workbook.scroll.0 {
+                self.workbook.scroll.0 = row - 1;
+            } else if row > self.workbook.scroll.0 + view_rows {
+                self.workbook.scroll.0 = row - view_rows;
+            }
+        }
+
+        // Adjust horizontal scroll
+        if col > self.workbook.frozen_cols {
+            let col = col - self.workbook.frozen_cols;
+            let view_cols = (view_cols as u32).saturating_sub(self.workbook.frozen_cols).max(1);
+            if col <= self.workbook.scroll.1 {
+                self.workbook.scroll.1 = col - 1;
+            } else if col > self.workbook.scroll.1 + view_cols {
+                self.workbook.scroll.1 = col - view_cols;
+            }
+        }
+    }
+
+    /// Row shown on the given 0-based line of the grid body (frozen rows first, hidden rows skipped)
+    pub fn row_at_line(&self, line: u32) -> u32 {
+        self.visible_rows().nth(line as usize).unwrap_or(MAX_ROWS)
+    }
+
+    /// Column shown at the given 0-based position of the grid (frozen columns first, hidden columns skipped)
+    pub fn col_at_position(&self, pos: u32) -> u32 {
+        grid::visible_indexes(self.workbook.frozen_cols, self.workbook.scroll.1, MAX_COLUMNS, |col| self.is_column_hidden(col))
+            .nth(pos as usize)
+            .unwrap_or(MAX_COLUMNS)
+    }
+
+    /// Rows from the top of the grid down, frozen rows first, without hidden rows
+    pub fn visible_rows(&self) -> impl Iterator<Item = u32> + '_ {
+        grid::visible_indexes(self.workbook.frozen_rows, self.workbook.scroll.0, MAX_ROWS, |row| self.is_row_hidden(row))
+    }
+
+    pub fn is_row_hidden(&self, row: u32) -> bool {
+        self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)
+            .and_then(|sheet| sheet.get_row_dimension(&row))
+            .is_some_and(|dimension| *dimension.get_hidden())
+    }
+
+    pub fn is_column_hidden(&self, col: u32) -> bool {
+        self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)
+            .and_then(|sheet| sheet.get_column_dimension_by_number(&col))
+            .is_some_and(|dimension| *dimension.get_hidden())
+    }
+
+    /// Hide (or show) the selected rows or columns. Showing with a single cell selected
+    /// brings back every hidden line of the sheet; the state is saved with the file.
+    fn set_lines_hidden(&mut self, columns: bool, hidden: bool) {
+        if self.reject_if_read_only() {
+            return;
+        }
+        let (min_row, min_col, max_row, max_col) = self.workbook.selection.bounds();
+        let (first, last) = match (columns, hidden, self.workbook.selection.is_single()) {
+            (true, false, true) => (1, MAX_COLUMNS),
+            (false, false, true) => (1, MAX_ROWS),
+            (true, _, _) => (min_col, max_col),
+            (false, _, _) => (min_row, max_row),
+        };
+        let changing: Vec<u32> = (first..=last)
+            .filter(|&index| if columns { self.is_column_hidden(index) } else { self.is_row_hidden(index) } != hidden)
+            .collect();
+
+        let widths: Vec<u16> = changing.iter()
+            .map(|&index| if columns { self.get_column_width(index) } else { 0 })
+            .collect();
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&self.workbook.current_sheet_index) else {
+            return;
+        };
+        for (&index, width) in changing.iter().zip(widths) {
+            if columns {
+                // A new column dimension needs a width, or the column would come back zero-wide
+                if sheet.get_column_dimension_by_number(&index).is_none() {
+                    sheet.get_column_dimension_by_number_mut(&index).set_width(width as f64 + EXCEL_WIDTH_PADDING);
+                }
+                sheet.get_column_dimension_by_number_mut(&index).set_hidden(hidden);
+            } else {
+                sheet.get_row_dimension_mut(&index).set_hidden(hidden);
+            }
+        }
+        if !changing.is_empty() {
+            self.workbook.dirty = true;
+        }
+
+        // Keep the cursor off hidden lines
+        if hidden {
+            self.workbook.selection = Selection::single(self.workbook.cursor.0, self.workbook.cursor.1);
+            self.move_cursor(0, 0, false);
+        }
+        let kind = match (columns, changing.len()) {
+            (true, 1) => "column",
+            (true, _) => "columns",
+            (false, 1) => "row",
+            (false, _) => "rows",
+        };
+        let verb = if hidden { "Hid" } else { "Showed" };
+        self.status_message = Some(format!("{} {} {}", verb, changing.len(), kind));
+    }
+
+    /// Freeze everything above and left of the cursor, or unfreeze if already frozen
+    fn toggle_freeze_panes(&mut self) {
+        if self.workbook.frozen_rows > 0 || self.workbook.frozen_cols > 0 {
+            self.workbook.frozen_rows = 0;
+            self.workbook.frozen_cols = 0;
+            self.status_message = Some("Panes unfrozen".to_string());
+        } else {
+            let (row, col) = self.workbook.cursor;
+            let (view_rows, view_cols) = self.viewport_size;
+            if row > view_rows as u32 || col > view_cols as u32 {
+                self.status_message = Some("Cannot freeze: frozen area would not fit on screen".to_string());
+                return;
+            }
+            self.workbook.frozen_rows = row - 1;
+            self.workbook.frozen_cols = col - 1;
+            self.status_message = Some(format!("Frozen {} row(s), {} column(s)", self.workbook.frozen_rows, self.workbook.frozen_cols));
+        }
+        self.workbook.scroll = (0, 0);
+        self.adjust_scroll();
+    }
+
+    /// A cell is blank when it has neither a value nor a formula (whether or not it exists)
+    pub fn is_cell_blank(&self, col: u32, row: u32) -> bool {
+        self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)
+            .and_then(|sheet| sheet.get_cell((col, row)))
+            .is_none_or(cell_is_blank)
+    }
+
+    /// Excel-style Ctrl+Arrow: run to the last filled cell of the current block,
+    /// or skip empty cells to the next filled one (stopping at the sheet edge)
+    fn jump_in_direction(&mut self, dx: i32, dy: i32, extend_selection: bool) {
+        let step = |(row, col): (u32, u32)| {
+            let row = row as i32 + dy;
+            let col = col as i32 + dx;
+            ((1..=MAX_ROWS as i32).contains(&row) && (1..=MAX_COLUMNS as i32).contains(&col))
+                .then_some((row as u32, col as u32))
+        };
+
+        let start = self.workbook.cursor;
+        let Some(mut pos) = step(start) else {
+            return;
+        };
+        if !self.is_cell_blank(start.1, start.0) && !self.is_cell_blank(pos.1, pos.0) {
+            while let Some(next) = step(pos)
+                && !self.is_cell_blank(next.1, next.0)
+            {
+                pos = next;
+            }
+        } else {
+            while self.is_cell_blank(pos.1, pos.0)
+                && let Some(next) = step(pos)
+            {
+                pos = next;
+            }
+        }
+
+        self.move_cursor_to(pos, extend_selection);
+    }
+
+    fn jump_to_start(&mut self, extend_selection: bool) {
+        self.move_cursor_to((1, 1), extend_selection);
+        self.workbook.scroll = (0, 0);
+    }
+
+    fn jump_to_end(&mut self, extend_selection: bool) {
+        // Find the last used cell
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) {
+            let row = sheet.get_highest_row().max(1);
+            let col = sheet.get_highest_column().max(1);
+            self.move_cursor_to((row, col), extend_selection);
+        }
+    }
+
+    fn jump_to_row_start(&mut self, extend_selection: bool) {
+        self.move_cursor_to((self.workbook.cursor.0, 1), extend_selection);
+    }
+
+    fn jump_to_row_end(&mut self, extend_selection: bool) {
+        // Find last used column in current row
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) {
+            let col = sheet.get_highest_column().max(1);
+            self.move_cursor_to((self.workbook.cursor.0, col), extend_selection);
+        }
+    }
+
+    fn clear_selection(&mut self) {
+        self.workbook.selection = Selection::single(self.workbook.cursor.0, self.workbook.cursor.1);
+        if self.cut_source.take().is_some() {
+            self.status_message = Some("Cut cancelled".to_string());
+        }
+    }
+
+    /// Last used column of the current sheet (at least 1)
+    pub fn last_used_col(&self) -> u32 {
+        self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)
+            .map(|sheet| sheet.get_highest_column())
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    fn select_row(&mut self) {
+        let max_col = self.last_used_col();
+        let row = self.workbook.cursor.0;
+        self.workbook.selection = Selection { start: (row, 1), end: (row, max_col) };
+    }
+
+    /// Last used row of the current sheet (at least 1)
+    pub fn last_used_row(&self) -> u32 {
+        self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)
+            .map(|sheet| sheet.get_highest_row())
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    fn select_column(&mut self) {
+        let max_row = self.last_used_row();
+        let col = self.workbook.cursor.1;
+        self.workbook.selection = Selection { start: (1, col), end: (max_row, col) };
+    }
+
+    /// Count, sum and average of the selected cells (only cells that exist are visited)
+    pub fn selection_stats(&self) -> SelectionStats {
+        self.stats_where(|row, col| self.workbook.selection.contains(row, col))
+    }
+
+    /// Count, sum and average of a whole column's used cells
+    pub fn column_stats(&self, col: u32) -> SelectionStats {
+        self.stats_where(|_, c| c == col)
+    }
+
+    fn stats_where(&self, include: impl Fn(u32, u32) -> bool) -> SelectionStats {
+        let mut stats = SelectionStats::default();
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) {
+            for cell in sheet.get_cell_collection() {
+                let coord = cell.get_coordinate();
+                if !include(*coord.get_row_num(), *coord.get_col_num()) {
+                    continue;
+                }
+                if cell_is_blank(cell) {
+                    continue;
+                }
+                let value = cell.get_value();
+                stats.count += 1;
+                if let Some(number) = parse_number(&value) {
+                    stats.numeric_count += 1;
+                    stats.sum += number;
+                }
+            }
+        }
+        stats
+    }
+
+    fn next_sheet(&mut self) {
+        if let Some(index) = self.step_listed_sheet(self.workbook.current_sheet_index, 1) {
+            self.switch_to_sheet(index);
+        }
+    }
+
+    fn prev_sheet(&mut self) {
+        if let Some(index) = self.step_listed_sheet(self.workbook.current_sheet_index, -1) {
+            self.switch_to_sheet(index);
+        }
+    }
+
+    pub fn is_sheet_hidden(&self, index: usize) -> bool {
+        self.workbook.spreadsheet.get_sheet(&index).is_some_and(sheet_is_hidden)
+    }
+
+    /// Sheets offered by the selector and sheet cycling: hidden ones only when revealed
+    pub fn listed_sheets(&self) -> Vec<usize> {
+        (0..self.workbook.spreadsheet.get_sheet_count())
+            .filter(|&i| self.show_hidden_sheets || !self.is_sheet_hidden(i))
+            .collect()
+    }
+
+    /// Next listed sheet `delta` steps away from `from`, wrapping around
+    fn step_listed_sheet(&self, from: usize, delta: i32) -> Option<usize> {
+        let count = self.workbook.spreadsheet.get_sheet_count() as i32;
+        let listed = self.listed_sheets();
+        let mut index = from as i32;
+        for _ in 0..count {
+            index = (index + delta).rem_euclid(count);
+            if listed.contains(&(index as usize)) {
+                return Some(index as usize);
+            }
+        }
+        None
+    }
+
+    fn toggle_show_hidden_sheets(&mut self) {
+        self.show_hidden_sheets = !self.show_hidden_sheets;
+        if !self.listed_sheets().contains(&self.sheet_select_index) {
+            self.sheet_select_index = self.step_listed_sheet(self.sheet_select_index, 1).unwrap_or(self.workbook.current_sheet_index);
+        }
+    }
+
+    /// Tab color of a sheet as ARGB, if it has one Excel can show without the theme
+    pub fn sheet_tab_color(&self, index: usize) -> Option<u32> {
+        self.workbook.spreadsheet.get_sheet(&index)?.get_tab_color().and_then(color_to_argb)
+    }
+
+    /// Open the color picker for the sheet highlighted in the selector
+    fn enter_tab_color_mode(&mut self) {
+        if self.reject_if_read_only() {
+            return;
+        }
+        self.tab_color_sheet = Some(self.sheet_select_index);
+        self.mode = Mode::ColorPick;
+    }
+
+    /// Set (or with None remove) the tab color picked for `tab_color_sheet`, then return to the selector
+    fn set_tab_color(&mut self, argb: Option<u32>) {
+        let Some(index) = self.tab_color_sheet.take() else {
+            return;
+        };
+        self.mode = Mode::SheetSelect;
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&index) else {
+            return;
+        };
+        match argb {
+            Some(argb) => sheet.set_tab_color(excel_color(argb)),
+            None => sheet.remove_tab_color(),
+        };
+        self.workbook.dirty = true;
+        let name = self.get_sheet_names()[index].clone();
+        let color = argb.and_then(|argb| COLOR_PALETTE.iter().find(|&&(_, entry)| entry == argb));
+        self.status_message = Some(match color {
+            Some((label, _)) => format!("Tab color of {} set to {}", name, label.to_lowercase()),
+            None => format!("Tab color of {} removed", name),
+        });
+    }
+
+    /// Hide or unhide the sheet highlighted in the selector; at least one sheet stays visible.
+    /// Hiding the current sheet moves to the next visible one.
+    fn toggle_sheet_hidden(&mut self) {
+        if self.reject_if_read_only() {
+            return;
+        }
+        let index = self.sheet_select_index;
+        let hide = !self.is_sheet_hidden(index);
+        let visible = (0..self.workbook.spreadsheet.get_sheet_count()).filter(|&i| !self.is_sheet_hidden(i)).count();
+        if hide && visible <= 1 {
+            self.status_message = Some("A workbook needs at least one visible sheet".to_string());
+            return;
+        }
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&index) {
+            sheet.set_sheet_state(if hide { "hidden" } else { "visible" }.to_string());
+            self.workbook.dirty = true;
+        }
+        if hide && index == self.workbook.current_sheet_index {
+            let count = self.workbook.spreadsheet.get_sheet_count();
+            if let Some(next) = (1..count).map(|step| (index + step) % count).find(|&i| !self.is_sheet_hidden(i)) {
+                self.switch_to_sheet(next);
+            }
+        }
+        if hide && !self.show_hidden_sheets {
+            self.sheet_select_index = self.step_listed_sheet(index, 1).unwrap_or(self.workbook.current_sheet_index);
+        }
+    }
+
+    /// Make another sheet current, remembering this sheet's view and restoring the target's
+    fn switch_to_sheet(&mut self, index: usize) {
+        if index == self.workbook.current_sheet_index {
+            return;
+        }
+
+        self.workbook.sheet_views.insert(self.workbook.current_sheet_index, SheetViewState {
+            cursor: self.workbook.cursor,
+            selection: self.workbook.selection,
+            scroll: self.workbook.scroll,
+        });
+
+        self.ensure_sheet_loaded(index);
+
+        // Sheets visited for the first time start at A1
+        let view = self.workbook.sheet_views.get(&index).copied().unwrap_or_default();
+        self.workbook.current_sheet_index = index;
+        self.workbook.cursor = view.cursor;
+        self.workbook.selection = view.selection;
+        self.workbook.scroll = view.scroll;
+        self.reset_split_view();
+    }
+
+    /// Parse a sheet left unread by a lazy open, picking up its cell marks
+    fn ensure_sheet_loaded(&mut self, index: usize) {
+        load_lazy_sheet(&mut self.workbook.spreadsheet, index, &mut self.workbook.cell_marks, &mut self.workbook.column_widths);
+    }
+
+    fn enter_edit_mode(&mut self) {
+        if self.reject_if_read_only() {
+            return;
+        }
+
+        if self.reject_if_locked(Selection::single(self.workbook.cursor.0, self.workbook.cursor.1)) {
+            return;
+        }
+
+        // Check if this is a formula cell (read-only)
+        if self.is_formula_cell(self.workbook.cursor.1, self.workbook.cursor.0) {
+            // Show the formula in status message instead of editing
+            if let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) {
+                let formula = sheet.get_cell_value((self.workbook.cursor.1, self.workbook.cursor.0)).get_formula();
+                self.status_message = Some(format!("Formula (read-only): ={}", formula));
+            }
+            return;
+        }
+
+        self.mode = Mode::Edit;
+        let is_text = self.cell_type(self.workbook.cursor.1, self.workbook.cursor.0) == CellType::Text;
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) {
+            let mut value = sheet.get_cell_value((self.workbook.cursor.1, self.workbook.cursor.0)).get_value().to_string();
+            // Text that would be typed as something else on save (e.g. the zip code 0123) gets
+            // its apostrophe back, so editing it doesn't change its type
+            let retyped = value.starts_with('=') || parse_input_to_cell_value(&value) != InputValue::Text(value.clone());
+            if is_text && retyped {
+                value.insert(0, '\'');
+            }
+            // One textarea line per line of the cell; joined back with "\n" on save
+            self.textarea = TextArea::from(value.split('\n'));
+        }
+    }
+
+    /// Save the edit and step the cursor as configured for Enter or Tab. With
+    /// `return_to_entry_column`, Enter after a run of Tabs goes back to the run's first column
+    /// (or row, when Tab moves vertically) before stepping, as Excel does for row-by-row entry.
+    fn commit_edit(&mut self, tab: bool) {
+        self.save_cell_value();
+        self.mode = Mode::View;
+
+        // The run is broken by moving the cursor any other way in between
+        let anchor = self.entry_anchor.take()
+            .filter(|&(_, expected)| expected == self.workbook.cursor)
+            .map(|(start, _)| start);
+        if tab {
+            let start = anchor.unwrap_or(self.workbook.cursor);
+            let (dx, dy) = self.tab_moves.delta();
+            self.move_cursor(dx, dy, false);
+            self.entry_anchor = Some((start, self.workbook.cursor));
+            return;
+        }
+
+        if let Some((row, col)) = anchor.filter(|_| self.return_to_entry_column) {
+            match self.tab_moves {
+                EntryMove::Right | EntryMove::Left => self.workbook.cursor.1 = col,
+                EntryMove::Down | EntryMove::Up => self.workbook.cursor.0 = row,
+                EntryMove::Stay => {}
+            }
+        }
+        let (dx, dy) = self.enter_moves.delta();
+        self.move_cursor(dx, dy, false);
+    }
+
+    fn save_cell_value(&mut self) {
+        let content = self.textarea.lines().join("\n");
+        self.set_cursor_value(content);
+    }
+
+    fn set_cursor_value(&mut self, content: String) {
+        if content.is_empty() {
+            // An empty edit clears the cell instead of leaving an empty-string cell behind
+            if self.clear_cell(self.workbook.cursor.1, self.workbook.cursor.0) {
+                self.workbook.dirty = true;
+            }
+            self.last_action = Some(LastAction::SetValue(content));
+            return;
+        }
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&self.workbook.current_sheet_index) {
+             let cell = sheet.get_cell_mut((self.workbook.cursor.1, self.workbook.cursor.0));
+             match parse_input_to_cell_value(&content) {
+                 InputValue::Number(number) => cell.set_value_number(number),
+                 InputValue::Bool(value) => cell.set_value_bool(value),
+                 // umya stores the standard error literals as error cells
+                 InputValue::Error(error) => cell.set_value(error),
+                 InputValue::Text(text) => cell.set_value_string(text),
+             };
+             self.workbook.dirty = true;
+             self.last_action = Some(LastAction::SetValue(content));
+        }
+    }
+
+    /// Re-apply the last mutating action at the current cursor/selection
+    fn repeat_last_action(&mut self) {
+        let Some(action) = self.last_action.clone() else {
+            self.status_message = Some("Nothing to repeat".to_string());
+            return;
+        };
+        if self.reject_if_read_only() {
+            return;
+        }
+        match action {
+            LastAction::SetValue(value) => {
+                if self.reject_if_locked(Selection::single(self.workbook.cursor.0, self.workbook.cursor.1)) {
+                    return;
+                }
+                if self.is_formula_cell(self.workbook.cursor.1, self.workbook.cursor.0) {
+                    self.status_message = Some("Formula cells are read-only".to_string());
+                    return;
+                }
+                self.set_cursor_value(value);
+                self.status_message = Some("Repeated: set value".to_string());
+            }
+            LastAction::Mark(mark) => self.set_mark_for_selection(mark),
+            LastAction::Clear => self.clear_selection_contents(),
+            LastAction::Fill { down, copy_only } => self.fill_selection(down, copy_only),
+            LastAction::NumberFormat { label, code } => self.apply_number_format(&label, &code),
+            LastAction::Decimals(delta) => self.adjust_decimals(delta),
+            LastAction::Emphasis(emphasis) => self.toggle_emphasis(emphasis),
+        }
+    }
+
+    /// Text of the comment attached to a cell on the current sheet, if any
+    pub fn comment_text(&self, col: u32, row: u32) -> Option<String> {
+        let sheet = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)?;
+        sheet.get_comments().iter()
+            .find(|comment| {
+                let coord = comment.get_coordinate();
+                *coord.get_col_num() == col && *coord.get_row_num() == row
+            })
+            .map(|comment| comment.get_text().get_text().to_string())
+    }
+
+    /// URL of the hyperlink attached to a cell on the current sheet, if any
+    pub fn hyperlink_url(&self, col: u32, row: u32) -> Option<String> {
+        self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)?
+            .get_cell((col, row))?
+            .get_hyperlink()
+            .map(|link| link.get_url().to_string())
+            .filter(|url| !url.is_empty())
+    }
+
+    /// Open the cursor cell's hyperlink with the OS default handler
+    fn open_hyperlink(&mut self) {
+        let Some(url) = self.hyperlink_url(self.workbook.cursor.1, self.workbook.cursor.0) else {
+            self.status_message = Some("No hyperlink in this cell".to_string());
+            return;
+        };
+        self.status_message = Some(match open::that_detached(&url) {
+            Ok(_) => format!("Opened {}", url),
+            Err(e) => format!("Error: failed to open {}: {}", url, e),
+        });
+    }
+
+    fn enter_comment_mode(&mut self) {
+        let text = self.comment_text(self.workbook.cursor.1, self.workbook.cursor.0).unwrap_or_default();
+        if self.read_only {
+            // Comments can still be read, just not edited
+            self.status_message = Some(if text.is_empty() {
+                "No comment on this cell".to_string()
+            } else {
+                format!("Comment: {}", text.replace('\n', " "))
+            });
+            return;
+        }
+        self.textarea = TextArea::from(text.split('\n'));
+        self.textarea.move_cursor(CursorMove::Bottom);
+        self.textarea.move_cursor(CursorMove::End);
+        self.mode = Mode::Comment;
+    }
+
+    /// Write the comment being edited back to the cursor cell; an empty comment removes it
+    fn save_comment(&mut self) {
+        let (row, col) = self.workbook.cursor;
+        let text = self.textarea.lines().join("\n");
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&self.workbook.current_sheet_index) else {
+            return;
+        };
+
+        let comments = sheet.get_comments_mut();
+        let existing = comments.iter().position(|comment| {
+            let coord = comment.get_coordinate();
+            *coord.get_col_num() == col && *coord.get_row_num() == row
+        });
+        match (existing, text.trim().is_empty()) {
+            (Some(index), true) => {
+                comments.remove(index);
+                self.status_message = Some("Comment removed".to_string());
+            }
+            (Some(index), false) => {
+                comments[index].set_text_string(text);
+                self.status_message = Some("Comment updated".to_string());
+            }
+            (None, true) => return,
+            (None, false) => {
+                let mut comment = Comment::default();
+                comment.new_comment(format!("{}{}", number_to_column(col), row));
+                comment.set_text_string(text);
+                sheet.add_comments(comment);
+                self.status_message = Some("Comment added".to_string());
+            }
+        }
+        self.workbook.dirty = true;
+    }
+
+    /// Write a snapshot of the active workbook on a background thread so the UI stays responsive
+    fn save_and_report(&mut self) {
+        if self.reject_if_read_only() {
+            return;
+        }
+        if self.pending_save.is_some() {
+            self.status_message = Some("A save is already in progress".to_string());
+            return;
+        }
+        // Another program wrote the file since it was read: ask before clobbering its changes
+        if self.changed_on_disk() {
+            self.mode = Mode::ConfirmDiskChange;
+            return;
+        }
+        self.start_background_save();
+    }
+
+    fn start_background_save(&mut self) {
+        let spreadsheet = self.workbook.spreadsheet.clone();
+        let path = self.workbook.path.clone();
+        let backup = self.backup;
+        let handle = std::thread::spawn({
+            let path = path.clone();
+            move || write_spreadsheet(&spreadsheet, &path, backup)
+        });
+
+        // Edits made while the write runs mark the workbook dirty again
+        self.workbook.dirty = false;
+        self.pending_save = Some(PendingSave { path, started: Instant::now(), handle });
+    }
+
+    /// Block until the background save (if any) finishes and report its result
+    pub fn wait_for_pending_save(&mut self) {
+        let Some(save) = self.pending_save.take() else {
+            return;
+        };
+        let result = save.handle.join()
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("Save thread panicked")));
+        match result {
+            Ok(_) => {
+                if self.workbook.path == save.path {
+                    self.workbook.disk_mtime = modified_time(&save.path);
+                    self.workbook.imported_from = None;
+                } else if let Some(workbook) = self.workbooks.iter_mut().find(|w| w.path == save.path) {
+                    workbook.disk_mtime = modified_time(&save.path);
+                    workbook.imported_from = None;
+                }
+                self.status_message = Some(format!("Saved: {:?}", save.path));
+            }
+            Err(e) => {
+                // The workbook may have been parked since the save started
+                if self.workbook.path == save.path {
+                    self.workbook.dirty = true;
+                } else if let Some(workbook) = self.workbooks.iter_mut().find(|w| w.path == save.path) {
+                    workbook.dirty = true;
+                }
+                self.status_message = Some(format!("Error: {}", e));
+            }
+        }
+    }
+
+    fn save_file(&mut self) -> Result<()> {
+        if self.read_only {
+            anyhow::bail!("Read-only mode");
+        }
+        self.wait_for_pending_save();
+        if self.changed_on_disk() && self.workbook.imported_from.is_some() {
+            anyhow::bail!("{:?} already exists (Ctrl+S to confirm overwriting it, :w! to overwrite)", self.workbook.path);
+        }
+        if self.changed_on_disk() {
+            anyhow::bail!("{:?} changed on disk since it was read (Ctrl+S to overwrite or reload, :w! to overwrite)", self.workbook.path);
+        }
+        self.overwrite_file()
+    }
+
+    /// Write the active workbook to its file without checking it for outside changes
+    fn overwrite_file(&mut self) -> Result<()> {
+        if self.read_only {
+            anyhow::bail!("Read-only mode");
+        }
+        self.wait_for_pending_save();
+        self.write_to(&self.workbook.path)?;
+        self.workbook.dirty = false;
+        self.workbook.disk_mtime = modified_time(&self.workbook.path);
+        self.workbook.imported_from = None;
+        Ok(())
+    }
+
+    fn write_to(&self, path: &Path) -> Result<()> {
+        write_spreadsheet(&self.workbook.spreadsheet, path, self.backup)
+    }
+
+    fn enter_save_as_mode(&mut self) {
+        if self.reject_if_read_only() {
+            return;
+        }
+        self.textarea = TextArea::from(vec![self.workbook.path.display().to_string()]);
+        self.textarea.move_cursor(CursorMove::End);
+        self.mode = Mode::SaveAs;
+    }
+
+    fn confirm_save_as(&mut self) {
+        let input = self.textarea.lines().join("");
+        let input = input.trim();
+        if input.is_empty() {
+            self.status_message = Some("No file name given".to_string());
+            self.mode = Mode::View;
+            return;
+        }
+
+        let path = PathBuf::from(input);
+        if path.exists() && path != self.workbook.path {
+            // Ask before clobbering a different existing file
+            self.pending_save_path = Some(path);
+            self.mode = Mode::ConfirmOverwrite;
+        } else {
+            self.save_as(path);
+        }
+    }
+
+    fn save_as(&mut self, path: PathBuf) {
+        // Finish a Ctrl+S still writing in the background first, so the two writes never overlap
+        self.wait_for_pending_save();
+        match self.write_to(&path) {
+            Ok(_) => {
+                self.workbook.disk_mtime = modified_time(&path);
+                self.workbook.imported_from = None;
+                self.workbook.path = path;
+                self.workbook.dirty = false;
+                self.status_message = Some(format!("Saved: {:?}", self.workbook.path));
+            }
+            Err(e) => self.status_message = Some(format!("Error: {}", e)),
+        }
+        self.mode = Mode::View;
+    }
+
+    fn widen_column(&mut self) {
+        let col = self.workbook.cursor.1;
+        let current = self.get_column_width(col);
+        let new_width = (current + self.column_width_step).min(self.max_column_width);
+        self.set_column_width(col, Some(new_width));
+        if new_width >= self.max_column_width {
+            self.status_message = Some(format!("Column width at maximum ({})", self.max_column_width));
+        }
+    }
+
+    fn shrink_column(&mut self) {
+        let col = self.workbook.cursor.1;
+        let current = self.get_column_width(col);
+        let new_width = current.saturating_sub(self.column_width_step).max(MIN_COLUMN_WIDTH);
+        if new_width <= MIN_COLUMN_WIDTH {
+            self.set_column_width(col, None);
+            self.status_message = Some(format!("Column width at minimum ({})", MIN_COLUMN_WIDTH));
+        } else {
+            self.set_column_width(col, Some(new_width));
+        }
+    }
+
+    /// Change a column's width on the current sheet (`None` resets it to the default)
+    /// and mirror it into the sheet's column dimensions so it is saved with the file
+    fn set_column_width(&mut self, col: u32, width: Option<u16>) {
+        let key = (self.workbook.current_sheet_index, col);
+        match width {
+            Some(width) => self.workbook.column_widths.insert(key, width),
+            None => self.workbook.column_widths.remove(&key),
+        };
+        if self.read_only {
+            return;
+        }
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&self.workbook.current_sheet_index) {
+            // Columns the file never sized stay unsized when reset to the default
+            if width.is_none() && sheet.get_column_dimension_by_number(&col).is_none() {
+                return;
+            }
+            let width = width.unwrap_or(self.default_column_width);
+            sheet.get_column_dimension_by_number_mut(&col).set_width(width as f64 + EXCEL_WIDTH_PADDING);
+            self.workbook.dirty = true;
+        }
+    }
+
+    fn cycle_row_display_height(&mut self) {
+        self.row_display_height = self.row_display_height % MAX_ROW_DISPLAY_HEIGHT + 1;
+        self.status_message = Some(format!("Row height: {} line(s)", self.row_display_height));
+    }
+
+    pub fn get_column_width(&self, col: u32) -> u16 {
+        self.workbook.column_widths.get(&(self.workbook.current_sheet_index, col)).copied()
+            .unwrap_or(self.default_column_width)
+            .min(self.max_column_width)
+    }
+
+    /// Widest display width (in terminal columns) of any used cell in the column
+    fn content_width(&self, col: u32) -> Option<u16> {
+        let sheet = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)?;
+        sheet.get_cell_collection()
+            .iter()
+            .filter(|cell| *cell.get_coordinate().get_col_num() == col)
+            .map(|cell| {
+                let row = *cell.get_coordinate().get_row_num();
+                mark_newlines(&self.get_cell_text(col, row)).width().min(self.max_column_width as usize) as u16
+            })
+            .max()
+    }
+
+    fn fit_column(&mut self, col: u32) -> Option<u16> {
+        let width = self.content_width(col)?.clamp(MIN_COLUMN_WIDTH, self.max_column_width);
+        self.set_column_width(col, Some(width));
+        Some(width)
+    }
+
+    fn autofit_column(&mut self, col: u32) {
+        match self.fit_column(col) {
+            Some(width) => self.status_message = Some(format!("Column width set to {}", width)),
+            None => self.status_message = Some("Column is empty".to_string()),
+        }
+    }
+
+    fn autofit_all_columns(&mut self) {
+        let cols: Vec<u32> = (0..self.viewport_size.1 as u32)
+            .map(|pos| self.col_at_position(pos))
+            .collect();
+        let fitted = cols.into_iter().filter(|&col| self.fit_column(col).is_some()).count();
+        self.status_message = Some(format!("Auto-fit {} column(s)", fitted));
+    }
+
+    fn copy_selection(&mut self) {
+        let (min_row, min_col, max_row, max_col) = self.workbook.selection.bounds();
+        let merged = self.merged_ranges();
+
+        let mut data = Vec::new();
+        let mut extras = Vec::new();
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) {
+            for r in min_row..=max_row {
+                let mut row_data = Vec::new();
+                let mut row_extras = Vec::new();
+                for c in min_col..=max_col {
+                    // A merged block contributes its value once, from its top-left cell
+                    let covered = merged.iter().any(|range| {
+                        let (top, left, _, _) = range.bounds();
+                        range.contains(r, c) && (top, left) != (r, c)
+                    });
+                    if covered {
+                        row_data.push(String::new());
+                        row_extras.push(ClipboardCell::default());
+                        continue;
+                    }
+
+                    let cell_value = sheet.get_cell_value((c, r));
+                    row_data.push(cell_value.get_value().to_string());
+
+                    let formula = cell_value.get_formula();
+                    let number_format = sheet.get_cell((c, r))
+                        .and_then(|cell| cell.get_style().get_number_format())
+                        .map(|num_fmt| num_fmt.get_format_code().to_string())
+                        .filter(|code| code != NumberingFormat::FORMAT_GENERAL);
+                    row_extras.push(ClipboardCell {
+                        number_format,
+                        mark: self.get_cell_mark(r, c),
+                        formula: (!formula.is_empty()).then(|| formula.to_string()),
+                    });
+                }
+                data.push(row_data);
+                extras.push(row_extras);
+            }
+        }
+
+        let merges = merged.iter()
+            .map(|range| range.bounds())
+            .filter(|&(r1, c1, r2, c2)| r1 >= min_row && c1 >= min_col && r2 <= max_row && c2 <= max_col)
+            .map(|(r1, c1, r2, c2)| ((r1 - min_row, c1 - min_col), (r2 - min_row, c2 - min_col)))
+            .collect();
+
+        let cells = (max_row - min_row + 1) * (max_col - min_col + 1);
+        self.clipboard = Clipboard { data, cells: extras, merges, origin: (min_row, min_col) };
+        self.cut_source = None;
+        self.status_message = Some(format!("Copied {} cell(s)", cells));
+    }
+
+    /// Put the sheet-qualified reference of the cursor cell or selection (e.g. Sheet1!C5:E9)
+    /// on the system clipboard
+    fn copy_reference(&mut self) {
+        let name = self.get_sheet_names().get(self.workbook.current_sheet_index).cloned().unwrap_or_default();
+        let reference = sheet_reference(&name, self.workbook.selection);
+        self.status_message = Some(match crate::osc52::copy(&reference) {
+            Ok(()) => format!("Copied reference {}", reference),
+            Err(e) => format!("Error: couldn't copy {}: {}", reference, e),
+        });
+    }
+
+    /// Copy the selection and clear it once it has been pasted somewhere
+    fn cut_selection(&mut self) {
+        if self.reject_if_read_only() || self.reject_if_locked(self.workbook.selection) {
+            return;
+        }
+        self.copy_selection();
+        self.cut_source = Some((self.workbook.current_sheet_index, self.workbook.selection));
+        let (min_row, min_col, max_row, max_col) = self.workbook.selection.bounds();
+        let cells = (max_row - min_row + 1) * (max_col - min_col + 1);
+        self.status_message = Some(format!("Cut {} cell(s) — paste to move, Esc to cancel", cells));
+    }
+
+    /// Pick up the selected block; arrows then move it and Enter drops it
+    fn start_move(&mut self) {
+        if self.reject_if_read_only() || self.reject_if_locked(self.workbook.selection) {
+            return;
+        }
+        let (top, left, bottom, right) = self.workbook.selection.bounds();
+        let block = Selection { start: (top, left), end: (bottom, right) };
+        self.moving = Some(block);
+        self.workbook.selection = block;
+        if self.cut_source.take().is_some() {
+            self.status_message = Some("Pending cut cancelled — moving the selected block instead".to_string());
+        }
+        self.mode = Mode::Move;
+    }
+
+    /// Shift the prospective position of the moving block, keeping it on the sheet
+    fn shift_move(&mut self, dx: i32, dy: i32) {
+        let (top, left, bottom, right) = self.workbook.selection.bounds();
+        let dy = dy.clamp(1 - top as i32, (MAX_ROWS - bottom) as i32);
+        let dx = dx.clamp(1 - left as i32, (MAX_COLUMNS - right) as i32);
+        let offset = |(row, col): (u32, u32)| ((row as i32 + dy) as u32, (col as i32 + dx) as u32);
+        self.workbook.selection = Selection { start: offset(self.workbook.selection.start), end: offset(self.workbook.selection.end) };
+        self.workbook.cursor = offset(self.workbook.cursor);
+        self.adjust_scroll();
+    }
+
+    /// Drop the moving block at the selection: a cut and paste that leaves the clipboard alone
+    fn commit_move(&mut self) {
+        self.mode = Mode::View;
+        let Some(source) = self.moving.take() else {
+            return;
+        };
+        let target = self.workbook.selection;
+        if target == source {
+            self.status_message = Some("Block not moved".to_string());
+            return;
+        }
+        if self.reject_if_locked(target) {
+            self.workbook.selection = source;
+            self.workbook.cursor = source.start;
+            self.adjust_scroll();
+            return;
+        }
+
+        let clipboard = std::mem::take(&mut self.clipboard);
+        self.workbook.selection = source;
+        self.cut_selection();
+        if self.cut_source.is_none() {
+            // The cut was refused; its message says why
+            self.clipboard = clipboard;
+            self.workbook.cursor = source.start;
+            self.adjust_scroll();
+            return;
+        }
+        self.workbook.cursor = target.start;
+        self.workbook.selection = Selection::single(target.start.0, target.start.1);
+        self.paste_clipboard();
+        self.clipboard = clipboard;
+        self.workbook.selection = target;
+    }
+
+    /// Put the moving block back where it was
+    fn cancel_move(&mut self) {
+        self.mode = Mode::View;
+        if let Some(source) = self.moving.take() {
+            self.workbook.selection = source;
+            self.workbook.cursor = source.start;
+            self.adjust_scroll();
+        }
+    }
+
+    /// While a block is being moved, the cell whose content shows at (row, col):
+    /// the block appears at its prospective position and its old area looks empty
+    pub fn move_preview_source(&self, row: u32, col: u32) -> Option<(u32, u32)> {
+        let Some(source) = self.moving else {
+            return Some((row, col));
+        };
+        let target = self.workbook.selection;
+        if target.contains(row, col) {
+            return Some((row - target.start.0 + source.start.0, col - target.start.1 + source.start.1));
+        }
+        (!source.contains(row, col)).then_some((row, col))
+    }
+
+    /// After pasting a cut, clear the source cells the paste didn't overwrite
+    fn finish_cut(&mut self, target: Selection) -> bool {
+        let Some((sheet_idx, source)) = self.cut_source.take() else {
+            return false;
+        };
+        let (min_row, min_col, max_row, max_col) = source.bounds();
+        for r in min_row..=max_row {
+            for c in min_col..=max_col {
+                if sheet_idx == self.workbook.current_sheet_index && target.contains(r, c) {
+                    continue;
+                }
+                // The mark went along with the paste; the formatting left decides whether the cell stays
+                if self.workbook.cell_marks.remove(&(sheet_idx, r, c)).is_some()
+                    && let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&sheet_idx)
+                {
+                    Self::apply_mark_style(sheet.get_cell_mut((c, r)).get_style_mut(), CellMark::None);
+                }
+                self.clear_cell_on(sheet_idx, c, r);
+            }
+        }
+        true
+    }
+
+    fn paste_clipboard(&mut self) {
+        self.paste(false, true);
+    }
+
+    fn paste_values(&mut self) {
+        self.paste(false, false);
+    }
+
+    fn paste_transposed(&mut self) {
+        self.paste(true, true);
+    }
+
+    fn paste(&mut self, transpose: bool, with_formatting: bool) {
+        if self.reject_if_read_only() {
+            return;
+        }
+        if self.clipboard.data.is_empty() {
+            self.status_message = Some("Clipboard is empty".to_string());
+            return;
+        }
+
+        let rows = self.clipboard.data.len();
+        let cols = self.clipboard.data.first().map(|r| r.len()).unwrap_or(0);
+        let (rows, cols) = if transpose { (cols, rows) } else { (rows, cols) };
+
+        // A selection bigger than the clipboard is filled with copies of it, as in Excel,
+        // as long as it divides evenly; a pending cut is only ever moved once
+        let (top, left, bottom, right) = self.workbook.selection.bounds();
+        let (sel_rows, sel_cols) = ((bottom - top + 1) as usize, (right - left + 1) as usize);
+        let mut origin = self.workbook.cursor;
+        let mut tiles = (1, 1);
+        let mut warning = None;
+        if self.cut_source.is_none() && (sel_rows > rows || sel_cols > cols) {
+            if sel_rows.is_multiple_of(rows) && sel_cols.is_multiple_of(cols) {
+                origin = (top, left);
+                tiles = (sel_rows / rows, sel_cols / cols);
+            } else {
+                warning = Some(format!("selection isn't a multiple of the {}x{} clipboard, pasted once", rows, cols));
+            }
+        }
+        let (start_row, start_col) = origin;
+        let target = Selection {
+            start: (start_row, start_col),
+            end: (start_row + (rows * tiles.0).saturating_sub(1) as u32, start_col + (cols * tiles.1).saturating_sub(1) as u32),
+        };
+        if self.reject_if_locked(target) {
+            return;
+        }
+        for tile_row in 0..tiles.0 {
+            for tile_col in 0..tiles.1 {
+                let tile_origin = (origin.0 + (tile_row * rows) as u32, origin.1 + (tile_col * cols) as u32);
+                self.paste_block(tile_origin, transpose, with_formatting);
+            }
+        }
+
+        self.workbook.dirty = true;
+        if self.finish_cut(target) {
+            self.status_message = Some(format!("Moved {}x{} cells", rows, cols));
+            return;
+        }
+        let mut notes = Vec::new();
+        if transpose {
+            notes.push("transposed".to_string());
+        }
+        if !with_formatting {
+            notes.push("values only".to_string());
+        }
+        notes.extend(warning);
+        let suffix = if notes.is_empty() { String::new() } else { format!(" ({})", notes.join(", ")) };
+        self.status_message = Some(if tiles == (1, 1) {
+            format!("Pasted {}x{} cells{}", rows, cols, suffix)
+        } else {
+            let (end_row, end_col) = target.end;
+            format!("Filled {}{}:{}{} with {} copies of {}x{} cells{}",
+                number_to_column(start_col), start_row, number_to_column(end_col), end_row,
+                tiles.0 * tiles.1, rows, cols, suffix)
+        });
+    }
+
+    /// Write the clipboard once with its top-left cell at `origin`
+    fn paste_block(&mut self, origin: (u32, u32), transpose: bool, with_formatting: bool) {
+        let (start_row, start_col) = origin;
+        let sheet_idx = self.workbook.current_sheet_index;
+        // A cut formula keeps pointing at the same cells, as in Excel; a copy shifts with the paste
+        let shift_references = self.cut_source.is_none();
+
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&sheet_idx) {
+            for (r, row_data) in self.clipboard.data.iter().enumerate() {
+                for (c, value) in row_data.iter().enumerate() {
+                    let (dr, dc) = if transpose { (c, r) } else { (r, c) };
+                    let target_row = start_row + dr as u32;
+                    let target_col = start_col + dc as u32;
+
+                    if target_row > MAX_ROWS || target_col > MAX_COLUMNS {
+                        continue;
+                    }
+
+                    let cell = sheet.get_cell_mut((target_col, target_row));
+                    let extra = self.clipboard.cells.get(r).and_then(|row| row.get(c));
+                    match extra.filter(|_| with_formatting) {
+                        Some(extra) => {
+                            match &extra.formula {
+                                Some(formula) if shift_references => {
+                                    let source = (self.clipboard.origin.0 + r as u32, self.clipboard.origin.1 + c as u32);
+                                    let delta = (target_row as i64 - source.0 as i64, target_col as i64 - source.1 as i64);
+                                    cell.set_formula(shift_formula(formula, delta.0, delta.1))
+                                }
+                                Some(formula) => cell.set_formula(formula.clone()),
+                                None => cell.set_value(value),
+                            };
+
+                            let style = cell.get_style_mut();
+                            match &extra.number_format {
+                                Some(code) => {
+                                    style.get_number_format_mut().set_format_code(code.clone());
+                                }
+                                None if style.get_number_format().is_some() => {
+                                    style.get_number_format_mut().set_format_code(NumberingFormat::FORMAT_GENERAL);
+                                }
+                                None => {}
+                            }
+
+                            // Only touch fill/font when a mark is added or replaced
+                            let key = (sheet_idx, target_row, target_col);
+                            if extra.mark != CellMark::None || self.workbook.cell_marks.contains_key(&key) {
+                                Self::apply_mark_style(style, extra.mark);
+                                if extra.mark == CellMark::None {
+                                    self.workbook.cell_marks.remove(&key);
+                                } else {
+                                    self.workbook.cell_marks.insert(key, extra.mark);
+                                }
+                            }
+                        }
+                        None => {
+                            cell.set_value(value);
+                        }
+                    }
+                }
+            }
+        }
+
+        if with_formatting {
+            self.paste_merges(origin, transpose);
+        }
+    }
+
+    /// Recreate the clipboard's merged blocks at `origin`, skipping any that
+    /// would overlap an existing merge or run off the sheet
+    fn paste_merges(&mut self, origin: (u32, u32), transpose: bool) {
+        let (start_row, start_col) = origin;
+        let existing = self.merged_ranges();
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&self.workbook.current_sheet_index) else {
+            return;
+        };
+        for &(top_left, bottom_right) in &self.clipboard.merges {
+            let offset = |(r, c): (u32, u32)| if transpose { (start_row + c, start_col + r) } else { (start_row + r, start_col + c) };
+            let range = Selection { start: offset(top_left), end: offset(bottom_right) };
+            let (r1, c1, r2, c2) = range.bounds();
+            if r2 > MAX_ROWS || c2 > MAX_COLUMNS {
+                continue;
+            }
+            let overlaps = existing.iter().any(|other| {
+                let (o1, p1, o2, p2) = other.bounds();
+                r1 <= o2 && o1 <= r2 && c1 <= p2 && p1 <= c2
+            });
+            if !overlaps {
+                sheet.add_merge_cells(format!("{}{}:{}{}", number_to_column(c1), r1, number_to_column(c2), r2));
+            }
+        }
+    }
+
+    /// Merged cell blocks on the current sheet
+    pub fn merged_ranges(&self) -> Vec<Selection> {
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) else {
+            return Vec::new();
+        };
+        sheet.get_merge_cells().iter()
+            .filter_map(|range| {
+                let range = range.get_range();
+                let (start, end) = range.split_once(':')?;
+                Some(Selection { start: parse_cell_ref(start)?, end: parse_cell_ref(end)? })
+            })
+            .collect()
+    }
+
+    /// Fill the rows below the top row of the selection
+    fn fill_down(&mut self, copy_only: bool) {
+        self.fill_selection(true, copy_only);
+    }
+
+    /// Fill the columns right of the left column of the selection
+    fn fill_right(&mut self, copy_only: bool) {
+        self.fill_selection(false, copy_only);
+    }
+
+    /// Fill each column (down) or row (right) of the selection from its first cell. When the
+    /// leading cells already form an arithmetic series of numbers or dates (1, 2 or Jan 1, Jan 8),
+    /// the series is continued instead, unless `copy_only`.
+    fn fill_selection(&mut self, down: bool, copy_only: bool) {
+        if self.reject_if_read_only() {
+            return;
+        }
+
+        let (min_row, min_col, max_row, max_col) = self.workbook.selection.bounds();
+        if (down && min_row == max_row) || (!down && min_col == max_col) {
+            self.status_message = Some("Select more than one row/column to fill".to_string());
+            return;
+        }
+        let filled = if down { Selection { start: (min_row + 1, min_col), end: (max_row, max_col) } }
+            else { Selection { start: (min_row, min_col + 1), end: (max_row, max_col) } };
+        if self.reject_if_locked(filled) {
+            return;
+        }
+
+        // Each line is a column when filling down and a row when filling right, as (col, row) cells
+        let (lines, along) = if down { (min_col..=max_col, min_row..=max_row) } else { (min_row..=max_row, min_col..=max_col) };
+        let cells_of = |line: u32| -> Vec<(u32, u32)> {
+            along.clone().map(|position| if down { (line, position) } else { (position, line) }).collect()
+        };
+
+        self.record_undo("fill", (filled.start.0..=filled.end.0)
+            .flat_map(|row| (filled.start.1..=filled.end.1).map(move |col| (col, row))));
+        let mut count = 0;
+        let mut series_count = 0;
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&self.workbook.current_sheet_index) {
+            for line in lines {
+                let cells = cells_of(line);
+                let series = if copy_only { None } else { detect_series(sheet, &cells) };
+                match series {
+                    Some(series) => {
+                        for (index, &coordinate) in cells.iter().enumerate().skip(series.seeds) {
+                            let cell = sheet.get_cell_mut(coordinate);
+                            cell.set_value_number(series.value(index));
+                            if let Some(code) = &series.number_format {
+                                cell.get_style_mut().get_number_format_mut().set_format_code(code.clone());
+                            }
+                            count += 1;
+                        }
+                        series_count += 1;
+                    }
+                    None => {
+                        let value = sheet.get_cell_value(cells[0]).get_value().to_string();
+                        for &coordinate in &cells[1..] {
+                            sheet.get_cell_mut(coordinate).set_value(value.clone());
+                            count += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        if count > 0 {
+            self.workbook.dirty = true;
+        } else {
+            self.workbook.undo.pop();
+        }
+        self.last_action = Some(LastAction::Fill { down, copy_only });
+        let direction = if down { "down" } else { "right" };
+        let mut message = format!("Filled {} cell(s) {}", count, direction);
+        if series_count > 0 {
+            let line = if down { "column" } else { "row" };
+            message.push_str(&format!(", continuing {} {}(s) as a series", series_count, line));
+        }
+        self.status_message = Some(message);
+    }
+
+    fn clear_selection_contents(&mut self) {
+        if self.reject_if_read_only() || self.reject_if_locked(self.workbook.selection) {
+            return;
+        }
+        let (min_row, min_col, max_row, max_col) = self.workbook.selection.bounds();
+
+        let mut count = 0;
+        for r in min_row..=max_row {
+            for c in min_col..=max_col {
+                if self.clear_cell(c, r) {
+                    count += 1;
+                }
+            }
+        }
+
+        if count > 0 {
+            self.workbook.dirty = true;
+        }
+        self.last_action = Some(LastAction::Clear);
+        self.status_message = Some(format!("Cleared {} cell(s)", count));
+    }
+
+    /// Empty a cell. An unformatted cell is removed from the sheet, so the used range can
+    /// shrink; any other keeps its whole style and loses only its value and formula.
+    /// Returns false when there was no cell.
+    fn clear_cell(&mut self, col: u32, row: u32) -> bool {
+        self.clear_cell_on(self.workbook.current_sheet_index, col, row)
+    }
+
+    /// `clear_cell` on any sheet
+    fn clear_cell_on(&mut self, sheet_idx: usize, col: u32, row: u32) -> bool {
+        let Some(cell) = self.workbook.spreadsheet.get_sheet(&sheet_idx)
+            .and_then(|sheet| sheet.get_cell((col, row)))
+        else {
+            return false;
+        };
+        // A formatted cell is recreated with only its style, so no empty string is stored
+        let style = (*cell.get_style() != Style::default()).then(|| cell.get_style().clone());
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&sheet_idx) {
+            sheet.remove_cell((col, row));
+            if let Some(style) = style {
+                sheet.get_cell_mut((col, row)).set_style(style);
+            }
+        }
+        true
+    }
+
+    /// Remember how `cells` ((col, row) on the current sheet) look now, so Ctrl+Z can put them back
+    fn record_undo(&mut self, label: &'static str, cells: impl IntoIterator<Item = (u32, u32)>) {
+        let sheet_idx = self.workbook.current_sheet_index;
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet(&sheet_idx) else {
+            return;
+        };
+        let cells = cells.into_iter().map(|coordinate| (coordinate, sheet.get_cell(coordinate).cloned())).collect();
+        self.workbook.undo.push(UndoStep { label, sheet: sheet_idx, cells });
+        if self.workbook.undo.len() > UNDO_STEPS {
+            self.workbook.undo.remove(0);
+        }
+    }
+
+    /// Put back the cells changed by the most recent fill or replace-all
+    fn undo(&mut self) {
+        if self.reject_if_read_only() {
+            return;
+        }
+        let Some(step) = self.workbook.undo.pop() else {
+            self.status_message = Some("Nothing to undo".to_string());
+            return;
+        };
+        self.switch_to_sheet(step.sheet);
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&step.sheet) {
+            for (coordinate, cell) in &step.cells {
+                sheet.remove_cell(*coordinate);
+                if let Some(cell) = cell {
+                    sheet.set_cell(cell.clone());
+                }
+            }
+        }
+        self.workbook.dirty = true;
+        self.status_message = Some(format!("Undid {} ({} cell(s))", step.label, step.cells.len()));
+    }
+
+    /// Put the current local date (or date and time) in the cursor cell as an Excel serial number
+    fn insert_now(&mut self, with_time: bool) {
+        if self.reject_if_read_only() || self.reject_if_locked(Selection::single(self.workbook.cursor.0, self.workbook.cursor.1)) {
+            return;
+        }
+        let now = chrono::Local::now().naive_local();
+        let serial = excel_serial(now);
+        let (value, code) = if with_time {
+            (serial, "yyyy-mm-dd hh:mm:ss")
+        } else {
+            (serial.floor(), "yyyy-mm-dd")
+        };
+
+        let (row, col) = self.workbook.cursor;
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&self.workbook.current_sheet_index) {
+            let cell = sheet.get_cell_mut((col, row));
+            cell.set_value_number(value);
+            cell.get_style_mut().get_number_format_mut().set_format_code(code);
+            self.workbook.dirty = true;
+        }
+        self.status_message = Some(format!("Inserted {}", self.get_cell_text(col, row)));
+    }
+
+    /// Cells and ranges referenced by the formula under the cursor (empty for other cells)
+    pub fn cursor_formula_references(&self) -> Vec<Selection> {
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) else {
+            return Vec::new();
+        };
+        formula_references(sheet.get_cell_value((self.workbook.cursor.1, self.workbook.cursor.0)).get_formula())
+    }
+
+    /// Check if a cell contains a formula
+    pub fn is_formula_cell(&self, col: u32, row: u32) -> bool {
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) {
+            let cell_value = sheet.get_cell_value((col, row));
+            !cell_value.get_formula().is_empty()
+        } else {
+            false
+        }
+    }
+
+    fn toggle_show_formulas(&mut self) {
+        self.show_formulas = !self.show_formulas;
+        self.status_message = Some(if self.show_formulas {
+            "Showing formulas".to_string()
+        } else {
+            "Showing values".to_string()
+        });
+    }
+
+    fn toggle_peek_formula(&mut self) {
+        self.peek_formula = !self.peek_formula;
+        self.status_message = Some(if self.peek_formula {
+            "Showing the formula of the cursor cell".to_string()
+        } else {
+            "Formula peek off".to_string()
+        });
+    }
+
+    /// Formula text of the cursor cell while it is being peeked at with ~ (None for other cells)
+    fn peeked_formula(&self, col: u32, row: u32) -> Option<String> {
+        if !self.peek_formula || (row, col) != self.workbook.cursor {
+            return None;
+        }
+        let formula = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)?
+            .get_cell((col, row))?
+            .get_formula();
+        (!formula.is_empty()).then(|| format!("={}", formula))
+    }
+
+    /// Width a column is drawn at: its own width, widened to fit a peeked formula in the cursor column
+    pub fn shown_column_width(&self, col: u32) -> u16 {
+        let width = self.get_column_width(col);
+        match self.peeked_formula(col, self.workbook.cursor.0) {
+            Some(formula) => width.max((formula.width() as u16).min(self.max_column_width)),
+            None => width,
+        }
+    }
+
+    fn toggle_r1c1(&mut self) {
+        self.r1c1 = !self.r1c1;
+        self.status_message = Some(if self.r1c1 {
+            "R1C1 references: columns are numbered".to_string()
+        } else {
+            "A1 references: columns are lettered".to_string()
+        });
+    }
+
+    /// Column header as shown: its letters, or its number in R1C1 mode
+    pub fn column_label(&self, col: u32) -> String {
+        if self.r1c1 { col.to_string() } else { number_to_column(col) }
+    }
+
+    /// Cell reference as shown: `C5`, or `R5C3` in R1C1 mode
+    pub fn cell_label(&self, row: u32, col: u32) -> String {
+        if self.r1c1 {
+            format!("R{}C{}", row, col)
+        } else {
+            format!("{}{}", number_to_column(col), row)
+        }
+    }
+
+    /// Parse a cell reference typed by the user, in the style `cell_label` shows
+    pub fn parse_user_ref(&self, reference: &str) -> Option<(u32, u32)> {
+        if self.r1c1 {
+            parse_r1c1_ref(reference)
+        } else {
+            parse_cell_ref(reference)
+        }
+    }
+
+    fn toggle_zebra(&mut self) {
+        self.zebra = !self.zebra;
+        self.status_message = Some(if self.zebra {
+            "Zebra shading on".to_string()
+        } else {
+            "Zebra shading off".to_string()
+        });
+    }
+
+    fn toggle_column_footer(&mut self) {
+        self.column_footer = !self.column_footer;
+        self.status_message = Some(if self.column_footer {
+            "Column totals on".to_string()
+        } else {
+            "Column totals off".to_string()
+        });
+    }
+
+    fn enter_command_mode(&mut self) {
+        self.textarea = TextArea::default();
+        self.mode = Mode::Command;
+    }
+
+    /// Run a `:` command line and return the message to show in the status bar
+    pub fn execute_command(&mut self, cmd: &str) -> Result<String> {
+        let cmd = cmd.trim().trim_start_matches(':').trim();
+        let (name, args) = cmd.split_once(char::is_whitespace).unwrap_or((cmd, ""));
+        let args = args.trim();
+
+        match name {
+            "w" => {
+                self.save_checked()?;
+                Ok(format!("Saved: {:?}", self.workbook.path))
+            }
+            "w!" => {
+                if self.read_only {
+                    anyhow::bail!("Read-only mode");
+                }
+                self.overwrite_file()?;
+                Ok(format!("Saved: {:?}", self.workbook.path))
+            }
+            "reload" | "reload!" => {
+                // A failed background save marks the workbook dirty again, so finish it before checking
+                self.wait_for_pending_save();
+                if self.workbook.dirty && name == "reload" {
+                    anyhow::bail!("Unsaved changes would be lost (add ! to override)");
+                }
+                self.reload_from_disk()?;
+                Ok(format!("Reloaded {:?}", self.workbook.path))
+            }
+            "wq" | "x" => {
+                self.save_checked()?;
+                if self.any_dirty() {
+                    anyhow::bail!("Another open workbook has unsaved changes (F7 to switch)");
+                }
+                self.should_quit = true;
+                Ok(String::new())
+            }
+            "q" => {
+                if self.any_dirty() {
+                    anyhow::bail!("No write since last change (add ! to override)");
+                }
+                self.should_quit = true;
+                Ok(String::new())
+            }
+            "q!" => {
+                self.should_quit = true;
+                Ok(String::new())
+            }
+            "sheet" => {
+                let index = self.find_sheet_index(args)
+                    .ok_or_else(|| anyhow::anyhow!("No such sheet: {}", args))?;
+                self.switch_to_sheet(index);
+                Ok(format!("Switched to sheet {}", self.get_sheet_names()[index]))
+            }
+            "goto" => {
+                let (row, col) = self.parse_user_ref(args)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid cell reference: {}", args))?;
+                self.workbook.cursor = (row, col);
+                self.workbook.selection = Selection::single(row, col);
+                self.adjust_scroll();
+                Ok(format!("Jumped to {}", args.to_uppercase()))
+            }
+            "trim" | "squeeze" | "upper" | "lower" => {
+                let transform = match name {
+                    "trim" => TextTransform::Trim,
+                    "squeeze" => TextTransform::Squeeze,
+                    "upper" => TextTransform::Upper,
+                    _ => TextTransform::Lower,
+                };
+                let whole_sheet = match args {
+                    "" => false,
+                    "all" => true,
+                    _ => anyhow::bail!("Usage: {} [all]", name),
+                };
+                if self.read_only {
+                    anyhow::bail!("Read-only mode");
+                }
+                let (count, skipped) = self.transform_text(transform, whole_sheet);
+                Ok(format!("Changed {} cell(s){}", count, locked_note(skipped)))
+            }
+            "markall" => {
+                let usage = || anyhow::anyhow!("Usage: markall <none|yellow|red|green|blue|magenta|#RRGGBB> <value>");
+                let (mark, query) = args.split_once(char::is_whitespace).ok_or_else(usage)?;
+                let mark = parse_mark(mark).ok_or_else(usage)?;
+                let query = query.trim();
+                // *text* matches cells containing the text, anything else the whole value
+                let contains = query.len() > 2 && query.starts_with('*') && query.ends_with('*');
+                let query = if contains { &query[1..query.len() - 1] } else { query };
+                if self.read_only {
+                    anyhow::bail!("Read-only mode");
+                }
+                let options = SearchOptions { whole_cell: !contains, ..SearchOptions::default() };
+                let count = self.mark_matching(query, options, mark);
+                Ok(format!("Marked {} cell(s) matching {:?}: {}", count, query, mark_name(mark)))
+            }
+            "marks" => {
+                let counts = self.mark_counts();
+                let sheet = self.get_sheet_names().get(self.workbook.current_sheet_index).cloned().unwrap_or_default();
+                if counts.is_empty() {
+                    return Ok(format!("No marked cells on {}", sheet));
+                }
+                let total: usize = counts.iter().map(|&(_, count)| count).sum();
+                let tally: Vec<String> = counts.iter()
+                    .map(|&(mark, count)| format!("{}: {}", mark_name(mark), count))
+                    .collect();
+                Ok(format!("Marks on {}: {} ({} cell(s))", sheet, tally.join(", "), total))
+            }
+            "dups" => {
+                // Optional "case" for case-sensitive text, and a mark other than yellow
+                let mut case_sensitive = false;
+                let mut mark = CellMark::YellowBg;
+                for word in args.split_whitespace() {
+                    match word {
+                        "case" => case_sensitive = true,
+                        _ => mark = parse_mark(word)
+                            .ok_or_else(|| anyhow::anyhow!("Usage: dups [case] [none|yellow|red|green|blue|magenta|#RRGGBB]"))?,
+                    }
+                }
+                if self.read_only {
+                    anyhow::bail!("Read-only mode");
+                }
+                let (groups, cells) = self.mark_duplicates(case_sensitive, mark);
+                let scope = if self.workbook.selection.is_single() { "sheet" } else { "selection" };
+                if groups == 0 {
+                    return Ok(format!("No duplicate values in the {}", scope));
+                }
+                Ok(format!("Found {} duplicated value(s) in {} cell(s) of the {}: {}", groups, cells, scope, mark_name(mark)))
+            }
+            "export" => {
+                let (format, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+                let rest = rest.trim();
+                match format {
+                    "csv" => {
+                        if rest.is_empty() {
+                            anyhow::bail!("Usage: export csv <path>");
+                        }
+                        let path = PathBuf::from(rest);
+                        self.export_csv(&path)?;
+                        Ok(format!("Exported csv to {:?}", path))
+                    }
+                    "md" => {
+                        // --letters heads the table with column letters instead of the first selected row
+                        let (letters, path) = match rest.strip_prefix("--letters") {
+                            Some(path) => (true, path.trim()),
+                            None => (false, rest),
+                        };
+                        let table = self.selection_markdown(letters);
+                        if path.is_empty() {
+                            crate::osc52::copy(&table)?;
+                            return Ok("Copied selection as a Markdown table".to_string());
+                        }
+                        let path = PathBuf::from(path);
+                        std::fs::write(&path, table).map_err(|e| anyhow::anyhow!("Failed to write {:?}: {}", path, e))?;
+                        Ok(format!("Exported md to {:?}", path))
+                    }
+                    "json" => {
+                        // --objects keys each row by the first row's values instead of emitting arrays
+                        let (objects, path) = match rest.strip_prefix("--objects") {
+                            Some(path) => (true, path.trim()),
+                            None => (false, rest),
+                        };
+                        if path.is_empty() {
+                            anyhow::bail!("Usage: export json [--objects] <path>");
+                        }
+                        let path = PathBuf::from(path);
+                        self.export_json(&path, objects)?;
+                        Ok(format!("Exported json to {:?}", path))
+                    }
+                    "" => anyhow::bail!("Usage: export <csv|md|json> [path]"),
+                    _ => anyhow::bail!("Unknown export format: {}", format),
+                }
+            }
+            _ => anyhow::bail!("Unknown command: {}", cmd),
+        }
+    }
+
+    /// Rewrite text in the selection (or every cell of the sheet), skipping formulas.
+    /// Returns how many cells actually changed, and how many locked cells were left alone.
+    fn transform_text(&mut self, transform: TextTransform, whole_sheet: bool) -> (usize, usize) {
+        let protect_locked = self.protect_locked;
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&self.workbook.current_sheet_index) else {
+            return (0, 0);
+        };
+        let mut skipped = 0;
+        let changes: Vec<((u32, u32), String)> = sheet.get_cell_collection().into_iter()
+            .filter(|cell| cell.get_formula().is_empty())
+            .filter_map(|cell| {
+                let row = *cell.get_coordinate().get_row_num();
+                let col = *cell.get_coordinate().get_col_num();
+                if !whole_sheet && !self.workbook.selection.contains(row, col) {
+                    return None;
+                }
+                let value = cell.get_value();
+                let new_value = transform.apply(&value);
+                if new_value == value {
+                    return None;
+                }
+                if protect_locked && cell_is_locked(cell) {
+                    skipped += 1;
+                    return None;
+                }
+                Some(((col, row), new_value))
+            })
+            .collect();
+
+        for (coordinate, value) in &changes {
+            sheet.get_cell_mut(*coordinate).set_value(value.clone());
+        }
+        if !changes.is_empty() {
+            self.workbook.dirty = true;
+        }
+        (changes.len(), skipped)
+    }
+
+    fn save_checked(&mut self) -> Result<()> {
+        if self.read_only {
+            anyhow::bail!("Read-only mode");
+        }
+        self.save_file()
+    }
+
+    /// Look up a sheet by exact name, falling back to a case-insensitive match
+    fn find_sheet_index(&self, name: &str) -> Option<usize> {
+        let names = self.get_sheet_names();
+        names.iter().position(|n| n == name)
+            .or_else(|| names.iter().position(|n| n.eq_ignore_ascii_case(name)))
+    }
+
+    /// Write the used range of the current sheet as CSV
+    fn export_csv(&self, path: &Path) -> Result<()> {
+        let sheet = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)
+            .ok_or_else(|| anyhow::anyhow!("No active sheet"))?;
+
+        let mut out = String::new();
+        for r in 1..=sheet.get_highest_row() {
+            let fields: Vec<String> = (1..=sheet.get_highest_column())
+                .map(|c| csv_escape(&sheet.get_cell_value((c, r)).get_value()))
+                .collect();
+            out.push_str(&fields.join(","));
+            out.push('\n');
+        }
+
+        std::fs::write(path, out).map_err(|e| anyhow::anyhow!("Failed to write {:?}: {}", path, e))
+    }
+
+    /// Write the selection (or the sheet's used range when a single cell is selected) as JSON:
+    /// an array of row arrays, or with `objects` an array of objects keyed by the first row
+    fn export_json(&self, path: &Path, objects: bool) -> Result<()> {
+        let sheet = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)
+            .ok_or_else(|| anyhow::anyhow!("No active sheet"))?;
+        let (min_row, min_col, max_row, max_col) = if self.workbook.selection.is_single() {
+            (1, 1, sheet.get_highest_row(), sheet.get_highest_column())
+        } else {
+            self.workbook.selection.bounds()
+        };
+
+        let rows: Vec<Vec<serde_json::Value>> = (min_row..=max_row)
+            .map(|r| (min_col..=max_col).map(|c| self.json_value(c, r)).collect())
+            .collect();
+
+        let json = if objects {
+            let mut rows = rows.into_iter();
+            let header: Vec<String> = rows.next().unwrap_or_default().into_iter()
+                .zip(min_col..)
+                .map(|(value, c)| match value {
+                    serde_json::Value::String(text) if !text.is_empty() => text,
+                    serde_json::Value::Null => number_to_column(c),
+                    other => other.to_string(),
+                })
+                .collect();
+            let header = unique_keys(header);
+            let records: Vec<serde_json::Value> = rows
+                .map(|row| header.iter().cloned().zip(row).collect::<serde_json::Map<_, _>>().into())
+                .collect();
+            serde_json::Value::Array(records)
+        } else {
+            serde_json::json!(rows)
+        };
+
+        let text = serde_json::to_string_pretty(&json)?;
+        std::fs::write(path, text).map_err(|e| anyhow::anyhow!("Failed to write {:?}: {}", path, e))
+    }
+
+    /// A cell as JSON: numbers and booleans keep their type, empty cells are null,
+    /// and everything else (dates included) is the text shown in the grid
+    fn json_value(&self, col: u32, row: u32) -> serde_json::Value {
+        match self.cell_type(col, row) {
+            CellType::Empty => serde_json::Value::Null,
+            CellType::Boolean => {
+                let value = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)
+                    .map(|sheet| sheet.get_cell_value((col, row)).get_value().to_uppercase())
+                    .unwrap_or_default();
+                serde_json::Value::Bool(value == "TRUE" || value == "1")
+            }
+            CellType::Number | CellType::Formula => {
+                let raw = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)
+                    .map(|sheet| sheet.get_cell_value((col, row)).get_value().to_string())
+                    .unwrap_or_default();
+                parse_number(&raw)
+                    .and_then(|n| {
+                        // Whole numbers are written without a trailing .0
+                        if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+                            Some(serde_json::Number::from(n as i64))
+                        } else {
+                            serde_json::Number::from_f64(n)
+                        }
+                    })
+                    .map(serde_json::Value::Number)
+                    .unwrap_or_else(|| serde_json::Value::String(self.get_cell_text(col, row)))
+            }
+            _ => serde_json::Value::String(self.get_cell_text(col, row)),
+        }
+    }
+
+    /// The selection as a GitHub-flavored Markdown table, headed by its first row
+    /// or, with `letters`, by the column letters
+    fn selection_markdown(&self, letters: bool) -> String {
+        let (min_row, min_col, max_row, max_col) = self.workbook.selection.bounds();
+        let row_cells = |r: u32| -> Vec<String> {
+            (min_col..=max_col).map(|c| markdown_escape(&self.get_cell_text(c, r))).collect()
+        };
+
+        let (header, first_body_row) = if letters {
+            ((min_col..=max_col).map(number_to_column).collect(), min_row)
+        } else {
+            (row_cells(min_row), min_row + 1)
+        };
+
+        let mut out = format!("| {} |\n", header.join(" | "));
+        out.push_str(&format!("|{}\n", " --- |".repeat(header.len())));
+        for r in first_body_row..=max_row {
+            out.push_str(&format!("| {} |\n", row_cells(r).join(" | ")));
+        }
+        out
+    }
+
+    fn enter_replace_mode(&mut self) {
+        if self.reject_if_read_only() {
+            return;
+        }
+        // A multi-cell selection limits the search; otherwise the whole sheet is searched
+        self.replace.scope = (!self.workbook.selection.is_single()).then_some(self.workbook.selection);
+        self.replace.field = ReplaceField::Find;
+        self.textarea = TextArea::from(vec![self.replace.query.clone()]);
+        self.textarea.move_cursor(CursorMove::End);
+        self.mode = Mode::Replace;
+    }
+
+    /// Store the textarea contents into the field being edited
+    fn sync_replace_field(&mut self) {
+        let text = self.textarea.lines().join("");
+        match self.replace.field {
+            ReplaceField::Find => self.replace.query = text,
+            ReplaceField::Replace => self.replace.replacement = text,
+        }
+    }
+
+    fn switch_replace_field(&mut self) {
+        self.sync_replace_field();
+        let (field, text) = match self.replace.field {
+            ReplaceField::Find => (ReplaceField::Replace, self.replace.replacement.clone()),
+            ReplaceField::Replace => (ReplaceField::Find, self.replace.query.clone()),
+        };
+        self.replace.field = field;
+        self.textarea = TextArea::from(vec![text]);
+        self.textarea.move_cursor(CursorMove::End);
+    }
+
+    /// Non-formula cells in the search scope whose value matches, in row-major order
+    fn replace_candidates(&self) -> Vec<(u32, u32)> {
+        let mut found = Vec::new();
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) {
+            for cell in sheet.get_cell_collection() {
+                let row = *cell.get_coordinate().get_row_num();
+                let col = *cell.get_coordinate().get_col_num();
+                if self.replace.scope.is_some_and(|scope| !scope.contains(row, col)) {
+                    continue;
+                }
+                if !cell.get_formula().is_empty() {
+                    continue;
+                }
+                if self.replace.options.matches(&cell.get_value(), &self.replace.query) {
+                    found.push((row, col));
+                }
+            }
+        }
+        found.sort();
+        found
+    }
+
+    /// Cells with matches, split into those that may be changed and the number that are locked
+    /// while protection is on
+    fn replace_targets(&self) -> (Vec<(u32, u32)>, usize) {
+        let candidates = self.replace_candidates();
+        let total = candidates.len();
+        let open: Vec<(u32, u32)> = candidates.into_iter()
+            .filter(|&(row, col)| !(self.protect_locked && self.is_cell_locked(col, row)))
+            .collect();
+        let locked = total - open.len();
+        (open, locked)
+    }
+
+    /// Replace matches in a single cell, returning the number of replacements.
+    /// Locked cells are left alone while protection is on.
+    fn replace_in_cell(&mut self, row: u32, col: u32) -> usize {
+        if self.protect_locked && self.is_cell_locked(col, row) {
+            return 0;
+        }
+        let options = self.replace.options;
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&self.workbook.current_sheet_index) else {
+            return 0;
+        };
+        let value = sheet.get_cell_value((col, row)).get_value().to_string();
+        let (new_value, count) = options.replace(&value, &self.replace.query, &self.replace.replacement);
+        if count > 0 {
+            sheet.get_cell_mut((col, row)).set_value(new_value);
+            self.workbook.dirty = true;
+        }
+        count
+    }
+
+    fn replace_next(&mut self) {
+        self.sync_replace_field();
+        if self.replace.query.is_empty() {
+            self.status_message = Some("Nothing to find".to_string());
+            return;
+        }
+
+        let (candidates, locked) = self.replace_targets();
+        // First match after the cursor, wrapping around to the start
+        let Some(&(row, col)) = candidates.iter()
+            .find(|&&pos| pos > self.workbook.cursor)
+            .or_else(|| candidates.first())
+        else {
+            self.status_message = Some(if locked > 0 {
+                format!("Matches only in locked cells (L turns protection off): {}", self.replace.query)
+            } else {
+                format!("Not found: {}", self.replace.query)
+            });
+            return;
+        };
+
+        let count = self.replace_in_cell(row, col);
+        self.workbook.cursor = (row, col);
+        self.workbook.selection = Selection::single(row, col);
+        self.adjust_scroll();
+        self.status_message = Some(format!("Replaced {} occurrence(s) in {}{}{}", count, number_to_column(col), row, locked_note(locked)));
+    }
+
+    fn replace_all(&mut self) {
+        self.sync_replace_field();
+        if self.replace.query.is_empty() {
+            self.status_message = Some("Nothing to find".to_string());
+            return;
+        }
+
+        let (candidates, locked) = self.replace_targets();
+        // One undo step covers the whole replace-all
+        self.record_undo("replace all", candidates.iter().map(|&(row, col)| (col, row)));
+        let total: usize = candidates.iter().map(|&(row, col)| self.replace_in_cell(row, col)).sum();
+        if total == 0 {
+            self.workbook.undo.pop();
+        }
+        self.status_message = Some(format!("Replaced {} occurrence(s) in {} cell(s){}", total, candidates.len(), locked_note(locked)));
+        self.mode = Mode::View;
+    }
+
+    fn enter_format_mode(&mut self) {
+        if self.reject_if_read_only() {
+            return;
+        }
+        self.mode = Mode::Format;
+    }
+
+    fn apply_number_format(&mut self, label: &str, code: &str) {
+        let (min_row, min_col, max_row, max_col) = self.workbook.selection.bounds();
+
+        let mut count = 0;
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&self.workbook.current_sheet_index) {
+            for r in min_row..=max_row {
+                for c in min_col..=max_col {
+                    sheet.get_cell_mut((c, r)).get_style_mut().get_number_format_mut().set_format_code(code);
+                    count += 1;
+                }
+            }
+        }
+
+        self.workbook.dirty = true;
+        self.last_action = Some(LastAction::NumberFormat { label: label.to_string(), code: code.to_string() });
+        self.status_message = Some(format!("Applied format {} to {} cell(s)", label, count));
+    }
+
+    /// Add (`delta > 0`) or remove one displayed decimal place on every selected cell
+    fn adjust_decimals(&mut self, delta: i32) {
+        if self.reject_if_read_only() {
+            return;
+        }
+        let (min_row, min_col, max_row, max_col) = self.workbook.selection.bounds();
+
+        let mut last_code = None;
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&self.workbook.current_sheet_index) {
+            for r in min_row..=max_row {
+                for c in min_col..=max_col {
+                    let current = sheet.get_cell((c, r))
+                        .and_then(|cell| cell.get_style().get_number_format())
+                        .map(|num_fmt| num_fmt.get_format_code().to_string())
+                        .unwrap_or_else(|| NumberingFormat::FORMAT_GENERAL.to_string());
+                    if Self::is_date_format(&current) {
+                        continue;
+                    }
+                    let code = step_decimals(&current, delta);
+                    if code == current {
+                        continue;
+                    }
+                    sheet.get_cell_mut((c, r)).get_style_mut().get_number_format_mut().set_format_code(code.clone());
+                    last_code = Some(code);
+                }
+            }
+        }
+
+        self.last_action = Some(LastAction::Decimals(delta));
+        match last_code {
+            Some(code) => {
+                self.workbook.dirty = true;
+                self.status_message = Some(format!("Number format: {}", code));
+            }
+            None => self.status_message = Some("Number format unchanged".to_string()),
+        }
+    }
+
+    /// Check if a format code represents a date/time format
+    fn is_date_format(format_code: &str) -> bool {
+        // Skip general and text formats
+        if format_code == NumberingFormat::FORMAT_GENERAL || format_code == NumberingFormat::FORMAT_TEXT {
+            return false;
+        }
+        // Check for date/time indicators in format code
+        let lower = format_code.to_lowercase();
+        lower.contains('y') || lower.contains('m') || lower.contains('d')
+            || lower.contains('h') || lower.contains("am") || lower.contains("pm")
+    }
+
+    /// Format code used to show a date/time cell, following the --date-format setting
+    fn normalize_date_format<'f>(&self, format_code: &'f str) -> &'f str {
+        let date = match self.date_display {
+            DateDisplay::Preserve => return format_code,
+            DateDisplay::Iso => "yyyy-mm-dd",
+            DateDisplay::Us => "mm/dd/yyyy",
+            DateDisplay::Eu => "dd/mm/yyyy",
+        };
+        let lower = format_code.to_lowercase();
+        // Check if it includes time
+        if lower.contains('h') || lower.contains("am") || lower.contains("pm") {
+            // Date + Time
+            if lower.contains('y') || lower.contains('d') {
+                match self.date_display {
+                    DateDisplay::Us => "mm/dd/yyyy hh:mm:ss",
+                    DateDisplay::Eu => "dd/mm/yyyy hh:mm:ss",
+                    _ => "yyyy-mm-dd hh:mm:ss",
+                }
+            } else {
+                // Time only
+                "hh:mm:ss"
+            }
+        } else {
+            // Date only
+            date
+        }
+    }
+
+    /// Get the full formatted cell value as it would be displayed, without truncation
+    pub fn get_cell_text(&self, col: u32, row: u32) -> String {
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) {
+            let cell_value = sheet.get_cell_value((col, row));
+            let formula = cell_value.get_formula();
+
+            // For formula cells, show the formula text or the cached result
+            if !formula.is_empty() && self.show_formulas {
+                format!("={}", formula)
+            } else if !formula.is_empty() {
+                // Show calculated result (cached by Excel)
+                let result = cell_value.get_value().to_string();
+                if result.is_empty() {
+                    "=...".to_string()  // Formula with no cached result
+                } else {
+                    result
+                }
+            } else {
+                // Check for number format (date/time formatting)
+                let raw_value = cell_value.get_value().to_string();
+
+                // Try to get cell style and format
+                if let Some(cell) = sheet.get_cell((col, row)) {
+                    if let Some(num_fmt) = cell.get_style().get_number_format() {
+                        let format_code = num_fmt.get_format_code();
+                        if Self::is_date_format(format_code) {
+                            // Dates are shown as ISO (yyyy-mm-dd) unless --date-format says otherwise
+                            let date_format = self.normalize_date_format(format_code);
+                            to_formatted_string(&raw_value, date_format)
+                        } else if format_code != NumberingFormat::FORMAT_GENERAL {
+                            // Apply other number formatting
+                            to_formatted_string(&raw_value, format_code)
+                        } else {
+                            raw_value
+                        }
+                    } else {
+                        raw_value
+                    }
+                } else {
+                    raw_value
+                }
+            }
+        } else {
+            String::new()
+        }
+    }
+
+    /// Full content of the cursor cell: the formula for formula cells, otherwise the formatted value
+    pub fn current_cell_full_value(&self) -> String {
+        let (row, col) = self.workbook.cursor;
+        if let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) {
+            let formula = sheet.get_cell_value((col, row)).get_formula();
+            if !formula.is_empty() {
+                return format!("={}", formula);
+            }
+        }
+        self.get_cell_text(col, row)
+    }
+
+    /// Infer the type of a cell from its stored data type and number format
+    pub fn cell_type(&self, col: u32, row: u32) -> CellType {
+        let Some(cell) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)
+            .and_then(|sheet| sheet.get_cell((col, row)))
+        else {
+            return CellType::Empty;
+        };
+        if cell_is_blank(cell) {
+            return CellType::Empty;
+        }
+        if !cell.get_formula().is_empty() {
+            return CellType::Formula;
+        }
+        match cell.get_data_type() {
+            "b" => CellType::Boolean,
+            "e" => CellType::Error,
+            "n" => {
+                let is_date = cell.get_style().get_number_format()
+                    .is_some_and(|format| Self::is_date_format(format.get_format_code()));
+                if is_date { CellType::Date } else { CellType::Number }
+            }
+            _ => CellType::Text,
+        }
+    }
+
+    /// Numbers, dates and numeric formula results are right-aligned, as in Excel
+    fn is_right_aligned(&self, col: u32, row: u32) -> bool {
+        match self.cell_type(col, row) {
+            CellType::Number | CellType::Date => true,
+            CellType::Formula => !self.show_formulas && self.get_cell_text(col, row).trim().parse::<f64>().is_ok(),
+            _ => false,
+        }
+    }
+
+    /// Get cell value, truncated to fit column width with ellipsis.
+    /// With a row height above 1 the text wraps onto that many lines instead.
+    /// Numeric content is right-aligned to the column width; text stays left-aligned.
+    pub fn get_cell_display(&self, col: u32, row: u32) -> CellDisplay {
+        let width = self.shown_column_width(col) as usize;
+        let peeked = self.peeked_formula(col, row);
+        let right_aligned = peeked.is_none() && self.is_right_aligned(col, row);
+        let color = self.number_format_color(col, row);
+        let cell_text = peeked.unwrap_or_else(|| self.get_cell_text(col, row));
+        if self.row_display_height > 1 {
+            let (lines, truncated) = wrap_to_width(&cell_text, width, self.row_display_height as usize);
+            let text = if right_aligned {
+                lines.iter().map(|line| align_right(line, width)).collect::<Vec<_>>().join("\n")
+            } else {
+                lines.join("\n")
+            };
+            return CellDisplay { text, truncated, color };
+        }
+        let text = mark_newlines(&cell_text);
+        let truncated = text.width() > width;
+        let display_value = truncate_to_width(&text, width);
+        let text = if right_aligned { align_right(&display_value, width) } else { display_value };
+        CellDisplay { text, truncated, color }
+    }
+
+    /// Text color a numeric cell's number format gives its value, e.g. red for negatives
+    /// under `#,##0;[Red]-#,##0`
+    pub fn number_format_color(&self, col: u32, row: u32) -> Option<u32> {
+        let cell = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)?.get_cell((col, row))?;
+        if cell.get_data_type() != "n" || !cell.get_formula().is_empty() {
+            return None;
+        }
+        let value = cell.get_value().parse::<f64>().ok()?;
+        let code = cell.get_style().get_number_format()?.get_format_code();
+        format_color(code, value)
+    }
+
+    fn enter_color_pick_mode(&mut self) {
+        if self.reject_if_read_only() {
+            return;
+        }
+        self.mode = Mode::ColorPick;
+    }
+
+    fn set_mark_for_selection(&mut self, mark: CellMark) {
+        if self.reject_if_read_only() {
+            return;
+        }
+        let (min_row, min_col, max_row, max_col) = self.workbook.selection.bounds();
+        let cells: Vec<(u32, u32)> = (min_row..=max_row)
+            .flat_map(|r| (min_col..=max_col).map(move |c| (r, c)))
+            .collect();
+        let count = self.mark_cells(&cells, mark);
+
+        self.last_action = Some(LastAction::Mark(mark));
+        self.status_message = Some(format!("Marked {} cell(s): {}", count, mark_name(mark)));
+    }
+
+    /// Mark every cell of the sheet whose value matches `query`, returning how many were marked
+    fn mark_matching(&mut self, query: &str, options: SearchOptions, mark: CellMark) -> usize {
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) else {
+            return 0;
+        };
+        let cells: Vec<(u32, u32)> = sheet.get_cell_collection().iter()
+            .filter(|cell| options.matches(&cell.get_value(), query))
+            .map(|cell| (*cell.get_coordinate().get_row_num(), *cell.get_coordinate().get_col_num()))
+            .collect();
+        self.mark_cells(&cells, mark)
+    }
+
+    /// How many cells of the current sheet carry each mark: the preset marks in key order, then custom colors
+    pub fn mark_counts(&self) -> Vec<(CellMark, usize)> {
+        let mut counts: Vec<(CellMark, usize)> = Vec::new();
+        for (&(sheet, _, _), &mark) in &self.workbook.cell_marks {
+            if sheet != self.workbook.current_sheet_index || mark == CellMark::None {
+                continue;
+            }
+            match counts.iter_mut().find(|(counted, _)| *counted == mark) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((mark, 1)),
+            }
+        }
+        counts.sort_by_key(|&(mark, _)| match mark {
+            CellMark::Custom { argb, is_bg } => (1, argb, is_bg),
+            preset => (0, preset_mark_key(preset), false),
+        });
+        counts
+    }
+
+    /// Mark the cells of the selection (or the whole sheet when one cell is selected) whose value
+    /// appears more than once there. Numbers compare by value, so 1 and 1.0 match; text compares
+    /// case-insensitively unless `case_sensitive`. Returns (duplicated values, cells marked).
+    fn mark_duplicates(&mut self, case_sensitive: bool, mark: CellMark) -> (usize, usize) {
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index) else {
+            return (0, 0);
+        };
+        let whole_sheet = self.workbook.selection.is_single();
+        let mut groups: HashMap<DuplicateKey, Vec<(u32, u32)>> = HashMap::new();
+        for cell in sheet.get_cell_collection() {
+            let row = *cell.get_coordinate().get_row_num();
+            let col = *cell.get_coordinate().get_col_num();
+            if cell_is_blank(cell) || !(whole_sheet || self.workbook.selection.contains(row, col)) {
+                continue;
+            }
+            let value = cell.get_value();
+            let key = match value.trim().parse::<f64>() {
+                // -0.0 + 0.0 is 0.0, so both zeros share a key
+                Ok(number) if cell.get_data_type() == "n" => DuplicateKey::Number((number + 0.0).to_bits()),
+                _ if case_sensitive => DuplicateKey::Text(value.to_string()),
+                _ => DuplicateKey::Text(value.to_lowercase()),
+            };
+            groups.entry(key).or_default().push((row, col));
+        }
+        let duplicates: Vec<Vec<(u32, u32)>> = groups.into_values().filter(|cells| cells.len() > 1).collect();
+        let cells: Vec<(u32, u32)> = duplicates.iter().flatten().copied().collect();
+        self.mark_cells(&cells, mark);
+        (duplicates.len(), cells.len())
+    }
+
+    /// Record a mark for (row, col) cells and write it into their Excel styles
+    fn mark_cells(&mut self, cells: &[(u32, u32)], mark: CellMark) -> usize {
+        let sheet_idx = self.workbook.current_sheet_index;
+        let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&sheet_idx) else {
+            return 0;
+        };
+        let mut count = 0;
+        for &(r, c) in cells {
+            let key = (sheet_idx, r, c);
+            if mark == CellMark::None {
+                // Clearing only touches marked cells, so other cells keep the
+                // fill and font color they were loaded with
+                if self.workbook.cell_marks.remove(&key).is_none() {
+                    continue;
+                }
+            } else {
+                self.workbook.cell_marks.insert(key, mark);
+            }
+
+            // Apply style to Excel cell
+            let cell = sheet.get_cell_mut((c, r));
+            Self::apply_mark_style(cell.get_style_mut(), mark);
+            count += 1;
+        }
+
+        if count > 0 {
+            self.workbook.dirty = true;
+        }
+        count
+    }
+
+    /// Write the fill/font colors for a mark into an Excel cell style
+    fn apply_mark_style(style: &mut Style, mark: CellMark) {
+        match mark {
+            CellMark::None => {
+                // Clear styles - reset to default
+                style.get_font_mut().set_color(excel_color(BLACK));
+                style.get_fill_mut().get_pattern_fill_mut().set_pattern_type(PatternValues::None);
+            }
+            CellMark::YellowBg => {
+                style.get_fill_mut().get_pattern_fill_mut()
+                    .set_foreground_color(excel_color(MARK_YELLOW))
+                    .set_pattern_type(PatternValues::Solid);
+                style.get_font_mut().set_color(excel_color(BLACK));
+            }
+            CellMark::RedText => {
+                style.get_font_mut().set_color(excel_color(MARK_RED));
+            }
+            CellMark::GreenText => {
+                style.get_font_mut().set_color(excel_color(MARK_GREEN));
+            }
+            CellMark::BlueBg => {
+                style.get_fill_mut().get_pattern_fill_mut()
+                    .set_foreground_color(excel_color(MARK_BLUE))
+                    .set_pattern_type(PatternValues::Solid);
+                style.get_font_mut().set_color(excel_color(WHITE));
+            }
+            CellMark::MagentaText => {
+                style.get_font_mut().set_color(excel_color(MARK_MAGENTA));
+            }
+            CellMark::Custom { argb, is_bg: true } => {
+                style.get_fill_mut().get_pattern_fill_mut()
+                    .set_foreground_color(excel_color(argb))
+                    .set_pattern_type(PatternValues::Solid);
+            }
+            CellMark::Custom { argb, is_bg: false } => {
+                style.get_font_mut().set_color(excel_color(argb));
+            }
+        }
+    }
+
+    pub fn cell_emphasis(&self, col: u32, row: u32) -> FontEmphasis {
+        self.workbook.spreadsheet.get_sheet(&self.workbook.current_sheet_index)
+            .and_then(|sheet| sheet.get_cell((col, row)))
+            .map(|cell| style_emphasis(cell.get_style()))
+            .unwrap_or_default()
+    }
+
+    /// Toggle emphasis on the whole selection; the cursor cell decides whether it is added or removed
+    fn toggle_emphasis(&mut self, emphasis: Emphasis) {
+        if self.reject_if_read_only() {
+            return;
+        }
+        let enable = !self.cell_emphasis(self.workbook.cursor.1, self.workbook.cursor.0).has(emphasis);
+        let (min_row, min_col, max_row, max_col) = self.workbook.selection.bounds();
+
+        let mut count = 0;
+        for r in min_row..=max_row {
+            for c in min_col..=max_col {
+                // Turning emphasis off must not give unstyled cells a font of their own
+                if !enable && !self.cell_emphasis(c, r).has(emphasis) {
+                    continue;
+                }
+                if let Some(sheet) = self.workbook.spreadsheet.get_sheet_mut(&self.workbook.current_sheet_index) {
+                    let font = sheet.get_cell_mut((c, r)).get_style_mut().get_font_mut();
+                    match emphasis {
+                        Emphasis::Bold => { font.set_bold(enable); }
+                        Emphasis::Italic => { font.set_italic(enable); }
+                        Emphasis::Underline => { font.set_underline(if enable { "single" } else { "none" }); }
+                    }
+                    count += 1;
+                }
+            }
+        }
+
+        if count > 0 {
+            self.workbook.dirty = true;
+        }
+        let name = match emphasis {
+            Emphasis::Bold => "Bold",
+            Emphasis::Italic => "Italic",
+            Emphasis::Underline => "Underline",
+        };
+        self.last_action = Some(LastAction::Emphasis(emphasis));
+        let state = if enable { "on" } else { "off" };
+        self.status_message = Some(format!("{} {} for {} cell(s)", name, state, count));
+    }
+
+    pub fn get_cell_mark(&self, row: u32, col: u32) -> CellMark {
+        let key = (self.workbook.current_sheet_index, row, col);
+        self.workbook.cell_marks.get(&key).copied().unwrap_or(CellMark::None)
+    }
+
+    fn enter_sheet_select_mode(&mut self) {
+        self.sheet_select_index = self.workbook.current_sheet_index;
+        self.sheet_filter.clear();
+        self.mode = Mode::SheetSelect;
+    }
+
+    fn sheet_select_move(&mut self, delta: i32) {
+        if let Some(index) = self.step_listed_sheet(self.sheet_select_index, delta) {
+            self.sheet_select_index = index;
+        }
+    }
+
+    /// Move the selector's highlight `delta` entries through the listed sheets, stopping at either end
+    fn sheet_select_jump(&mut self, delta: i32) {
+        let listed = self.listed_sheets();
+        let Some(position) = listed.iter().position(|&i| i == self.sheet_select_index) else {
+            return;
+        };
+        let target = (position as i32).saturating_add(delta).clamp(0, listed.len() as i32 - 1);
+        self.sheet_select_index = listed[target as usize];
+    }
+
+    /// First sheet whose name starts with the type-ahead filter, falling back to one that contains it
+    pub fn filtered_sheet_index(&self) -> Option<usize> {
+        if self.sheet_filter.is_empty() {
+            return None;
+        }
+        let filter = self.sheet_filter.to_lowercase();
+        let names = self.get_sheet_names();
+        let listed: Vec<(usize, String)> = self.listed_sheets().into_iter()
+            .map(|i| (i, names[i].to_lowercase()))
+            .collect();
+        listed.iter().find(|(_, name)| name.starts_with(&filter))
+            .or_else(|| listed.iter().find(|(_, name)| name.contains(&filter)))
+            .map(|&(i, _)| i)
+    }
+
+    fn jump_to_filtered_sheet(&mut self) {
+        if let Some(index) = self.filtered_sheet_index() {
+            self.sheet_select_index = index;
+        }
+    }
+
+    fn confirm_sheet_selection(&mut self) {
+        self.switch_to_sheet(self.sheet_select_index);
+        self.mode = Mode::View;
+    }
+
+    pub fn get_sheet_names(&self) -> Vec<String> {
+        self.workbook.spreadsheet.get_sheet_collection()
+            .iter()
+            .map(|s| s.get_name().to_string())
+            .collect()
+    }
+}
+
+/// Truncate text to fit in `width` terminal columns, marking the cut with `~`.
+/// Measures display width so wide (CJK, emoji) characters are never split.
+pub fn truncate_to_width(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+
+    let budget = width.saturating_sub(1); // leave room for the ellipsis
+    let mut truncated = String::new();
+    let mut used = 0;
+    for grapheme in text.graphemes(true) {
+        let grapheme_width = grapheme.width();
+        if used + grapheme_width > budget {
+            break;
+        }
+        truncated.push_str(grapheme);
+        used += grapheme_width;
+    }
+    truncated.push('~');
+    truncated
+}
+
+/// Pad text on the left so it ends at the right edge of `width` terminal columns
+pub fn align_right(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(text.width());
+    format!("{}{}", " ".repeat(padding), text)
+}
+
+/// Wrap text onto at most `max_lines` lines of `width` terminal columns,
+/// honouring embedded line breaks. A trailing `~` marks hidden overflow, which is also
+/// reported by the returned flag.
+pub fn wrap_to_width(text: &str, width: usize, max_lines: usize) -> (Vec<String>, bool) {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        let mut used = 0;
+        for grapheme in paragraph.trim_end_matches('\r').graphemes(true) {
+            let grapheme_width = grapheme.width();
+            if used + grapheme_width > width && !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+                used = 0;
+            }
+            line.push_str(grapheme);
+            used += grapheme_width;
+        }
+        lines.push(line);
+    }
+
+    let truncated = lines.len() > max_lines;
+    if truncated {
+        lines.truncate(max_lines);
+        if let Some(last) = lines.last_mut() {
+            *last = truncate_to_width(&format!("{}~", last), width);
+        }
+    }
+    (lines, truncated)
+}
+
+/// Replace line breaks with a visible marker so multi-line cells fit on one grid line
+pub fn mark_newlines(text: &str) -> String {
+    text.replace("\r\n", NEWLINE_MARKER).replace('\n', NEWLINE_MARKER)
+}
+
+/// Convert a 1-based column number to its letters (1 -> A, 27 -> AA)
+pub fn number_to_column(n: u32) -> String {
+    let mut n = n;
+    let mut result = String::new();
+    while n > 0 {
+        n -= 1;
+        let remainder = n % 26;
+        let char_code = (remainder as u8) + b'A';
+        result.insert(0, char_code as char);
+        n /= 26;
+    }
+    result
+}
+
+/// Parse an A1-style reference, case-insensitive, into (row, col)
+pub fn parse_cell_ref(reference: &str) -> Option<(u32, u32)> {
+    let reference = reference.trim();
+    let split = reference.find(|c: char| c.is_ascii_digit())?;
+    let (letters, digits) = reference.split_at(split);
+    if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let mut col: u32 = 0;
+    for c in letters.chars() {
+        col = col.checked_mul(26)?.checked_add((c.to_ascii_uppercase() as u8 - b'A' + 1) as u32)?;
+    }
+    let row: u32 = digits.parse().ok()?;
+
+    if row == 0 || row > MAX_ROWS || col > MAX_COLUMNS {
+        return None;
+    }
+    Some((row, col))
+}
+
+/// Parse an R1C1-style reference (e.g. "R5C3"), case-insensitive, to (row, col)
+pub fn parse_r1c1_ref(reference: &str) -> Option<(u32, u32)> {
+    let rest = reference.trim().strip_prefix(['R', 'r'])?;
+    let (row, col) = rest.split_once(['C', 'c'])?;
+    if !row.bytes().chain(col.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (row, col): (u32, u32) = (row.parse().ok()?, col.parse().ok()?);
+    if row == 0 || col == 0 || row > MAX_ROWS || col > MAX_COLUMNS {
+        return None;
+    }
+    Some((row, col))
+}
+
+/// Header names made usable as JSON object keys: a repeated name gets a `_2`, `_3`, ...
+/// suffix, so no column's values overwrite another's
+pub fn unique_keys(names: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    names.into_iter()
+        .map(|name| {
+            let key = (1..)
+                .map(|n| if n == 1 { name.clone() } else { format!("{}_{}", name, n) })
+                .find(|key| !seen.contains(key))
+                .expect("an unused suffix always exists");
+            seen.insert(key.clone());
+            key
+        })
+        .collect()
+}
+
+/// A1-style reference to a cell or range on a named sheet, as Excel writes it in formulas:
+/// `Sheet1!C5`, `Sheet1!C5:E9`, or `'Q1 Sales'!A1` for names that need quoting
+pub fn sheet_reference(sheet: &str, selection: Selection) -> String {
+    let (min_row, min_col, max_row, max_col) = selection.bounds();
+    let mut range = format!("{}{}", number_to_column(min_col), min_row);
+    if (min_row, min_col) != (max_row, max_col) {
+        range.push_str(&format!(":{}{}", number_to_column(max_col), max_row));
+    }
+    let plain = sheet.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && sheet.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '.'))
+        && parse_cell_ref(sheet).is_none();
+    if plain {
+        format!("{}!{}", sheet, range)
+    } else {
+        format!("'{}'!{}", sheet.replace('\'', "''"), range)
+    }
+}
+
+/// Same-sheet A1-style cells and ranges a formula refers to (`$` anchors are ignored).
+/// References to other sheets, whole rows/columns and defined names are skipped.
+pub fn formula_references(formula: &str) -> Vec<Selection> {
+    let mut references = Vec::new();
+    let mut chars = formula.chars().peekable();
+    let mut token = String::new();
+    let mut other_sheet = false;
+
+    let mut finish = |token: &mut String, other_sheet: &mut bool, next: Option<char>| {
+        // A token followed by `(` is a function name, and one with `!` is on another sheet
+        if !token.is_empty() && next != Some('(') && !*other_sheet && !token.contains('!') {
+            let plain = token.replace('$', "");
+            let mut ends = plain.split(':');
+            let start = ends.next().and_then(parse_cell_ref);
+            let end = ends.next().map(parse_cell_ref);
+            match (start, end, ends.next()) {
+                (Some(start), None, None) => references.push(Selection::single(start.0, start.1)),
+                (Some(start), Some(Some(end)), None) => references.push(Selection { start, end }),
+                _ => {}
+            }
+        }
+        token.clear();
+        *other_sheet = false;
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            // String literals can't hold references
+            '"' => {
+                finish(&mut token, &mut other_sheet, Some(c));
+                for c in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                }
+            }
+            // A quoted sheet name, always followed by `!`
+            '\'' => {
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                }
+                other_sheet = true;
+            }
+            c if c.is_ascii_alphanumeric() || matches!(c, '$' | ':' | '!' | '_' | '.') => token.push(c),
+            _ => finish(&mut token, &mut other_sheet, Some(c)),
         }
     }
+    finish(&mut token, &mut other_sheet, None);
+    references
+}
 
-    fn move_cursor(&mut self, dx: i32, dy: i32, extend_selection: bool) {
-        let (row, col) = self.cursor;
-        let new_row = (row as i32 + dy).clamp(1, MAX_ROWS as i32) as u32;
-        let new_col = (col as i32 + dx).clamp(1, MAX_COLUMNS as i32) as u32;
-        self.cursor = (new_row, new_col);
+/// Arithmetic series found at the start of a line of cells by `detect_series`
+struct Series {
+    seeds: usize, // leading cells the series was read from
+    first: f64,
+    step: f64,
+    decimals: i32, // most decimal places written in a seed; values are snapped to this precision
+    number_format: Option<String>, // format of the last seed (e.g. a date format), applied to new cells
+}
 
-        if extend_selection {
-            // Extend selection from anchor
-            self.selection.end = self.cursor;
-        } else {
-            // Reset selection to single cell
-            self.selection = Selection::single(new_row, new_col);
+impl Series {
+    /// Value at 0-based position `index`, rounded to the seeds' precision so a step like 0.1
+    /// doesn't pile up float error. Values too large to scale exactly are left as computed.
+    fn value(&self, index: usize) -> f64 {
+        let value = self.first + self.step * index as f64;
+        let scale = 10f64.powi(self.decimals);
+        if (value * scale).abs() >= 2f64.powi(53) {
+            return value;
         }
+        (value * scale).round() / scale
+    }
+}
 
-        self.adjust_scroll();
+/// Decimal places in the shortest text that reads back as `value` (0.25 -> 2, 1e9 -> 0)
+fn decimal_places(value: f64) -> i32 {
+    let text = value.to_string();
+    text.split_once('.').map_or(0, |(_, fraction)| fraction.len() as i32)
+}
+
+/// The numbers or dates leading a line of (col, row) cells, when there are at least two of them,
+/// they don't fill the whole line, and they step by a constant amount
+fn detect_series(sheet: &Worksheet, cells: &[(u32, u32)]) -> Option<Series> {
+    let seeds: Vec<f64> = cells.iter()
+        .map_while(|&coordinate| {
+            let cell = sheet.get_cell(coordinate)?;
+            if cell.get_data_type() != "n" || !cell.get_formula().is_empty() {
+                return None;
+            }
+            cell.get_value().parse::<f64>().ok()
+        })
+        .collect();
+    if seeds.len() < 2 || seeds.len() == cells.len() {
+        return None;
+    }
+    let step = seeds[1] - seeds[0];
+    let tolerance = 1e-9 * step.abs().max(1.0);
+    if seeds.windows(2).any(|pair| ((pair[1] - pair[0]) - step).abs() > tolerance) {
+        return None;
     }
+    let number_format = sheet.get_cell(cells[seeds.len() - 1])
+        .and_then(|cell| cell.get_style().get_number_format())
+        .map(|format| format.get_format_code().to_string())
+        .filter(|code| code != NumberingFormat::FORMAT_GENERAL);
+    let decimals = seeds.iter().map(|&seed| decimal_places(seed)).max().unwrap_or(0).min(15);
+    Some(Series { seeds: seeds.len(), first: seeds[0], step, decimals, number_format })
+}
 
-    fn adjust_scroll(&mut self) {
-        let (row, col) = self.cursor;
-        let (view_rows, view_cols) = self.viewport_size;
+/// Infer the type of an edited (non-formula) value, as Excel does when typing into a cell:
+/// numbers, TRUE/FALSE in any case and error literals like `#N/A` get their own type, and a
+/// leading `'` keeps the rest as text (so `'123` stays the text "123").
+pub fn parse_input_to_cell_value(input: &str) -> InputValue {
+    if let Some(text) = input.strip_prefix('\'') {
+        return InputValue::Text(text.to_string());
+    }
+    let trimmed = input.trim();
+    // Rust also parses "inf" and "NaN", which Excel treats as text
+    if trimmed.contains(|c: char| c.is_ascii_digit())
+        && let Ok(number) = trimmed.parse::<f64>()
+        && number.is_finite()
+    {
+        return InputValue::Number(number);
+    }
+    if trimmed.eq_ignore_ascii_case("TRUE") {
+        return InputValue::Bool(true);
+    }
+    if trimmed.eq_ignore_ascii_case("FALSE") {
+        return InputValue::Bool(false);
+    }
+    if let Some(error) = ERROR_LITERALS.iter().find(|error| error.eq_ignore_ascii_case(trimmed)) {
+        return InputValue::Error(error.to_string());
+    }
+    InputValue::Text(input.to_string())
+}
 
-        // Adjust vertical scroll
-        if row <= self.scroll.0 {
-            self.scroll.0 = row.saturating_sub(1);
-        } else if row > self.scroll.0 + view_rows as u32 {
-            self.scroll.0 = row - view_rows as u32;
+/// Move the relative parts of a formula's A1 references by `rows` and `cols`, as when a
+/// copied formula is pasted elsewhere: `=A1+$B$1` one row down becomes `=A2+$B$1`.
+/// References pushed off the sheet become `#REF!`; strings, function names and defined names are left alone.
+pub fn shift_formula(formula: &str, rows: i64, cols: i64) -> String {
+    let mut shifted = String::with_capacity(formula.len());
+    let mut token = String::new();
+    let mut chars = formula.chars().peekable();
+
+    let finish = |token: &mut String, shifted: &mut String, next: Option<char>| {
+        // A token followed by `(` is a function name
+        if next == Some('(') {
+            shifted.push_str(token);
+        } else {
+            shifted.push_str(&shift_reference(token, rows, cols));
         }
+        token.clear();
+    };
 
-        // Adjust horizontal scroll
-        if col <= self.scroll.1 {
-            self.scroll.1 = col.saturating_sub(1);
-        } else if col > self.scroll.1 + view_cols as u32 {
-            self.scroll.1 = col - view_cols as u32;
+    while let Some(c) = chars.next() {
+        match c {
+            // String literals and quoted sheet names are copied as they are
+            '"' | '\'' => {
+                finish(&mut token, &mut shifted, Some(c));
+                shifted.push(c);
+                for inner in chars.by_ref() {
+                    shifted.push(inner);
+                    if inner == c {
+                        break;
+                    }
+                }
+            }
+            c if c.is_ascii_alphanumeric() || matches!(c, '$' | ':' | '!' | '_' | '.') => token.push(c),
+            _ => {
+                finish(&mut token, &mut shifted, Some(c));
+                shifted.push(c);
+            }
         }
     }
+    finish(&mut token, &mut shifted, None);
+    shifted
+}
 
-    fn jump_to_start(&mut self) {
-        self.cursor = (1, 1);
-        self.selection = Selection::single(1, 1);
-        self.scroll = (0, 0);
+/// Shift one formula token if it is a cell, range, or whole row/column range (with an optional sheet prefix)
+fn shift_reference(token: &str, rows: i64, cols: i64) -> String {
+    let (sheet, reference) = match token.rfind('!') {
+        Some(bang) => token.split_at(bang + 1),
+        None => ("", token),
+    };
+    let parts: Vec<&str> = reference.split(':').collect();
+    if parts.len() > 2 {
+        return token.to_string();
     }
 
-    fn jump_to_end(&mut self) {
-        // Find the last used cell
-        if let Some(sheet) = self.spreadsheet.get_sheet(&self.current_sheet_index) {
-            let max_row = sheet.get_highest_row();
-            let max_col = sheet.get_highest_column();
-            let row = max_row.max(1);
-            let col = max_col.max(1);
-            self.cursor = (row, col);
-            self.selection = Selection::single(row, col);
-            self.adjust_scroll();
+    let mut shifted = Vec::new();
+    for part in &parts {
+        match shift_reference_part(part, rows, cols, parts.len() == 2) {
+            Some(Some(part)) => shifted.push(part),
+            Some(None) => return format!("{}#REF!", sheet),
+            None => return token.to_string(), // not a reference
         }
     }
+    format!("{}{}", sheet, shifted.join(":"))
+}
 
-    fn jump_to_row_start(&mut self) {
-        self.cursor.1 = 1;
-        self.selection = Selection::single(self.cursor.0, 1);
-        self.adjust_scroll();
+/// Shift a `$`-anchored cell (`$A1`), or a lone column (`A`) or row (`$3`) when part of a range.
+/// Returns None when the text isn't a reference, and Some(None) when it would leave the sheet.
+fn shift_reference_part(part: &str, rows: i64, cols: i64, in_range: bool) -> Option<Option<String>> {
+    let (first_anchor, rest) = match part.strip_prefix('$') {
+        Some(rest) => (true, rest),
+        None => (false, part),
+    };
+    let split = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+    let (letters, rest) = rest.split_at(split);
+    let (row_anchor, digits) = match rest.strip_prefix('$') {
+        Some(digits) => (true, digits),
+        None => (false, rest),
+    };
+    if !digits.chars().all(|c| c.is_ascii_digit()) || (letters.is_empty() && digits.is_empty()) {
+        return None;
     }
 
-    fn jump_to_row_end(&mut self) {
-        // Find last used column in current row
-        if let Some(sheet) = self.spreadsheet.get_sheet(&self.current_sheet_index) {
-            let max_col = sheet.get_highest_column();
-            let col = max_col.max(1);
-            self.cursor.1 = col;
-            self.selection = Selection::single(self.cursor.0, col);
-            self.adjust_scroll();
+    let column = if letters.is_empty() {
+        None
+    } else {
+        if letters.len() > 3 {
+            return None;
         }
+        let number = letters.chars().fold(0, |n, c| n * 26 + (c.to_ascii_uppercase() as u8 - b'A' + 1) as u32);
+        if number > MAX_COLUMNS {
+            return None;
+        }
+        Some((number, first_anchor))
+    };
+    let row = if digits.is_empty() {
+        None
+    } else {
+        let number: u32 = digits.parse().ok().filter(|&n| (1..=MAX_ROWS).contains(&n))?;
+        // With no column letters, a leading `$` anchors the row
+        Some((number, row_anchor || (letters.is_empty() && first_anchor)))
+    };
+    if (column.is_none() || row.is_none()) && !in_range {
+        return None;
     }
 
-    fn clear_selection(&mut self) {
-        self.selection = Selection::single(self.cursor.0, self.cursor.1);
+    let shift = |(number, anchored): (u32, bool), delta: i64, max: u32| {
+        let moved = if anchored { number as i64 } else { number as i64 + delta };
+        (1..=max as i64).contains(&moved).then_some((moved as u32, anchored))
+    };
+    let anchor = |anchored: bool| if anchored { "$" } else { "" };
+    let mut shifted = String::new();
+    if let Some(column) = column {
+        let Some((number, anchored)) = shift(column, cols, MAX_COLUMNS) else {
+            return Some(None);
+        };
+        shifted.push_str(anchor(anchored));
+        shifted.push_str(&number_to_column(number));
+    }
+    if let Some(row) = row {
+        let Some((number, anchored)) = shift(row, rows, MAX_ROWS) else {
+            return Some(None);
+        };
+        shifted.push_str(anchor(anchored));
+        shifted.push_str(&number.to_string());
     }
+    Some(Some(shifted))
+}
 
-    fn next_sheet(&mut self) {
-        let count = self.spreadsheet.get_sheet_count();
-        if count > 0 {
-            self.current_sheet_index = (self.current_sheet_index + 1) % count;
+/// Human-readable name of a mark for status messages
+fn mark_name(mark: CellMark) -> String {
+    match mark {
+        CellMark::None => "cleared".to_string(),
+        CellMark::YellowBg => "yellow bg".to_string(),
+        CellMark::RedText => "red text".to_string(),
+        CellMark::GreenText => "green text".to_string(),
+        CellMark::BlueBg => "blue bg".to_string(),
+        CellMark::MagentaText => "magenta text".to_string(),
+        CellMark::Custom { argb, is_bg } => {
+            format!("#{:06X} {}", argb & 0xFFFFFF, if is_bg { "bg" } else { "text" })
         }
     }
+}
 
-    fn prev_sheet(&mut self) {
-        let count = self.spreadsheet.get_sheet_count();
-        if count > 0 {
-            if self.current_sheet_index == 0 {
-                self.current_sheet_index = count - 1;
-            } else {
-                self.current_sheet_index -= 1;
-            }
-        }
+/// The number key that applies a preset mark (1 clears)
+fn preset_mark_key(mark: CellMark) -> u32 {
+    match mark {
+        CellMark::None => 1,
+        CellMark::YellowBg => 2,
+        CellMark::RedText => 3,
+        CellMark::GreenText => 4,
+        CellMark::BlueBg => 5,
+        CellMark::MagentaText => 6,
+        CellMark::Custom { .. } => 0,
     }
+}
 
-    fn enter_edit_mode(&mut self) {
-        // Check if this is a formula cell (read-only)
-        if self.is_formula_cell(self.cursor.1, self.cursor.0) {
-            // Show the formula in status message instead of editing
-            if let Some(sheet) = self.spreadsheet.get_sheet(&self.current_sheet_index) {
-                let formula = sheet.get_cell_value((self.cursor.1, self.cursor.0)).get_formula();
-                self.status_message = Some(format!("Formula (read-only): ={}", formula));
-            }
-            return;
+/// Parse a mark given to a command: a mark color name, `none`, or `#RRGGBB` for a custom background
+fn parse_mark(name: &str) -> Option<CellMark> {
+    match name.to_lowercase().as_str() {
+        "none" | "clear" => Some(CellMark::None),
+        "yellow" => Some(CellMark::YellowBg),
+        "red" => Some(CellMark::RedText),
+        "green" => Some(CellMark::GreenText),
+        "blue" => Some(CellMark::BlueBg),
+        "magenta" => Some(CellMark::MagentaText),
+        _ => {
+            let argb = parse_argb(name.strip_prefix('#')?)?;
+            Some(CellMark::Custom { argb, is_bg: true })
         }
+    }
+}
 
-        self.mode = Mode::Edit;
-        if let Some(sheet) = self.spreadsheet.get_sheet(&self.current_sheet_index) {
-            let value = sheet.get_cell_value((self.cursor.1, self.cursor.0));
-            self.textarea = TextArea::from(vec![value.get_value().to_string()]);
+/// Parse a cell value as a finite number
+pub fn parse_number(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// Number format code with one more (`delta > 0`) or one fewer decimal place than `code`.
+/// General/text formats gain `0.0` when increasing and are left alone when decreasing.
+fn step_decimals(code: &str, delta: i32) -> String {
+    if code.is_empty() || code == NumberingFormat::FORMAT_GENERAL || code == NumberingFormat::FORMAT_TEXT {
+        // General shows as many decimals as a value needs, so there are none to take away
+        return if delta > 0 { "0.0" } else { code }.to_string();
+    }
+    code.split(';')
+        .map(|section| step_section_decimals(section, delta))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Apply `step_decimals` to one `;`-separated section, ignoring quoted and escaped literals
+fn step_section_decimals(section: &str, delta: i32) -> String {
+    let chars: Vec<char> = section.chars().collect();
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut point = None;
+    let mut last_digit = None;
+    for (i, &ch) in chars.iter().enumerate() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match ch {
+            '"' => in_quotes = !in_quotes,
+            '\\' if !in_quotes => escaped = true,
+            '.' if !in_quotes && point.is_none() => point = Some(i),
+            '0' | '#' | '?' if !in_quotes && point.is_none() => last_digit = Some(i),
+            _ => {}
         }
     }
 
-    fn save_cell_value(&mut self) {
-        if let Some(sheet) = self.spreadsheet.get_sheet_mut(&self.current_sheet_index) {
-             let content = self.textarea.lines().join("\n");
-             sheet.get_cell_mut((self.cursor.1, self.cursor.0)).set_value(content);
+    let mut chars = chars;
+    match point {
+        Some(p) => {
+            let decimals = chars[p + 1..].iter().take_while(|&&c| matches!(c, '0' | '#' | '?')).count();
+            if delta > 0 {
+                chars.insert(p + 1 + decimals, '0');
+            } else if decimals > 1 {
+                chars.remove(p + decimals);
+            } else {
+                // Dropping the last decimal also drops the point
+                chars.drain(p..=p + decimals);
+            }
+        }
+        None => {
+            if let Some(d) = last_digit
+                && delta > 0
+            {
+                chars.splice(d + 1..d + 1, ['.', '0']);
+            }
         }
     }
+    chars.into_iter().collect()
+}
 
-    fn save_file(&self) -> Result<()> {
-        umya_spreadsheet::writer::xlsx::write(&self.spreadsheet, &self.path)
-            .map_err(|e| anyhow::anyhow!("Failed to save file: {}", e))
+/// Workbook features umya may not write back faithfully, as plural nouns for a warning message
+pub fn detect_lossy_features(spreadsheet: &Spreadsheet) -> Vec<String> {
+    let sheets = spreadsheet.get_sheet_collection();
+    let any_sheet = |has: fn(&Worksheet) -> bool| sheets.iter().any(has);
+
+    let mut features = Vec::new();
+    if any_sheet(|sheet| !sheet.get_worksheet_drawing().get_chart_collection().is_empty()) {
+        features.push("charts".to_string());
+    }
+    if any_sheet(|sheet| !sheet.get_worksheet_drawing().get_image_collection().is_empty()) {
+        features.push("images".to_string());
     }
+    if any_sheet(|sheet| !sheet.get_pivot_tables().is_empty()) {
+        features.push("pivot tables".to_string());
+    }
+    if *spreadsheet.get_has_macros() {
+        features.push("macros".to_string());
+    }
+    features
+}
 
-    fn widen_column(&mut self) {
-        let col = self.cursor.1;
-        let current = self.column_widths.get(&col).copied().unwrap_or(DEFAULT_COLUMN_WIDTH);
-        let new_width = (current + COLUMN_WIDTH_STEP).min(MAX_COLUMN_WIDTH);
-        self.column_widths.insert(col, new_width);
-        if new_width >= MAX_COLUMN_WIDTH {
-            self.status_message = Some(format!("Column width at maximum ({})", MAX_COLUMN_WIDTH));
+/// Color a number format gives `value`, taken from the `[Red]` / `[Color10]` tag of the
+/// section that applies (positive;negative;zero;text), as ARGB
+pub fn format_color(code: &str, value: f64) -> Option<u32> {
+    let sections = format_sections(code);
+    let section = match sections.len() {
+        0 => return None,
+        1 => sections[0],
+        _ if value < 0.0 => sections[1],
+        3.. if value == 0.0 => sections[2],
+        _ => sections[0],
+    };
+
+    let mut rest = section;
+    while let Some(start) = rest.find('[') {
+        let end = start + rest[start..].find(']')?;
+        let tag = rest[start + 1..end].to_ascii_lowercase();
+        let color = match tag.as_str() {
+            "black" => Some(0xFF000000),
+            "white" => Some(0xFFFFFFFF),
+            "red" => Some(0xFFFF0000),
+            "green" => Some(0xFF00FF00),
+            "blue" => Some(0xFF0000FF),
+            "yellow" => Some(0xFFFFFF00),
+            "magenta" => Some(0xFFFF00FF),
+            "cyan" => Some(0xFF00FFFF),
+            // [Color1]..[Color56] are palette slots 8..63
+            _ => tag.strip_prefix("color")
+                .and_then(|n| n.parse::<usize>().ok())
+                .filter(|n| (1..=56).contains(n))
+                .map(|n| EXCEL_INDEXED_COLORS[n + 7]),
+        };
+        if color.is_some() {
+            return color;
         }
+        rest = &rest[end + 1..];
     }
+    None
+}
 
-    fn shrink_column(&mut self) {
-        let col = self.cursor.1;
-        let current = self.column_widths.get(&col).copied().unwrap_or(DEFAULT_COLUMN_WIDTH);
-        let min_width: u16 = 3;
-        let new_width = current.saturating_sub(COLUMN_WIDTH_STEP).max(min_width);
-        if new_width <= min_width {
-            self.column_widths.remove(&col);
-            self.status_message = Some(format!("Column width at minimum ({})", min_width));
-        } else {
-            self.column_widths.insert(col, new_width);
+/// Split a number format code into its `;`-separated sections, ignoring
+/// separators inside quoted text, brackets or after a backslash
+fn format_sections(code: &str) -> Vec<&str> {
+    let mut sections = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut in_brackets = false;
+    let mut escaped = false;
+    for (i, c) in code.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if !in_quotes => escaped = true,
+            '"' if !in_brackets => in_quotes = !in_quotes,
+            '[' if !in_quotes => in_brackets = true,
+            ']' if !in_quotes => in_brackets = false,
+            ';' if !in_quotes && !in_brackets => {
+                sections.push(&code[start..i]);
+                start = i + 1;
+            }
+            _ => {}
         }
     }
+    sections.push(&code[start..]);
+    sections
+}
 
-    pub fn get_column_width(&self, col: u32) -> u16 {
-        self.column_widths.get(&col).copied().unwrap_or(DEFAULT_COLUMN_WIDTH)
+/// The first index from `start` on, stepping in `direction` (both ways when 0), that isn't hidden
+fn first_visible(start: u32, direction: i32, last: u32, hidden: impl Fn(u32) -> bool) -> Option<u32> {
+    let forward = (start..=last).find(|&index| !hidden(index));
+    let backward = || (1..=start).rev().find(|&index| !hidden(index));
+    match direction {
+        0 => forward.or_else(backward),
+        d if d > 0 => forward,
+        _ => backward(),
     }
+}
 
-    fn copy_selection(&mut self) {
-        let (min_row, min_col, max_row, max_col) = self.selection.bounds();
+/// Bold, italic and underline set in a cell style's font
+fn style_emphasis(style: &Style) -> FontEmphasis {
+    style.get_font()
+        .map(|font| FontEmphasis {
+            bold: *font.get_bold(),
+            italic: *font.get_italic(),
+            underline: !matches!(font.get_underline(), "" | "none"),
+        })
+        .unwrap_or_default()
+}
 
-        let mut data = Vec::new();
-        if let Some(sheet) = self.spreadsheet.get_sheet(&self.current_sheet_index) {
-            for r in min_row..=max_row {
-                let mut row_data = Vec::new();
-                for c in min_col..=max_col {
-                    let value = sheet.get_cell_value((c, r)).get_value().to_string();
-                    row_data.push(value);
-                }
-                data.push(row_data);
-            }
-        }
+/// Last-modified time of a file, `None` when it doesn't exist
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
 
-        let cells = (max_row - min_row + 1) * (max_col - min_col + 1);
-        self.clipboard = Clipboard { data };
-        self.status_message = Some(format!("Copied {} cell(s)", cells));
-    }
+/// What two cells must share to count as duplicates in `mark_duplicates`
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum DuplicateKey {
+    Number(u64), // f64 bits
+    Text(String),
+}
 
-    fn paste_clipboard(&mut self) {
-        if self.clipboard.data.is_empty() {
-            self.status_message = Some("Clipboard is empty".to_string());
-            return;
-        }
+/// Swap the left and right movement keys (arrows and A/D) for a right-to-left grid.
+/// Ctrl or Alt with a letter is a command, not a move, so it is left alone.
+fn mirror_horizontal_key(mut key: KeyEvent) -> KeyEvent {
+    let command = key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+    key.code = match key.code {
+        KeyCode::Left => KeyCode::Right,
+        KeyCode::Right => KeyCode::Left,
+        KeyCode::Char('a') if !command => KeyCode::Char('d'),
+        KeyCode::Char('d') if !command => KeyCode::Char('a'),
+        KeyCode::Char('A') if !command => KeyCode::Char('D'),
+        KeyCode::Char('D') if !command => KeyCode::Char('A'),
+        code => code,
+    };
+    key
+}
 
-        let (start_row, start_col) = self.cursor;
+/// Status suffix for cells a change skipped because they are locked
+fn locked_note(skipped: usize) -> String {
+    if skipped == 0 {
+        String::new()
+    } else {
+        format!(" ({} locked cell(s) skipped)", skipped)
+    }
+}
 
-        if let Some(sheet) = self.spreadsheet.get_sheet_mut(&self.current_sheet_index) {
-            for (dr, row_data) in self.clipboard.data.iter().enumerate() {
-                for (dc, value) in row_data.iter().enumerate() {
-                    let target_row = start_row + dr as u32;
-                    let target_col = start_col + dc as u32;
+/// Locked by its own protection setting; Excel's implicit default lock isn't counted
+fn cell_is_locked(cell: &Cell) -> bool {
+    cell.get_style().get_protection().is_some_and(|protection| *protection.get_locked())
+}
 
-                    if target_row <= MAX_ROWS && target_col <= MAX_COLUMNS {
-                        sheet.get_cell_mut((target_col, target_row)).set_value(value);
-                    }
-                }
-            }
-        }
+/// Neither a value nor a formula: an empty-string cell counts as blank too
+fn cell_is_blank(cell: &Cell) -> bool {
+    cell.get_value().is_empty() && cell.get_formula().is_empty()
+}
 
-        let rows = self.clipboard.data.len();
-        let cols = self.clipboard.data.first().map(|r| r.len()).unwrap_or(0);
-        self.status_message = Some(format!("Pasted {}x{} cells", rows, cols));
+/// Excel serial date: days since 1899-12-30, with the time of day as the fraction
+pub fn excel_serial(datetime: chrono::NaiveDateTime) -> f64 {
+    let epoch = chrono::NaiveDate::from_ymd_opt(1899, 12, 30)
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .expect("valid epoch");
+    (datetime - epoch).num_seconds() as f64 / 86_400.0
+}
+
+/// Build an Excel color that shows exactly `argb`. umya's own palette lookup in
+/// `set_argb` can pick the wrong `indexed` slot for palette colors, so those are
+/// given their index explicitly (from 8 on, the slots Excel itself uses).
+/// Parse a sheet left unread by a lazy open, adding its cell marks and column widths
+fn load_lazy_sheet(
+    spreadsheet: &mut Spreadsheet,
+    index: usize,
+    cell_marks: &mut HashMap<(usize, u32, u32), CellMark>,
+    column_widths: &mut HashMap<(usize, u32), u16>,
+) {
+    let loaded = spreadsheet.get_sheet(&index).is_none_or(|sheet| sheet.is_deserialized());
+    if loaded {
+        return;
     }
+    spreadsheet.read_sheet(index);
+    if let Some(sheet) = spreadsheet.get_sheet(&index) {
+        App::load_cell_marks_from_sheet(index, sheet, cell_marks);
+        App::load_column_widths_from_sheet(index, sheet, column_widths);
+    }
+}
 
-    /// Check if a cell contains a formula
-    pub fn is_formula_cell(&self, col: u32, row: u32) -> bool {
-        if let Some(sheet) = self.spreadsheet.get_sheet(&self.current_sheet_index) {
-            let cell_value = sheet.get_cell_value((col, row));
-            !cell_value.get_formula().is_empty()
-        } else {
-            false
-        }
+fn sheet_is_hidden(sheet: &Worksheet) -> bool {
+    matches!(sheet.get_sheet_state(), "hidden" | "veryHidden")
+}
+
+/// The sheet a workbook opens on: the first one not hidden (or the first, if all are)
+fn first_visible_sheet(book: &Spreadsheet) -> usize {
+    book.get_sheet_collection().iter().position(|sheet| !sheet_is_hidden(sheet)).unwrap_or(0)
+}
+
+fn excel_color(argb: u32) -> Color {
+    let mut color = Color::default();
+    match EXCEL_INDEXED_COLORS.iter().skip(8).position(|&entry| entry == argb) {
+        Some(position) => color.set_indexed(position as u32 + 8),
+        None => color.set_argb(format!("{:08X}", argb)),
+    };
+    color
+}
+
+/// The ARGB an Excel color shows: `indexed` colors are looked up in Excel's palette
+/// rather than umya's, and theme colors (which need the theme) are skipped
+fn color_to_argb(color: &Color) -> Option<u32> {
+    let index = *color.get_indexed() as usize;
+    if index > 0 && index < EXCEL_INDEXED_COLORS.len() {
+        return Some(EXCEL_INDEXED_COLORS[index]);
     }
+    parse_argb(color.get_argb())
+}
 
-    /// Check if a format code represents a date/time format
-    fn is_date_format(format_code: &str) -> bool {
-        // Skip general and text formats
-        if format_code == NumberingFormat::FORMAT_GENERAL || format_code == NumberingFormat::FORMAT_TEXT {
-            return false;
-        }
-        // Check for date/time indicators in format code
-        let lower = format_code.to_lowercase();
-        lower.contains('y') || lower.contains('m') || lower.contains('d')
-            || lower.contains('h') || lower.contains("am") || lower.contains("pm")
+/// Parse an `RRGGBB` or `AARRGGBB` hex color (6-digit values are treated as opaque)
+fn parse_argb(hex: &str) -> Option<u32> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
     }
+    match hex.len() {
+        6 => u32::from_str_radix(hex, 16).ok().map(|rgb| 0xFF000000 | rgb),
+        8 => u32::from_str_radix(hex, 16).ok(),
+        _ => None,
+    }
+}
 
-    /// Convert date format to ISO format (yyyy-mm-dd or yyyy-mm-dd hh:mm:ss)
-    fn normalize_date_format(format_code: &str) -> &'static str {
-        let lower = format_code.to_lowercase();
-        // Check if it includes time
-        if lower.contains('h') || lower.contains("am") || lower.contains("pm") {
-            // Date + Time
-            if lower.contains('y') || lower.contains('d') {
-                "yyyy-mm-dd hh:mm:ss"
-            } else {
-                // Time only
-                "hh:mm:ss"
-            }
-        } else {
-            // Date only
-            "yyyy-mm-dd"
-        }
+/// Write `spreadsheet` to `path`, first copying any existing file to `<path>.bak` when `backup` is set
+fn write_spreadsheet(spreadsheet: &Spreadsheet, path: &Path, backup: bool) -> Result<()> {
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+        && !parent.is_dir()
+    {
+        anyhow::bail!("Directory does not exist: {:?}", parent);
     }
+    if backup && path.exists() {
+        let mut backup_path = path.as_os_str().to_owned();
+        backup_path.push(".bak");
+        std::fs::copy(path, &backup_path)
+            .map_err(|e| anyhow::anyhow!("Failed to write backup {:?}: {}", backup_path, e))?;
+    }
+    umya_spreadsheet::writer::xlsx::write(spreadsheet, path)
+        .map_err(|e| anyhow::anyhow!("Failed to save file: {}", e))
+}
 
-    /// Get cell value, truncated to fit column width with ellipsis
-    pub fn get_cell_display(&self, col: u32, row: u32) -> String {
-        if let Some(sheet) = self.spreadsheet.get_sheet(&self.current_sheet_index) {
-            let cell_value = sheet.get_cell_value((col, row));
-            let formula = cell_value.get_formula();
-            let width = self.get_column_width(col) as usize;
+/// Guess whether piped text is tab- or comma-separated from the first few lines
+pub fn detect_delimiter(text: &str) -> char {
+    let sample: String = text.lines().take(10).collect();
+    let tabs = sample.matches('\t').count();
+    let commas = sample.matches(',').count();
+    if tabs > 0 && tabs >= commas { '\t' } else { ',' }
+}
 
-            // For formula cells, show the cached result
-            let display_value = if !formula.is_empty() {
-                // Show calculated result (cached by Excel)
-                let result = cell_value.get_value().to_string();
-                if result.is_empty() {
-                    "=...".to_string()  // Formula with no cached result
-                } else {
-                    result
-                }
+/// What a column of piped data is loaded as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Text,
+    Number,
+    Date, // ISO 8601, e.g. 2024-03-01 or 2024-03-01 14:30:00
+}
+
+/// Infer each column's type from its non-empty values: it is a number or date column only
+/// when every one of them parses as such. The first row is left out when there are others,
+/// since it is usually a header.
+pub fn infer_column_types(rows: &[Vec<String>]) -> Vec<ColumnType> {
+    let body = if rows.len() > 1 { &rows[1..] } else { rows };
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    (0..columns)
+        .map(|c| {
+            let mut values = body.iter()
+                .filter_map(|row| row.get(c))
+                .filter(|value| !value.trim().is_empty())
+                .peekable();
+            if values.peek().is_none() {
+                return ColumnType::Text;
+            }
+            let values: Vec<&String> = values.collect();
+            if values.iter().all(|value| parse_delimited_number(value).is_some()) {
+                ColumnType::Number
+            } else if values.iter().all(|value| import::parse_iso_datetime(value.trim()).is_some()) {
+                ColumnType::Date
             } else {
-                // Check for number format (date/time formatting)
-                let raw_value = cell_value.get_value().to_string();
+                ColumnType::Text
+            }
+        })
+        .collect()
+}
 
-                // Try to get cell style and format
-                if let Some(cell) = sheet.get_cell((col, row)) {
-                    if let Some(num_fmt) = cell.get_style().get_number_format() {
-                        let format_code = num_fmt.get_format_code();
-                        if Self::is_date_format(format_code) {
-                            // Apply ISO date formatting (yyyy-mm-dd)
-                            let iso_format = Self::normalize_date_format(format_code);
-                            to_formatted_string(&raw_value, iso_format)
-                        } else if format_code != NumberingFormat::FORMAT_GENERAL {
-                            // Apply other number formatting
-                            to_formatted_string(&raw_value, format_code)
-                        } else {
-                            raw_value
-                        }
-                    } else {
-                        raw_value
-                    }
-                } else {
-                    raw_value
-                }
-            };
+/// A number in piped data. Leading zeros (as in ZIP codes or IDs like 007) mean the value is
+/// really text, so those don't count.
+fn parse_delimited_number(value: &str) -> Option<f64> {
+    let trimmed = value.trim();
+    let digits = trimmed.trim_start_matches(['-', '+']);
+    if digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.") {
+        return None;
+    }
+    match parse_input_to_cell_value(trimmed) {
+        InputValue::Number(number) => Some(number),
+        _ => None,
+    }
+}
 
-            if display_value.len() > width {
-                // Truncate with ellipsis
-                let truncated: String = display_value.chars().take(width.saturating_sub(1)).collect();
-                format!("{}~", truncated)
-            } else {
-                display_value
+/// Read piped delimited text line by line, stopping once it holds more than `max_rows`
+/// records so huge input isn't buffered whole. A record ends at a line break outside
+/// double quotes; the extra record read lets `parse_delimited` report the cut.
+pub fn read_delimited_input(mut reader: impl std::io::BufRead, max_rows: usize) -> std::io::Result<String> {
+    let mut text = String::new();
+    let mut records = 0;
+    let mut in_quotes = false;
+    loop {
+        let start = text.len();
+        if reader.read_line(&mut text)? == 0 {
+            break;
+        }
+        // "" inside a quoted field toggles twice, so only the parity matters
+        in_quotes ^= text[start..].matches('"').count() % 2 == 1;
+        if !in_quotes {
+            records += 1;
+            if records > max_rows {
+                break;
             }
-        } else {
-            String::new()
         }
     }
+    Ok(text)
+}
 
-    fn set_mark_for_selection(&mut self, mark: CellMark) {
-        let (min_row, min_col, max_row, max_col) = self.selection.bounds();
-        let sheet_idx = self.current_sheet_index;
-
-        let mut count = 0;
-        if let Some(sheet) = self.spreadsheet.get_sheet_mut(&sheet_idx) {
-            for r in min_row..=max_row {
-                for c in min_col..=max_col {
-                    let key = (sheet_idx, r, c);
-                    if mark == CellMark::None {
-                        self.cell_marks.remove(&key);
-                    } else {
-                        self.cell_marks.insert(key, mark);
-                    }
-
-                    // Apply style to Excel cell
-                    let cell = sheet.get_cell_mut((c, r));
-                    let style = cell.get_style_mut();
-
-                    // Use slightly adjusted colors to avoid indexed color mapping bug
-                    // umya-spreadsheet converts exact palette matches to indexed colors incorrectly
-                    match mark {
-                        CellMark::None => {
-                            // Clear styles - reset to default
-                            style.get_font_mut().set_color(Color::default().set_argb("FF000001").clone());
-                            style.get_fill_mut().get_pattern_fill_mut().set_pattern_type(PatternValues::None);
-                        }
-                        CellMark::YellowBg => {
-                            // Yellow slightly adjusted to avoid indexed: FFFFEF00
-                            let bg = Color::default().set_argb("FFFFEF00").clone();
-                            style.get_fill_mut().get_pattern_fill_mut()
-                                .set_foreground_color(bg)
-                                .set_pattern_type(PatternValues::Solid);
-                            style.get_font_mut().set_color(Color::default().set_argb("FF000001").clone());
-                        }
-                        CellMark::RedText => {
-                            // Red slightly adjusted: FFFF0001
-                            style.get_font_mut().set_color(Color::default().set_argb("FFFF0001").clone());
-                        }
-                        CellMark::GreenText => {
-                            // Dark green slightly adjusted: FF008001
-                            style.get_font_mut().set_color(Color::default().set_argb("FF008001").clone());
-                        }
-                        CellMark::BlueBg => {
-                            // Blue slightly adjusted: FF0000FE
-                            let bg = Color::default().set_argb("FF0000FE").clone();
-                            style.get_fill_mut().get_pattern_fill_mut()
-                                .set_foreground_color(bg)
-                                .set_pattern_type(PatternValues::Solid);
-                            style.get_font_mut().set_color(Color::default().set_argb("FFFFFFFE").clone());
-                        }
-                        CellMark::MagentaText => {
-                            // Magenta slightly adjusted: FFFF00FE
-                            style.get_font_mut().set_color(Color::default().set_argb("FFFF00FE").clone());
-                        }
-                    }
+/// Split delimited text into rows of fields, honouring double-quoted fields.
+/// Stops after `max_rows` rows and drops fields beyond `max_cols`; the flag reports whether anything was cut.
+pub fn parse_delimited(text: &str, delimiter: char, max_rows: usize, max_cols: usize) -> (Vec<Vec<String>>, bool) {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut truncated = false;
+    let mut chars = text.chars().peekable();
 
-                    count += 1;
+    while let Some(ch) = chars.next() {
+        if in_quotes {
+            match ch {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
                 }
+                '"' => in_quotes = false,
+                _ => field.push(ch),
             }
+            continue;
+        }
+        match ch {
+            '"' if field.is_empty() => in_quotes = true,
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+                if rows.len() >= max_rows {
+                    truncated = chars.peek().is_some();
+                    break;
+                }
+            }
+            c if c == delimiter => row.push(std::mem::take(&mut field)),
+            _ => field.push(ch),
         }
-
-        let mark_name = match mark {
-            CellMark::None => "cleared",
-            CellMark::YellowBg => "yellow bg",
-            CellMark::RedText => "red text",
-            CellMark::GreenText => "green text",
-            CellMark::BlueBg => "blue bg",
-            CellMark::MagentaText => "magenta text",
-        };
-        self.status_message = Some(format!("Marked {} cell(s): {}", count, mark_name));
-    }
-
-    pub fn get_cell_mark(&self, row: u32, col: u32) -> CellMark {
-        let key = (self.current_sheet_index, row, col);
-        self.cell_marks.get(&key).copied().unwrap_or(CellMark::None)
     }
-
-    fn enter_sheet_select_mode(&mut self) {
-        self.sheet_select_index = self.current_sheet_index;
-        self.mode = Mode::SheetSelect;
+    if (!field.is_empty() || !row.is_empty()) && rows.len() < max_rows {
+        row.push(field);
+        rows.push(row);
     }
 
-    fn sheet_select_move(&mut self, delta: i32) {
-        let count = self.spreadsheet.get_sheet_count();
-        if count == 0 {
-            return;
+    for row in &mut rows {
+        if row.len() > max_cols {
+            row.truncate(max_cols);
+            truncated = true;
         }
-        let new_index = (self.sheet_select_index as i32 + delta).rem_euclid(count as i32) as usize;
-        self.sheet_select_index = new_index;
     }
+    (rows, truncated)
+}
 
-    fn confirm_sheet_selection(&mut self) {
-        self.current_sheet_index = self.sheet_select_index;
-        self.mode = Mode::View;
-    }
+/// Make a value safe inside a Markdown table cell: pipes are escaped and line breaks become `<br>`
+fn markdown_escape(value: &str) -> String {
+    value.replace('|', "\\|").replace("\r\n", "<br>").replace('\n', "<br>")
+}
 
-    pub fn get_sheet_names(&self) -> Vec<String> {
-        self.spreadsheet.get_sheet_collection()
-            .iter()
-            .map(|s| s.get_name().to_string())
-            .collect()
+/// Quote a CSV field if it contains a delimiter, quote or line break
+fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
     }
 }
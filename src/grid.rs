@@ -0,0 +1,97 @@
+//! Pure mapping between sheet cells and screen positions in the grid.
+//! `draw_grid` records a `GridLayout` each frame and mouse handling reads it back,
+//! so both always agree on where every cell is.
+
+use ratatui::layout::Rect;
+
+/// Stop adding columns after this many, however narrow they are
+const MAX_VISIBLE_COLUMNS: usize = 50;
+
+/// Geometry of the grid as last drawn
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GridLayout {
+    pub origin: (u16, u16), // (x, y) of the grid's inner area; its first line holds the column letters
+    pub row_number_width: u16,
+    pub row_height: u16, // terminal lines per row
+    pub columns: Vec<(u32, u16)>, // visible (col, width) from left to right
+    pub rows: Vec<u32>, // visible rows from top to bottom
+    pub row_numbers_right: bool, // right-to-left layout: row numbers follow the columns instead of leading them
+}
+
+/// Sheet indexes (1-based, up to `last`) shown from the first grid position on:
+/// frozen lines first, then from `scroll` onward, leaving out hidden ones
+pub fn visible_indexes(frozen: u32, scroll: u32, last: u32, hidden: impl Fn(u32) -> bool) -> impl Iterator<Item = u32> {
+    (1..=frozen.min(last))
+        .chain(frozen + scroll + 1..=last)
+        .filter(move |&index| !hidden(index))
+}
+
+/// Columns that fit in `available_width`, each taking its width plus one column of spacing.
+/// At least one column is always returned so a very narrow terminal still shows the cursor column.
+pub fn visible_columns(
+    frozen_cols: u32,
+    scroll_col: u32,
+    last_col: u32,
+    available_width: u16,
+    width_of: impl Fn(u32) -> u16,
+    hidden: impl Fn(u32) -> bool,
+) -> Vec<u32> {
+    let mut columns = Vec::new();
+    let mut used_width: u16 = 0;
+    let mut candidates = visible_indexes(frozen_cols, scroll_col, last_col, hidden).peekable();
+    let first = candidates.peek().copied();
+    for col in candidates.take(MAX_VISIBLE_COLUMNS) {
+        let width = width_of(col) + 1; // +1 for spacing
+        if used_width + width > available_width {
+            break;
+        }
+        used_width += width;
+        columns.push(col);
+    }
+    if columns.is_empty() {
+        columns.push(first.unwrap_or((frozen_cols + scroll_col + 1).min(last_col)));
+    }
+    columns
+}
+
+/// Screen x of the first data column (after the row numbers and their spacing, unless they are on the right)
+fn data_x(layout: &GridLayout) -> u16 {
+    if layout.row_numbers_right {
+        layout.origin.0
+    } else {
+        layout.origin.0 + layout.row_number_width + 1
+    }
+}
+
+/// The (row, col) of the cell under a screen position, if any
+pub fn cell_at_screen(x: u16, y: u16, layout: &GridLayout) -> Option<(u32, u32)> {
+    // First line of the grid is the column-letter header
+    if y <= layout.origin.1 {
+        return None;
+    }
+    let line = (y - layout.origin.1 - 1) / layout.row_height.max(1);
+    let row = *layout.rows.get(line as usize)?;
+
+    let mut col_x = data_x(layout);
+    if x < col_x {
+        return None;
+    }
+    for &(col, width) in &layout.columns {
+        let width = width + 1; // +1 for spacing
+        if x < col_x + width {
+            return Some((row, col));
+        }
+        col_x += width;
+    }
+    None
+}
+
+/// Screen area covered by a cell, or `None` when it is scrolled out of view
+pub fn screen_rect_for_cell(row: u32, col: u32, layout: &GridLayout) -> Option<Rect> {
+    let line = layout.rows.iter().position(|&r| r == row)? as u16;
+    let position = layout.columns.iter().position(|&(c, _)| c == col)?;
+    let x = data_x(layout) + layout.columns[..position].iter().map(|&(_, width)| width + 1).sum::<u16>();
+    let height = layout.row_height.max(1);
+    let y = layout.origin.1 + 1 + line * height;
+    Some(Rect::new(x, y, layout.columns[position].1, height))
+}
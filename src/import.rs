@@ -0,0 +1,130 @@
+//! Reading spreadsheet formats umya-spreadsheet can't open (ODS and legacy XLS/XLSB).
+//! Only values are brought over, into a fresh workbook; formulas, styles and comments are lost,
+//! and the result is saved as XLSX.
+
+use anyhow::Result;
+use calamine::{open_workbook_auto, Data, Reader};
+use std::path::Path;
+use umya_spreadsheet::Spreadsheet;
+
+use crate::app::excel_serial;
+
+/// File format of an opened workbook, detected from its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Xlsx,
+    Xls,
+    Xlsb,
+    Ods,
+}
+
+impl FileFormat {
+    /// Detect the format of an existing file. Anything that isn't a known foreign
+    /// format is handed to umya as XLSX, except plain-text data, which it can't read.
+    pub fn detect(path: &Path) -> Result<Self> {
+        let extension = path.extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "xls" => Ok(FileFormat::Xls),
+            "xlsb" => Ok(FileFormat::Xlsb),
+            "ods" => Ok(FileFormat::Ods),
+            "csv" | "tsv" | "txt" => anyhow::bail!(
+                "Can't open .{} files directly; pipe them in instead (e.g. cat data.{} | term-xlsx new.xlsx)",
+                extension, extension
+            ),
+            "numbers" => anyhow::bail!("Unsupported file format: .numbers (export it to .xlsx first)"),
+            _ => Ok(FileFormat::Xlsx),
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FileFormat::Xlsx => "XLSX",
+            FileFormat::Xls => "XLS",
+            FileFormat::Xlsb => "XLSB",
+            FileFormat::Ods => "ODS",
+        }
+    }
+}
+
+/// Read every sheet's values into a new workbook
+pub fn import_values(path: &Path) -> Result<Spreadsheet> {
+    let mut source = open_workbook_auto(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read file: {}", e))?;
+    let mut book = umya_spreadsheet::new_file_empty_worksheet();
+
+    for name in source.sheet_names() {
+        let range = source.worksheet_range(&name)
+            .map_err(|e| anyhow::anyhow!("Failed to read sheet {}: {}", name, e))?;
+        let sheet = book.new_sheet(name.clone())
+            .map_err(|e| anyhow::anyhow!("Failed to add sheet {}: {}", name, e))?;
+        let Some((start_row, start_col)) = range.start() else {
+            continue; // empty sheet
+        };
+
+        for (row, col, value) in range.cells() {
+            // calamine positions are 0-based and relative to the range start
+            let coordinate = (start_col + col as u32 + 1, start_row + row as u32 + 1);
+            match value {
+                Data::Empty => {}
+                Data::Int(n) => {
+                    sheet.get_cell_mut(coordinate).set_value_number(*n as f64);
+                }
+                Data::Float(n) => {
+                    sheet.get_cell_mut(coordinate).set_value_number(*n);
+                }
+                Data::Bool(b) => {
+                    sheet.get_cell_mut(coordinate).set_value_bool(*b);
+                }
+                Data::DateTime(datetime) => {
+                    // Going through chrono rebases dates from 1904-based workbooks
+                    let (serial, code) = match datetime.as_datetime() {
+                        Some(date) if datetime.is_datetime() => {
+                            let serial = excel_serial(date);
+                            let code = if serial.fract() == 0.0 { "yyyy-mm-dd" } else { "yyyy-mm-dd hh:mm:ss" };
+                            (serial, code)
+                        }
+                        _ => (datetime.as_f64(), "[h]:mm:ss"), // a duration
+                    };
+                    let cell = sheet.get_cell_mut(coordinate);
+                    cell.set_value_number(serial);
+                    cell.get_style_mut().get_number_format_mut().set_format_code(code);
+                }
+                Data::DateTimeIso(text) => match parse_iso_datetime(text) {
+                    Some((serial, code)) => {
+                        let cell = sheet.get_cell_mut(coordinate);
+                        cell.set_value_number(serial);
+                        cell.get_style_mut().get_number_format_mut().set_format_code(code);
+                    }
+                    None => {
+                        sheet.get_cell_mut(coordinate).set_value(text.clone());
+                    }
+                },
+                Data::String(text) | Data::DurationIso(text) => {
+                    sheet.get_cell_mut(coordinate).set_value(text.clone());
+                }
+                Data::Error(error) => {
+                    sheet.get_cell_mut(coordinate).set_value(error.to_string());
+                }
+            }
+        }
+    }
+
+    if book.get_sheet_count() == 0 {
+        let _ = book.new_sheet("Sheet1");
+    }
+    Ok(book)
+}
+
+/// ODS stores dates as ISO 8601 text (as do many CSV exports); convert them to an Excel
+/// serial and matching format code. The time may follow a `T` or a space.
+pub(crate) fn parse_iso_datetime(text: &str) -> Option<(f64, &'static str)> {
+    for pattern in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+        if let Ok(datetime) = chrono::NaiveDateTime::parse_from_str(text, pattern) {
+            return Some((excel_serial(datetime), "yyyy-mm-dd hh:mm:ss"));
+        }
+    }
+    let date = chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+    Some((excel_serial(date.and_hms_opt(0, 0, 0)?), "yyyy-mm-dd"))
+}
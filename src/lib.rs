@@ -0,0 +1,24 @@
+//! Terminal spreadsheet editor for XLSX files, usable as a library.
+//!
+//! The binary is a thin wrapper: it builds an [`App`] for a file, feeds it
+//! crossterm key/mouse events and calls [`draw`] every frame. An embedding
+//! application can do the same inside its own event loop:
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! let mut app = term_xlsx::App::new("book.xlsx".into(), false)?;
+//! # let mut terminal = ratatui::Terminal::new(ratatui::backend::TestBackend::new(80, 24))?;
+//! terminal.draw(|f| term_xlsx::draw(f, &mut app))?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod app;
+pub mod grid;
+pub mod import;
+mod osc52;
+pub mod search;
+pub mod ui;
+
+pub use app::{App, CellMark, Clipboard, Selection};
+pub use ui::draw;
@@ -1,50 +1,191 @@
 use anyhow::Result;
 use clap::Parser;
 use crossterm::{
+    cursor::Show,
     event::{DisableMouseCapture, EnableMouseCapture},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
-use std::{io, path::PathBuf, time::Duration};
-
-mod app;
-mod ui;
+use std::{
+    io::{self, IsTerminal, Stdout},
+    path::PathBuf,
+    time::Duration,
+};
 
-use app::App;
+use term_xlsx::app::{self, DateDisplay, EntryMove};
+use term_xlsx::{ui, App};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Terminal-based XLSX editor", long_about = None)]
 struct Args {
-    /// Path to the XLSX file (will be created if it doesn't exist)
-    path: PathBuf,
+    /// Paths to XLSX files (created if they don't exist); F7 cycles between them
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Open the file without allowing any edits or saves
+    #[arg(long)]
+    read_only: bool,
+
+    /// Copy the existing file to <path>.bak before every save
+    #[arg(long)]
+    backup: bool,
+
+    /// Save unsaved changes automatically every SECS seconds
+    #[arg(long, value_name = "SECS")]
+    autosave: Option<u64>,
+
+    /// Wrap left/right moves onto the previous/next row at the edge of the used range
+    #[arg(long)]
+    wrap: bool,
+
+    /// Start on this sheet (by name)
+    #[arg(long, value_name = "NAME")]
+    sheet: Option<String>,
+
+    /// Start with the cursor on this cell, e.g. B12
+    #[arg(long, value_name = "CELL")]
+    cell: Option<String>,
+
+    /// Keep the cursor from moving more than one row/column past the data
+    #[arg(long)]
+    constrain_to_data: bool,
+
+    /// Where Enter moves the cursor after an edit
+    #[arg(long, value_enum, value_name = "DIR", default_value_t = EntryMove::Down)]
+    enter_moves: EntryMove,
+
+    /// Where Tab moves the cursor after an edit
+    #[arg(long, value_enum, value_name = "DIR", default_value_t = EntryMove::Right)]
+    tab_moves: EntryMove,
+
+    /// After a run of Tab-committed edits, Enter goes back to the column the run started in
+    #[arg(long)]
+    return_on_enter: bool,
+
+    /// Show the terminal's own cursor on the active cell (for screen readers)
+    #[arg(long)]
+    terminal_cursor: bool,
+
+    /// Edit cells in a larger popup that word-wraps long text, instead of in the status bar
+    #[arg(long)]
+    edit_popup: bool,
+
+    /// Edit cells in the formula bar above the grid, instead of in the status bar
+    #[arg(long)]
+    edit_bar: bool,
+
+    /// Lay the grid out right to left (column A on the right, text right-aligned) for Arabic or Hebrew data
+    #[arg(long)]
+    rtl: bool,
+
+    /// Number the columns and show cell references in R1C1 style (toggle with #)
+    #[arg(long)]
+    r1c1: bool,
+
+    /// Freeze row 1 as a table header and style it apart from the data
+    #[arg(long)]
+    header: bool,
+
+    /// Load piped data as text, without turning number and date columns into numbers and dates
+    #[arg(long)]
+    as_text: bool,
+
+    /// Don't warn about charts, images, pivot tables or macros that may be lost on save
+    #[arg(long)]
+    no_lossy_warning: bool,
+
+    /// How often to check for input and background work when idle, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 250,
+        value_parser = clap::value_parser!(u64).range(10..=5000))]
+    poll_ms: u64,
+
+    /// Keep status messages for SECS seconds instead of clearing them on the next key
+    #[arg(long, value_name = "SECS")]
+    status_timeout: Option<u64>,
+
+    /// Width of columns the file doesn't size, in terminal columns
+    #[arg(long, value_name = "WIDTH", default_value_t = app::DEFAULT_COLUMN_WIDTH,
+        value_parser = clap::value_parser!(u16).range(app::MIN_COLUMN_WIDTH as i64..=app::COLUMN_WIDTH_LIMIT as i64))]
+    col_width: u16,
+
+    /// How much E/R widen or shrink a column per press
+    #[arg(long, value_name = "WIDTH", default_value_t = app::COLUMN_WIDTH_STEP,
+        value_parser = clap::value_parser!(u16).range(1..=app::COLUMN_WIDTH_LIMIT as i64))]
+    col_step: u16,
+
+    /// Widest a column may be shown or grow to
+    #[arg(long, value_name = "WIDTH", default_value_t = app::MAX_COLUMN_WIDTH,
+        value_parser = clap::value_parser!(u16).range(app::MIN_COLUMN_WIDTH as i64..=app::COLUMN_WIDTH_LIMIT as i64))]
+    max_col_width: u16,
+
+    /// How to show dates: iso (2024-03-31), us (03/31/2024), eu (31/03/2024) or preserve the file's own format
+    #[arg(long, value_enum, value_name = "STYLE", default_value_t = DateDisplay::Iso)]
+    date_format: DateDisplay,
+
+    /// Parse only the first sheet at startup and the others when first shown
+    #[arg(long)]
+    lazy: bool,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    if args.col_width > args.max_col_width {
+        anyhow::bail!("--col-width ({}) can't exceed --max-col-width ({})", args.col_width, args.max_col_width);
+    }
+
+    // Piped input has to be read before the terminal switches to raw mode
+    let piped = if io::stdin().is_terminal() {
+        None
+    } else {
+        Some(app::read_delimited_input(io::stdin().lock(), app::MAX_ROWS as usize)?)
+    };
 
-    // Setup terminal
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    // Setup terminal; it is restored when the guard drops, and before a panic message prints
+    install_panic_hook();
+    let mut guard = TerminalGuard::new()?;
 
     // Create app
-    let mut app = App::new(args.path)?;
+    let mut paths = args.paths.into_iter();
+    let mut app = App::new(paths.next().expect("clap requires at least one path"), args.lazy)?;
+    for path in paths {
+        app.open_workbook(path)?;
+    }
+    app.read_only = args.read_only;
+    if let Some(text) = piped.filter(|text| !text.trim().is_empty()) {
+        app.load_delimited_text(&text, !args.as_text);
+    }
+    app.backup = args.backup;
+    app.wrap_navigation = args.wrap;
+    app.constrain_to_data = args.constrain_to_data;
+    app.enter_moves = args.enter_moves;
+    app.tab_moves = args.tab_moves;
+    app.return_to_entry_column = args.return_on_enter;
+    app.terminal_cursor = args.terminal_cursor;
+    app.edit_popup = args.edit_popup;
+    app.edit_in_bar = args.edit_bar;
+    app.right_to_left = args.rtl;
+    app.r1c1 = args.r1c1;
+    app.default_column_width = args.col_width;
+    app.column_width_step = args.col_step;
+    app.max_column_width = args.max_col_width;
+    app.date_display = args.date_format;
+    if args.header {
+        app.enable_header_row();
+    }
+    app.open_at(args.sheet.as_deref(), args.cell.as_deref());
+    if !args.no_lossy_warning {
+        app.warn_lossy_features();
+    }
+    app.status_timeout = args.status_timeout.map(Duration::from_secs);
+    app.autosave_interval = args.autosave.filter(|&secs| secs > 0).map(Duration::from_secs);
 
     // Run app loop
-    let res = run_app(&mut terminal, &mut app);
-
-    // Restore terminal
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+    let res = run_app(&mut guard.terminal, &mut app, Duration::from_millis(args.poll_ms));
+    // Don't exit in the middle of writing a file
+    app.wait_for_pending_save();
 
+    drop(guard);
     if let Err(err) = res {
         println!("{:?}", err);
     }
@@ -52,16 +193,63 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
+/// Raw mode, the alternate screen and mouse capture, undone when dropped
+/// (including on early returns and while unwinding from a panic)
+struct TerminalGuard {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+}
+
+impl TerminalGuard {
+    fn new() -> Result<Self> {
+        enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen, EnableMouseCapture).inspect_err(|_| restore_terminal())?;
+        let terminal = Terminal::new(CrosstermBackend::new(stdout)).inspect_err(|_| restore_terminal())?;
+        Ok(Self { terminal })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+/// Put the terminal back in its normal state; safe to call more than once
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture, Show);
+}
+
+/// Restore the terminal before the default hook prints a panic, so the message is readable.
+/// Panics on other threads (a background save) are reported by the app and leave the UI running.
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().name() == Some("main") {
+            restore_terminal();
+        }
+        default_hook(info);
+    }));
+}
+
+fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, app: &mut App, poll_interval: Duration) -> Result<()> {
     loop {
-        terminal.draw(|f| ui::draw(f, app))?;
+        // Only redraw when input, a resize or background work changed something
+        if app.needs_redraw {
+            terminal.draw(|f| ui::draw(f, app))?;
+            app.needs_redraw = false;
+        }
 
-        if crossterm::event::poll(Duration::from_millis(250))? {
-            let event = crossterm::event::read()?;
-            if let crossterm::event::Event::Key(key) = event {
-                app.on_key(key);
+        if crossterm::event::poll(poll_interval)? {
+            match crossterm::event::read()? {
+                crossterm::event::Event::Key(key) => app.on_key(key),
+                crossterm::event::Event::Mouse(mouse) => app.on_mouse(mouse),
+                crossterm::event::Event::Resize(_, _) => app.needs_redraw = true,
+                _ => {}
             }
         }
+        app.on_tick();
 
         if app.should_quit {
             return Ok(());
@@ -0,0 +1,15 @@
+//! Copying text to the system clipboard through the terminal (OSC 52).
+//! Works over SSH and needs no platform clipboard library, but the terminal
+//! has to support it (most modern ones do; tmux needs `set-clipboard on`).
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::io::{self, Write};
+
+/// Ask the terminal to put `text` on the system clipboard
+pub fn copy(text: &str) -> Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", STANDARD.encode(text))?;
+    stdout.flush()?;
+    Ok(())
+}
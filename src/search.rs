@@ -0,0 +1,124 @@
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+
+/// Matching rules shared by find and replace
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    pub whole_cell: bool, // match only when the entire value equals the query
+    pub ignore_accents: bool, // "é" matches "e": diacritics are stripped before comparing
+}
+
+impl SearchOptions {
+    pub fn matches(&self, value: &str, query: &str) -> bool {
+        if self.is_blank(query) {
+            return false;
+        }
+        if self.whole_cell {
+            self.chars_equal(value, query)
+        } else {
+            self.find_at(value, query, 0).is_some()
+        }
+    }
+
+    /// Replace every match of `query` in `value`, returning the new text and the number of replacements
+    pub fn replace(&self, value: &str, query: &str, replacement: &str) -> (String, usize) {
+        if self.is_blank(query) {
+            return (value.to_string(), 0);
+        }
+        if self.whole_cell {
+            return if self.chars_equal(value, query) {
+                (replacement.to_string(), 1)
+            } else {
+                (value.to_string(), 0)
+            };
+        }
+
+        let mut result = String::new();
+        let mut count = 0;
+        let mut pos = 0;
+        while let Some((start, end)) = self.find_at(value, query, pos) {
+            result.push_str(&value[pos..start]);
+            result.push_str(replacement);
+            count += 1;
+            if end > start {
+                pos = end;
+                continue;
+            }
+            // Never match the same empty span twice: keep the next character and move past it
+            let Some(c) = value[start..].chars().next() else {
+                break;
+            };
+            result.push(c);
+            pos = start + c.len_utf8();
+        }
+        result.push_str(&value[pos..]);
+        (result, count)
+    }
+
+    /// Byte range of the first match starting at or after `from`
+    fn find_at(&self, value: &str, query: &str, from: usize) -> Option<(usize, usize)> {
+        if self.is_blank(query) {
+            return None;
+        }
+        value[from..].char_indices()
+            .filter(|&(_, c)| !self.is_ignored(c))
+            .find_map(|(offset, _)| {
+                let start = from + offset;
+                self.prefix_len(&value[start..], query).map(|len| (start, start + len))
+            })
+    }
+
+    /// Length in bytes of `text`'s prefix that matches `query`, if it does
+    fn prefix_len(&self, text: &str, query: &str) -> Option<usize> {
+        let mut text_chars = text.char_indices().filter(|&(_, c)| !self.is_ignored(c));
+        let mut len = 0;
+        for q in query.chars().filter(|&c| !self.is_ignored(c)) {
+            let (offset, t) = text_chars.next()?;
+            if !self.char_eq(t, q) {
+                return None;
+            }
+            len = offset + t.len_utf8();
+        }
+        // Accents written as separate combining marks belong to the last matched letter
+        if self.ignore_accents {
+            len += text[len..].chars().take_while(|&c| is_combining_mark(c)).map(char::len_utf8).sum::<usize>();
+        }
+        Some(len)
+    }
+
+    fn chars_equal(&self, a: &str, b: &str) -> bool {
+        let a: Vec<char> = a.chars().filter(|&c| !self.is_ignored(c)).collect();
+        let b: Vec<char> = b.chars().filter(|&c| !self.is_ignored(c)).collect();
+        a.len() == b.len() && a.iter().zip(&b).all(|(&x, &y)| self.char_eq(x, y))
+    }
+
+    fn char_eq(&self, a: char, b: char) -> bool {
+        let (a, b) = if self.ignore_accents { (base_letter(a), base_letter(b)) } else { (a, b) };
+        if self.case_sensitive {
+            a == b
+        } else {
+            a.to_lowercase().eq(b.to_lowercase())
+        }
+    }
+
+    /// Whether nothing of `query` is left to compare, e.g. only combining marks with accents ignored
+    fn is_blank(&self, query: &str) -> bool {
+        query.chars().all(|c| self.is_ignored(c))
+    }
+
+    /// Combining marks are skipped entirely when accents are ignored
+    fn is_ignored(&self, c: char) -> bool {
+        self.ignore_accents && is_combining_mark(c)
+    }
+}
+
+/// The letter an accented character is built on ("é" -> 'e'); other characters are returned as is
+fn base_letter(c: char) -> char {
+    let mut base = None;
+    decompose_canonical(c, |part| {
+        if base.is_none() && !is_combining_mark(part) {
+            base = Some(part);
+        }
+    });
+    base.unwrap_or(c)
+}
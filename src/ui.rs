@@ -1,104 +1,399 @@
 use ratatui::{
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Constraint, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, Cell, Clear, List, ListItem, Paragraph, Row, Table},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Scrollbar, ScrollbarOrientation, ScrollbarState, Table, Wrap},
     Frame,
 };
-use crate::app::{App, CellMark, Mode};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use crate::grid::{self, GridLayout};
+use crate::app::{mark_newlines, App, CellDisplay, CellMark, CellType, Mode, ReplaceField, Selection, SplitDirection, COLOR_PALETTE, MAX_COLUMNS, MAX_ROWS, NUMBER_FORMATS, ROW_NUMBER_WIDTH, SHEET_LIST_ROWS};
+
+/// Most lines a long status message wraps onto before it is cut off
+const MAX_STATUS_LINES: usize = 3;
+/// The header warns once a sheet's used rows or columns pass this share of the sheet limits
+const LIMIT_WARNING_PERCENT: u64 = 90;
+/// Most lines the formula bar grows to while a multi-line value is edited in it (--edit-bar)
+const MAX_EDIT_BAR_LINES: usize = 5;
+
+/// Every keybinding, grouped by category. Shown by the help overlay (F1 / ?).
+const KEYBINDINGS: &[(&str, &[(&str, &str)])] = &[
+    ("Navigation", &[
+        ("W/A/S/D, Arrows", "Move cursor"),
+        ("Enter / Shift+Enter", "Move down / up"),
+        ("Tab / Shift+Tab", "Move right / left"),
+        ("Home / End", "Column A / last used column"),
+        ("Ctrl+Home / Ctrl+End", "A1 / last used cell"),
+        ("Ctrl+Arrows", "Jump to edge of data block"),
+        ("Mouse", "Click to move, drag to select, wheel to scroll"),
+    ]),
+    ("Selection", &[
+        ("Shift+W/A/S/D, Shift+Arrows", "Extend selection"),
+        ("Ctrl+Shift+Arrows", "Extend selection to edge of data"),
+        ("Shift+Home / Shift+End", "Extend selection to column A / last column"),
+        ("Ctrl+Shift+Home / End", "Extend selection to A1 / last cell"),
+        ("Shift+Space", "Select row"),
+        ("Ctrl+Space", "Select column"),
+        ("Esc", "Clear selection"),
+    ]),
+    ("Editing", &[
+        ("F2", "Edit cell"),
+        ("Enter / Tab (editing)", "Save and move down / right"),
+        ("Alt+Enter (editing)", "Insert line break"),
+        ("Shift+F2", "View / edit cell comment"),
+        ("Ctrl+Enter", "Open cell hyperlink"),
+        ("Esc (editing)", "Cancel edit"),
+        ("Delete", "Clear selected cells"),
+        ("Ctrl+D / Ctrl+R", "Fill down / right (continues number series)"),
+        ("Ctrl+Alt+D / Ctrl+Alt+R", "Fill down / right by copying"),
+        ("Ctrl+Z", "Undo the last fill or replace-all"),
+        ("L / Shift+L", "Lock or unlock selection / protection on or off"),
+        (".", "Repeat last change"),
+        ("; / Alt+;", "Insert today's date / current date and time"),
+        ("Ctrl+F", "Find & replace"),
+        ("N", "Number format"),
+        ("> / <", "More / fewer decimal places"),
+    ]),
+    ("Clipboard", &[
+        ("C / F5", "Copy"),
+        ("X", "Cut (cleared on paste, Esc cancels)"),
+        ("V / F6", "Paste with formatting"),
+        ("Ctrl+V", "Paste values only"),
+        ("Shift+V", "Paste transposed"),
+        ("M", "Move selected block (arrows, Enter drops, Esc cancels)"),
+        ("Y", "Copy the cell / range reference (e.g. Sheet1!C5:E9)"),
+    ]),
+    ("View", &[
+        ("E / R", "Widen / narrow column"),
+        ("Shift+E", "Auto-fit column"),
+        ("Ctrl+E", "Auto-fit visible columns"),
+        ("H", "Cycle row height"),
+        ("( / )", "Hide selected rows / columns"),
+        ("Alt+( / Alt+)", "Show hidden rows / columns"),
+        ("F", "Freeze / unfreeze panes at cursor"),
+        ("Ctrl+`", "Show formulas / values"),
+        ("~", "Show the cursor cell's formula on / off"),
+        ("Ctrl+T", "Column totals footer on / off"),
+        ("Ctrl+L", "Zebra row shading on / off"),
+        ("#", "Numbered columns and R1C1 references on / off"),
+    ]),
+    ("Marking", &[
+        ("1", "Clear mark"),
+        ("2 / 3 / 4", "Yellow bg / red text / green text"),
+        ("5 / 6", "Blue bg / magenta text"),
+        ("P", "Color picker (background / text)"),
+        ("B / I / U", "Toggle bold / italic / underline"),
+    ]),
+    ("Sheets", &[
+        ("PageUp / PageDown", "Page up / down (Shift extends selection)"),
+        ("Ctrl+PageUp / Ctrl+PageDown", "Previous / next sheet"),
+        ("F4", "Sheet selector (type to jump to a sheet)"),
+        ("PageUp / PageDown (selector)", "Jump a page through the sheet list"),
+        ("Tab / Ctrl+V (selector)", "Show hidden sheets / hide or unhide sheet"),
+        ("Ctrl+T (selector)", "Set or remove the sheet's tab color"),
+        ("| / _", "Split side by side / stacked (again to close)"),
+        ("Ctrl+O", "Switch split pane"),
+        ("F7", "Next open workbook"),
+    ]),
+    ("File", &[
+        ("Ctrl+S", "Save"),
+        ("Ctrl+Shift+S", "Save as"),
+        (":", "Command line"),
+        ("Ctrl+W", "Quit"),
+        ("F9", "Recent status messages"),
+        ("F1 / ?", "This help"),
+    ]),
+];
 
 pub fn draw(f: &mut Frame, app: &mut App) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Length(3), // Header/Tabs
+            Constraint::Length(value_bar_height(app)), // Formula/value bar
             Constraint::Min(0),    // Grid
-            Constraint::Length(3), // Status/Input
+            Constraint::Length(if app.column_footer { 1 } else { 0 }), // Column totals
+            Constraint::Length(status_height(app, f.area().width)), // Status/Input
         ])
         .split(f.area());
 
     draw_header(f, app, chunks[0]);
-    draw_grid(f, app, chunks[1]);
-    draw_status(f, app, chunks[2]);
+    draw_value_bar(f, app, chunks[1]);
+    draw_grid_area(f, app, chunks[2]);
+    // Park the real terminal cursor on the active cell, for screen readers and cursor-following terminals
+    if app.terminal_cursor && app.mode == Mode::View
+        && let Some(rect) = grid::screen_rect_for_cell(app.workbook.cursor.0, app.workbook.cursor.1, &app.grid_layout)
+    {
+        f.set_cursor_position((rect.x, rect.y));
+    }
+    if app.column_footer {
+        draw_column_footer(f, app, chunks[3]);
+    }
+    draw_status(f, app, chunks[4]);
 
     // Draw sheet selection popup if in SheetSelect mode
     if app.mode == Mode::SheetSelect {
         draw_sheet_select_popup(f, app);
     }
+
+    // Draw number format popup if in Format mode
+    if app.mode == Mode::Format {
+        draw_format_popup(f);
+    }
+
+    // Draw color picker popup if in ColorPick mode
+    if app.mode == Mode::ColorPick {
+        draw_color_pick_popup(f, app);
+    }
+
+    // Draw recent status messages
+    if app.mode == Mode::MessageLog {
+        draw_message_log_popup(f, app);
+    }
+
+    if app.mode == Mode::Edit && app.edit_popup {
+        draw_edit_popup(f, app);
+    }
+
+    // Draw keybinding help overlay
+    if app.mode == Mode::Help {
+        draw_help_popup(f, app);
+    }
+
+    // Draw progress overlay while a background save runs
+    if app.pending_save.is_some() {
+        draw_saving_popup(f, app);
+    }
 }
 
 fn draw_header(f: &mut Frame, app: &App, area: Rect) {
-    let current_sheet_name = app.spreadsheet.get_sheet_collection()
-        .get(app.current_sheet_index)
+    let current_sheet_name = app.workbook.spreadsheet.get_sheet_collection()
+        .get(app.workbook.current_sheet_index)
         .map(|s| s.get_name().to_string())
         .unwrap_or_else(|| "???".to_string());
 
-    let sheet_count = app.spreadsheet.get_sheet_count();
-    let title = format!("File: {:?} | Sheet: {} ({}/{})",
-        app.path, current_sheet_name, app.current_sheet_index + 1, sheet_count);
+    let sheet_count = app.workbook.spreadsheet.get_sheet_count();
+    let read_only = if app.read_only { "[RO] " } else { "" };
+    let workbooks = if app.workbook_count() > 1 {
+        format!(" [{}/{}]", app.workbook_index + 1, app.workbook_count())
+    } else {
+        String::new()
+    };
+    let mut title = Line::from(format!("{}File: {:?} [{}]{} | Sheet: ", read_only, app.workbook.path, app.workbook.format.label(), workbooks));
+    if app.sheet_tab_color(app.workbook.current_sheet_index).is_some() {
+        title.push_span(tab_color_swatch(app, app.workbook.current_sheet_index));
+    }
+    title.push_span(format!("{} ({}/{})", current_sheet_name, app.workbook.current_sheet_index + 1, sheet_count));
+
+    // Used size of the sheet, with a warning as it nears the sheet limits
+    let (rows, cols) = (app.last_used_row(), app.last_used_col());
+    let mut line = if rows == 1 && cols == 1 && app.is_cell_blank(1, 1) {
+        Line::from(" Empty sheet")
+    } else {
+        Line::from(format!(" Used: {} rows × {} columns ({}:{})", rows, cols, app.cell_label(1, 1), app.cell_label(rows, cols)))
+    };
+    let fill = |used: u32, max: u32| used as u64 * 100 / max as u64;
+    let near_limit: Vec<String> = [(fill(rows, MAX_ROWS), MAX_ROWS, "row"), (fill(cols, MAX_COLUMNS), MAX_COLUMNS, "column")]
+        .into_iter()
+        .filter(|&(percent, _, _)| percent >= LIMIT_WARNING_PERCENT)
+        .map(|(percent, max, kind)| format!("{}% of the {}-{} limit", percent, max, kind))
+        .collect();
+    if !near_limit.is_empty() {
+        line.push_span(Span::styled(format!(" · {}", near_limit.join(", ")), Style::default().fg(Color::Yellow)));
+    }
 
     let block = Block::default().borders(Borders::ALL).title(title);
-    f.render_widget(block, area);
+    f.render_widget(Paragraph::new(line).block(block), area);
 }
 
-fn draw_grid(f: &mut Frame, app: &mut App, area: Rect) {
-    let block = Block::default().borders(Borders::ALL).title("Grid");
-    let inner = block.inner(area);
+/// Whether the cell being edited is edited in the formula bar rather than the status bar or popup
+fn editing_in_bar(app: &App) -> bool {
+    app.mode == Mode::Edit && app.edit_in_bar && !app.edit_popup
+}
 
-    // Calculate how many rows/cols we can fit
-    let row_num_width: u16 = 6; // Width for row numbers
-    let available_width = inner.width.saturating_sub(row_num_width);
-    let available_height = inner.height.saturating_sub(1); // -1 for header row
+/// Height of the formula bar: one line, or one per edited line while editing in it
+fn value_bar_height(app: &App) -> u16 {
+    if editing_in_bar(app) {
+        app.textarea.lines().len().clamp(1, MAX_EDIT_BAR_LINES) as u16
+    } else {
+        1
+    }
+}
 
-    // Calculate visible columns based on their widths
-    let start_col = app.scroll.1 + 1;
-    let mut num_cols = 0u32;
-    let mut used_width: u16 = 0;
-    loop {
-        let col_idx = start_col + num_cols;
-        let col_width = app.get_column_width(col_idx) + 1; // +1 for spacing
-        if used_width + col_width > available_width {
-            break;
-        }
-        used_width += col_width;
-        num_cols += 1;
-        if num_cols >= 50 {
-            break; // Safety limit
+/// One-line bar showing the untruncated content of the cursor cell, like Excel's formula bar.
+/// With --edit-bar the edit box replaces the content while a cell is edited.
+fn draw_value_bar(f: &mut Frame, app: &mut App, area: Rect) {
+    let cell_ref = app.cell_label(app.workbook.cursor.0, app.workbook.cursor.1);
+    let label = Line::from(vec![
+        Span::styled(format!(" {:<7}", cell_ref), Style::default().add_modifier(Modifier::BOLD)),
+        Span::raw("│ "),
+    ]);
+    if editing_in_bar(app) {
+        let label_width = label.width() as u16;
+        let [label_area, edit_area] = Layout::horizontal([Constraint::Length(label_width), Constraint::Min(0)]).areas(area);
+        f.render_widget(Paragraph::new(label), label_area);
+        app.textarea.set_block(Block::default());
+        f.render_widget(&app.textarea, edit_area);
+        return;
+    }
+    let mut line = label;
+    // Locked cells are flagged, brighter while protection is on
+    if app.is_cell_locked(app.workbook.cursor.1, app.workbook.cursor.0) {
+        let color = if app.protect_locked { Color::Yellow } else { Color::DarkGray };
+        line.push_span(Span::styled("[locked] ", Style::default().fg(color)));
+    }
+    line.push_span(Span::raw(mark_newlines(&app.current_cell_full_value())));
+    f.render_widget(Paragraph::new(line), area);
+}
+
+/// Sum and count of the numbers in the cursor's column
+fn draw_column_footer(f: &mut Frame, app: &App, area: Rect) {
+    let col = app.workbook.cursor.1;
+    let stats = app.column_stats(col);
+    let mut text = format!(" Column {}: Count {} Numbers {}", app.column_label(col), stats.count, stats.numeric_count);
+    if let Some(avg) = stats.average() {
+        text.push_str(&format!(" Sum {} Avg {:.2}", stats.sum, avg));
+    }
+    f.render_widget(Paragraph::new(text).style(Style::default().fg(Color::Cyan)), area);
+}
+
+/// Draw the grid, or both panes of a split view
+fn draw_grid_area(f: &mut Frame, app: &mut App, area: Rect) {
+    let Some(split) = app.split else {
+        draw_grid(f, app, area, None);
+        return;
+    };
+    let direction = match split.direction {
+        SplitDirection::Vertical => Direction::Horizontal,
+        SplitDirection::Horizontal => Direction::Vertical,
+    };
+    let panes = Layout::default()
+        .direction(direction)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+    let focused = app.focused_pane;
+
+    // The unfocused pane is drawn first with its own view swapped in, so the
+    // viewport size and layout left behind for scrolling and mouse are the focused pane's
+    app.swap_split_view();
+    draw_grid(f, app, panes[1 - focused], Some(false));
+    app.swap_split_view();
+    draw_grid(f, app, panes[focused], Some(true));
+}
+
+/// Draw one grid pane; `focused` is `None` when the grid isn't split
+fn draw_grid(f: &mut Frame, app: &mut App, area: Rect, focused: Option<bool>) {
+    let mut title = if app.workbook.frozen_rows > 0 || app.workbook.frozen_cols > 0 {
+        format!("Grid (frozen at {})", app.cell_label(app.workbook.frozen_rows + 1, app.workbook.frozen_cols + 1))
+    } else {
+        "Grid".to_string()
+    };
+    let mut block = Block::default().borders(Borders::ALL);
+    if let Some(focused) = focused {
+        title.push_str(&format!(" {}", app.cell_label(app.workbook.cursor.0, app.workbook.cursor.1)));
+        if focused {
+            block = block.border_style(Style::default().fg(Color::Yellow));
         }
     }
-    num_cols = num_cols.max(1);
+    let inner = block.inner(area);
 
-    let num_rows = (available_height as u32).max(1);
+    // Calculate how many rows/cols we can fit
+    let available_height = inner.height.saturating_sub(1); // -1 for header row
+    let row_height = app.row_display_height.max(1);
+    let num_rows = ((available_height / row_height) as u32).max(1);
+    let visible_rows: Vec<u32> = app.visible_rows().take(num_rows as usize).collect();
 
-    // Update viewport size for scroll calculations
-    app.viewport_size = (num_rows as u16, num_cols as u16);
+    // Row numbers get wider for the largest one shown, keeping room for the hidden-row marker
+    let digits = visible_rows.last().map_or(1, |row| row.to_string().len()) as u16;
+    let row_num_width = ROW_NUMBER_WIDTH.max(digits + 1);
+    let available_width = inner.width.saturating_sub(row_num_width);
 
-    let start_row = app.scroll.0 + 1;
+    // Calculate visible columns based on their widths (frozen columns first, hidden ones left out)
+    let columns = grid::visible_columns(app.workbook.frozen_cols, app.workbook.scroll.1, MAX_COLUMNS, available_width,
+        |col| app.shown_column_width(col), |col| app.is_column_hidden(col));
+    let num_cols = columns.len() as u32;
+
+    // Right to left: columns run from the right edge leftward, with the row numbers after them
+    let rtl = app.right_to_left;
+    let screen_columns: Vec<u32> = if rtl { columns.iter().rev().copied().collect() } else { columns.clone() };
+    let table_area = if rtl {
+        let used_width = row_num_width + columns.iter().map(|&col| app.shown_column_width(col) + 1).sum::<u16>();
+        let leftover = inner.width.saturating_sub(used_width);
+        Rect::new(inner.x + leftover, inner.y, inner.width - leftover, inner.height)
+    } else {
+        inner
+    };
+
+    // Update viewport size and layout for scroll and mouse calculations
+    app.viewport_size = (num_rows as u16, num_cols as u16);
+    app.grid_layout = GridLayout {
+        origin: (table_area.x, table_area.y),
+        row_number_width: row_num_width,
+        row_height,
+        columns: screen_columns.iter().map(|&col| (col, app.shown_column_width(col))).collect(),
+        rows: visible_rows.clone(),
+        row_numbers_right: rtl,
+    };
+
+    // Visible range and the extent of the data, e.g. "A1:J40 of 500x26"
+    let (used_rows, used_cols) = (app.last_used_row(), app.last_used_col());
+    let first_row = visible_rows.first().copied().unwrap_or(1);
+    let last_row = visible_rows.last().copied().unwrap_or(1);
+    let last_col = columns.last().copied().unwrap_or(1);
+    title.push_str(&format!(" {}:{} of {}x{}",
+        app.cell_label(first_row, columns[0]), app.cell_label(last_row, last_col), used_rows, used_cols));
+    let block = block.title(title);
 
     let mut rows = Vec::new();
+    let references = app.cursor_formula_references();
+
+    // Headers covered by the selection are highlighted so the cursor position is easy to spot
+    let (sel_min_row, sel_min_col, sel_max_row, sel_max_col) = app.workbook.selection.bounds();
+    let header_style = |highlighted: bool| {
+        if highlighted {
+            Style::default().bg(Color::Blue).fg(Color::White).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().add_modifier(Modifier::BOLD)
+        }
+    };
+
+    // A + in front of a header marks hidden lines just before it
+    let hidden_marker = |gap: bool| Span::styled(if gap { "+" } else { "" }, Style::default().fg(Color::Yellow));
 
     // Header row (Column letters)
-    let mut header_cells = vec![Cell::from("     ")];
-    for c in 0..num_cols {
-        let col_idx = start_col + c;
-        let col_letter = number_to_column(col_idx);
-        header_cells.push(Cell::from(col_letter).style(Style::default().add_modifier(Modifier::BOLD)));
+    let label_width = (row_num_width - 1) as usize;
+    let mut header_cells = vec![Cell::from(" ".repeat(label_width))];
+    for &col_idx in &screen_columns {
+        let gap = col_idx > 1 && app.is_column_hidden(col_idx - 1);
+        let highlighted = (sel_min_col..=sel_max_col).contains(&col_idx);
+        let label = Line::from(vec![hidden_marker(gap), Span::raw(app.column_label(col_idx))]);
+        header_cells.push(Cell::from(label).style(header_style(highlighted)));
+    }
+    if rtl {
+        header_cells.rotate_left(1);
     }
     rows.push(Row::new(header_cells));
 
-    for r in 0..num_rows {
-        let row_idx = start_row + r;
-        let mut row_cells = vec![Cell::from(format!("{:>5}", row_idx)).style(Style::default().add_modifier(Modifier::BOLD))];
-
-        for c in 0..num_cols {
-            let col_idx = start_col + c;
-            let value = app.get_cell_display(col_idx, row_idx);
+    for &row_idx in &visible_rows {
+        let gap = row_idx > 1 && app.is_row_hidden(row_idx - 1);
+        let highlighted = (sel_min_row..=sel_max_row).contains(&row_idx);
+        let number = format!("{:>width$}", row_idx, width = label_width - gap as usize);
+        let label = Line::from(vec![hidden_marker(gap), Span::raw(number)]);
+        let mut row_cells = vec![Cell::from(label).style(header_style(highlighted))];
+
+        for &col_idx in &screen_columns {
+            let display = match app.move_preview_source(row_idx, col_idx) {
+                Some((row, col)) => app.get_cell_display(col, row),
+                None => CellDisplay { text: String::new(), truncated: false, color: None },
+            };
 
-            let is_cursor = row_idx == app.cursor.0 && col_idx == app.cursor.1;
-            let is_selected = app.selection.contains(row_idx, col_idx);
+            let is_cursor = row_idx == app.workbook.cursor.0 && col_idx == app.workbook.cursor.1;
+            let is_selected = app.workbook.selection.contains(row_idx, col_idx);
+            let is_referenced = references.iter().any(|range| range.contains(row_idx, col_idx));
             let is_formula = app.is_formula_cell(col_idx, row_idx);
             let mark = app.get_cell_mark(row_idx, col_idx);
 
-            // Build style: cursor > selection > formula > mark > default
+            // Build style: cursor > selection > formula reference > formula > mark > default
             let style = if is_cursor {
                 if is_formula {
                     // Formula cell under cursor: blue bg + italic
@@ -112,118 +407,386 @@ fn draw_grid(f: &mut Frame, app: &mut App, area: Rect) {
                 } else {
                     Style::default().bg(Color::DarkGray).fg(Color::White)
                 }
+            } else if is_referenced {
+                // Cells the formula under the cursor depends on
+                Style::default().bg(Color::Rgb(70, 40, 80)).fg(Color::LightMagenta)
             } else if is_formula {
                 // Formula cells: gray background + italic to indicate read-only
                 Style::default().bg(Color::Rgb(60, 60, 60)).fg(Color::Cyan).add_modifier(Modifier::ITALIC)
             } else {
+                // Header and zebra shading sit underneath marks, so background marks still win
+                let mut base = if app.header_row && row_idx == 1 {
+                    Style::default().bg(Color::Rgb(45, 45, 70)).add_modifier(Modifier::BOLD)
+                } else if app.zebra && row_idx.is_multiple_of(2) {
+                    Style::default().bg(Color::Rgb(35, 35, 45))
+                } else {
+                    Style::default()
+                };
+                // A color from the number format (e.g. red negatives) gives way to text marks
+                if let Some(argb) = display.color {
+                    base = base.fg(argb_color(argb));
+                }
                 match mark {
-                    CellMark::None => Style::default(),
-                    CellMark::YellowBg => Style::default().bg(Color::Yellow).fg(Color::Black),
-                    CellMark::RedText => Style::default().fg(Color::Red),
-                    CellMark::GreenText => Style::default().fg(Color::Green),
-                    CellMark::BlueBg => Style::default().bg(Color::LightBlue).fg(Color::Black),
-                    CellMark::MagentaText => Style::default().fg(Color::Magenta),
+                    CellMark::None => base,
+                    CellMark::YellowBg => base.bg(Color::Yellow).fg(Color::Black),
+                    CellMark::RedText => base.fg(Color::Red),
+                    CellMark::GreenText => base.fg(Color::Green),
+                    CellMark::BlueBg => base.bg(Color::LightBlue).fg(Color::Black),
+                    CellMark::MagentaText => base.fg(Color::Magenta),
+                    CellMark::Custom { argb, is_bg: true } => base.bg(argb_color(argb)).fg(contrast_color(argb)),
+                    CellMark::Custom { argb, is_bg: false } => base.fg(argb_color(argb)),
                 }
             };
 
-            row_cells.push(Cell::from(value).style(style));
+            // Font emphasis and hyperlinks are layered on top of whatever else styles the cell
+            let emphasis = app.cell_emphasis(col_idx, row_idx);
+            let mut style = style;
+            if emphasis.bold {
+                style = style.add_modifier(Modifier::BOLD);
+            }
+            if emphasis.italic {
+                style = style.add_modifier(Modifier::ITALIC);
+            }
+            if emphasis.underline || app.hyperlink_url(col_idx, row_idx).is_some() {
+                style = style.add_modifier(Modifier::UNDERLINED);
+            }
+            // A pending cut is dimmed until it is pasted or cancelled
+            if app.cut_source.is_some_and(|(sheet, range)| sheet == app.workbook.current_sheet_index && range.contains(row_idx, col_idx)) {
+                style = style.add_modifier(Modifier::DIM);
+            }
+
+            let text = cell_text(display.text, display.truncated);
+            let text = if rtl { text.right_aligned() } else { text };
+            row_cells.push(Cell::from(text).style(style));
+        }
+        if rtl {
+            row_cells.rotate_left(1);
         }
-        rows.push(Row::new(row_cells));
+        rows.push(Row::new(row_cells).height(row_height));
     }
 
     // Build dynamic column widths
     let mut widths = vec![Constraint::Length(row_num_width)];
-    for c in 0..num_cols {
-        let col_idx = start_col + c;
-        let width = app.get_column_width(col_idx);
-        widths.push(Constraint::Length(width));
+    for &col_idx in &screen_columns {
+        widths.push(Constraint::Length(app.shown_column_width(col_idx)));
+    }
+    if rtl {
+        widths.rotate_left(1);
     }
 
     let table = Table::new(rows, widths)
-        .block(block)
         .column_spacing(1);
 
-    f.render_widget(table, area);
+    f.render_widget(block, area);
+    f.render_widget(table, table_area);
+
+    // Scrollbars on the right and bottom borders, only once the data (or the view) runs past the screen.
+    // Their content length counts scroll positions, so the thumb reaches the end at the last one.
+    let scrolled_rows = num_rows.saturating_sub(app.workbook.frozen_rows);
+    let row_extent = used_rows.max(last_row).saturating_sub(app.workbook.frozen_rows);
+    if row_extent > scrolled_rows || app.workbook.scroll.0 > 0 {
+        let mut state = ScrollbarState::new(row_extent.saturating_sub(scrolled_rows).max(app.workbook.scroll.0) as usize + 1)
+            .position(app.workbook.scroll.0 as usize)
+            .viewport_content_length(scrolled_rows as usize);
+        let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight).begin_symbol(None).end_symbol(None);
+        f.render_stateful_widget(scrollbar, area.inner(Margin { vertical: 1, horizontal: 0 }), &mut state);
+    }
+    let scrolled_cols = num_cols.saturating_sub(app.workbook.frozen_cols);
+    let col_extent = used_cols.max(last_col).saturating_sub(app.workbook.frozen_cols);
+    if col_extent > scrolled_cols || app.workbook.scroll.1 > 0 {
+        let mut state = ScrollbarState::new(col_extent.saturating_sub(scrolled_cols).max(app.workbook.scroll.1) as usize + 1)
+            .position(app.workbook.scroll.1 as usize)
+            .viewport_content_length(scrolled_cols as usize);
+        let scrollbar = Scrollbar::new(ScrollbarOrientation::HorizontalBottom).begin_symbol(None).end_symbol(None);
+        f.render_stateful_widget(scrollbar, area.inner(Margin { vertical: 0, horizontal: 1 }), &mut state);
+    }
 }
 
-fn draw_status(f: &mut Frame, app: &mut App, area: Rect) {
+/// Cell content for the grid; when it was cut short, the trailing `~` overflow marker
+/// is dimmed so it can't be mistaken for a tilde in the value
+fn cell_text(value: String, truncated: bool) -> Text<'static> {
+    if !truncated {
+        return Text::from(value);
+    }
+    let mut lines: Vec<Line> = value.split('\n').map(|line| Line::from(line.to_string())).collect();
+    if let Some(last) = lines.pop() {
+        let content = last.to_string();
+        lines.push(match content.strip_suffix('~') {
+            Some(kept) => Line::from(vec![
+                Span::raw(kept.to_string()),
+                Span::styled("~", Style::default().fg(Color::DarkGray).add_modifier(Modifier::DIM)),
+            ]),
+            None => last,
+        });
+    }
+    Text::from(lines)
+}
+
+/// Text of the status bar in modes without their own prompt; `None` in prompt modes
+fn status_text(app: &App) -> Option<String> {
     match app.mode {
-        Mode::View | Mode::SheetSelect => {
+        Mode::View | Mode::SheetSelect | Mode::Format | Mode::ColorPick | Mode::Help | Mode::MessageLog => {
             // Show status message if present, otherwise show help
             let text = if let Some(ref msg) = app.status_message {
                 msg.clone()
             } else {
                 // Build cell reference
-                let cell_ref = format!("{}{}",
-                    number_to_column(app.cursor.1),
-                    app.cursor.0
-                );
+                let cell_ref = app.cell_label(app.workbook.cursor.0, app.workbook.cursor.1);
 
                 // Show selection info if multi-cell
-                let sel_info = if !app.selection.is_single() {
-                    let (r1, c1, r2, c2) = app.selection.bounds();
-                    format!(" [{}{}:{}{}]",
-                        number_to_column(c1), r1,
-                        number_to_column(c2), r2
-                    )
+                let sel_info = if !app.workbook.selection.is_single() {
+                    let (r1, c1, r2, c2) = app.workbook.selection.bounds();
+                    let stats = app.selection_stats();
+                    let mut info = format!(" [{}:{}] Count: {}",
+                        app.cell_label(r1, c1),
+                        app.cell_label(r2, c2),
+                        stats.count
+                    );
+                    if let Some(avg) = stats.average() {
+                        info.push_str(&format!(" Sum: {} Avg: {:.2}", stats.sum, avg));
+                    }
+                    info
                 } else {
                     String::new()
                 };
 
-                format!("{}{} | ^W:Quit ^S:Save | WASD:Move | C/V:Copy/Paste | F2:Edit | F4:Sheets",
-                    cell_ref, sel_info)
+                // Comments are otherwise invisible, so note them for the cursor cell
+                let comment = app.comment_text(app.workbook.cursor.1, app.workbook.cursor.0)
+                    .map(|text| format!(" [Comment: {}]", text.replace('\n', " ")))
+                    .unwrap_or_default();
+                let link = app.hyperlink_url(app.workbook.cursor.1, app.workbook.cursor.0)
+                    .map(|url| format!(" [Link: {} (Ctrl+Enter opens)]", url))
+                    .unwrap_or_default();
+
+                let cell_type = match app.cell_type(app.workbook.cursor.1, app.workbook.cursor.0) {
+                    CellType::Empty => String::new(),
+                    cell_type => format!(" Type: {}", cell_type.label()),
+                };
+
+                format!("{}{}{}{}{} | ^W:Quit ^S:Save | WASD:Move | C/V:Copy/Paste | F2:Edit | F4:Sheets",
+                    cell_ref, cell_type, link, comment, sel_info)
             };
+            Some(text)
+        }
+        _ => None,
+    }
+}
 
-            let p = Paragraph::new(text).block(Block::default().borders(Borders::ALL));
+/// Height of the status area: long status text wraps onto up to `MAX_STATUS_LINES` lines
+fn status_height(app: &App, width: u16) -> u16 {
+    let inner_width = width.saturating_sub(2).max(1) as usize;
+    let lines = status_text(app)
+        .map(|text| text.width().div_ceil(inner_width).clamp(1, MAX_STATUS_LINES))
+        .unwrap_or(1);
+    lines as u16 + 2 // + borders
+}
+
+fn draw_status(f: &mut Frame, app: &mut App, area: Rect) {
+    if let Some(text) = status_text(app) {
+        let p = Paragraph::new(text)
+            .wrap(Wrap { trim: true })
+            .block(Block::default().borders(Borders::ALL));
+        f.render_widget(p, area);
+        return;
+    }
+    match app.mode {
+        // Drawn from status_text above
+        Mode::View | Mode::SheetSelect | Mode::Format | Mode::ColorPick | Mode::Help | Mode::MessageLog => {}
+        Mode::Move => {
+            let range = |selection: Selection| {
+                let (r1, c1, r2, c2) = selection.bounds();
+                format!("{}:{}", app.cell_label(r1, c1), app.cell_label(r2, c2))
+            };
+            let from = app.moving.map(range).unwrap_or_default();
+            let p = Paragraph::new(format!("Moving {} to {} — Arrows/WASD:Move, Enter:Drop here, Esc:Cancel", from, range(app.workbook.selection)))
+                .style(Style::default().fg(Color::Yellow))
+                .block(Block::default().borders(Borders::ALL).title("Move"));
+            f.render_widget(p, area);
+        }
+        Mode::ConfirmQuit => {
+            let prompt = if app.read_only {
+                "Unsaved changes (read-only, can't be saved) — y to quit, Esc to cancel"
+            } else {
+                "Unsaved changes — y to quit, s to save & quit, Esc to cancel"
+            };
+            let p = Paragraph::new(prompt)
+                .style(Style::default().fg(Color::Yellow))
+                .block(Block::default().borders(Borders::ALL).title("Quit"));
+            f.render_widget(p, area);
+        }
+        Mode::Command => {
+            app.textarea.set_block(Block::default().borders(Borders::ALL).title("Command (Enter:Run, Esc:Cancel)"));
+            f.render_widget(&app.textarea, area);
+        }
+        Mode::Replace => {
+            let state = &app.replace;
+            let prompt = match state.field {
+                ReplaceField::Find => format!("Find (replace with \"{}\")", state.replacement),
+                ReplaceField::Replace => format!("Replace \"{}\" with", state.query),
+            };
+            let scope = if state.scope.is_some() { "selection" } else { "sheet" };
+            let toggle = |on: bool| if on { "on" } else { "off" };
+            let title = format!("{} in {} | Case:{} Whole:{} Accents:{} | Tab:Field Enter:Next ^A:All Alt+C/W/A:Toggle Esc:Close",
+                prompt, scope, toggle(state.options.case_sensitive), toggle(state.options.whole_cell),
+                if state.options.ignore_accents { "ignored" } else { "exact" });
+            app.textarea.set_block(Block::default().borders(Borders::ALL).title(title));
+            f.render_widget(&app.textarea, area);
+        }
+        Mode::SaveAs => {
+            app.textarea.set_block(Block::default().borders(Borders::ALL).title("Save As (Enter:Save, Esc:Cancel)"));
+            f.render_widget(&app.textarea, area);
+        }
+        Mode::ConfirmOverwrite => {
+            let target = app.pending_save_path.as_ref().map(|p| p.display().to_string()).unwrap_or_default();
+            let p = Paragraph::new(format!("{} already exists — overwrite? (y/n)", target))
+                .style(Style::default().fg(Color::Yellow))
+                .block(Block::default().borders(Borders::ALL).title("Save As"));
+            f.render_widget(p, area);
+        }
+        Mode::ConfirmDiskChange => {
+            let text = match &app.workbook.imported_from {
+                Some(source) => format!("{} already exists — y to overwrite it, r to import {} again (discarding your changes), Esc to cancel",
+                    app.workbook.path.display(), source.display()),
+                None => format!("{} changed on disk since it was read — y to overwrite, r to reload (discarding your changes), Esc to cancel",
+                    app.workbook.path.display()),
+            };
+            let p = Paragraph::new(text)
+                .style(Style::default().fg(Color::Yellow))
+                .block(Block::default().borders(Borders::ALL).title("Save"));
+            f.render_widget(p, area);
+        }
+        Mode::Comment => {
+            let title = format!("Comment on {} (Enter:Save, Alt+Enter:New line, Esc:Cancel; empty removes)",
+                app.cell_label(app.workbook.cursor.0, app.workbook.cursor.1));
+            app.textarea.set_block(Block::default().borders(Borders::ALL).title(title));
+            f.render_widget(&app.textarea, area);
+        }
+        // The popup editor is drawn over the grid by draw_edit_popup
+        Mode::Edit if app.edit_popup => {
+            let p = Paragraph::new(format!("Editing {} in the popup", app.cell_label(app.workbook.cursor.0, app.workbook.cursor.1)))
+                .block(Block::default().borders(Borders::ALL));
+            f.render_widget(p, area);
+        }
+        // The edit box is drawn in the formula bar by draw_value_bar
+        Mode::Edit if app.edit_in_bar => {
+            let p = Paragraph::new(edit_title(app)).block(Block::default().borders(Borders::ALL));
             f.render_widget(p, area);
         }
         Mode::Edit => {
-            app.textarea.set_block(Block::default().borders(Borders::ALL).title("Editing (Enter:Save+Down, Tab:Save+Right, Esc:Cancel)"));
+            app.textarea.set_block(Block::default().borders(Borders::ALL).title(edit_title(app)));
             f.render_widget(&app.textarea, area);
         }
     }
 }
 
-fn number_to_column(n: u32) -> String {
-    let mut n = n;
-    let mut result = String::new();
-    while n > 0 {
-        n -= 1;
-        let remainder = n % 26;
-        let char_code = (remainder as u8) + b'A';
-        result.insert(0, char_code as char);
-        n /= 26;
+/// Title of the edit box: the cell, whether a formula is being typed, and the keys
+fn edit_title(app: &App) -> String {
+    let is_formula = app.textarea.lines().first().is_some_and(|line| line.len() > 1 && line.starts_with('='));
+    format!("Editing {}{} (Enter/Tab:Save, Alt+Enter:New line, Esc:Cancel)",
+        app.cell_label(app.workbook.cursor.0, app.workbook.cursor.1), if is_formula { " [formula]" } else { "" })
+}
+
+/// Edit box in a centered popup with long lines word-wrapped (--edit-popup). The text is drawn
+/// from the textarea's lines, which still handle the keys, and the terminal cursor marks the caret.
+fn draw_edit_popup(f: &mut Frame, app: &App) {
+    let area = f.area();
+    let popup_width = area.width.saturating_sub(8).clamp(20, 100).min(area.width);
+    let inner_width = popup_width.saturating_sub(2).max(1) as usize;
+    let (rows, (cursor_row, cursor_x)) = wrap_for_editing(app.textarea.lines(), app.textarea.cursor(), inner_width);
+
+    let max_height = area.height.saturating_sub(4).max(3);
+    let popup_height = (rows.len() as u16 + 2).clamp(5, max_height).min(area.height);
+    let visible_rows = popup_height.saturating_sub(2).max(1) as usize;
+    let scroll = (cursor_row + 1).saturating_sub(visible_rows);
+
+    let x = area.x + (area.width.saturating_sub(popup_width)) / 2;
+    let y = area.y + (area.height.saturating_sub(popup_height)) / 2;
+    let popup_area = Rect::new(x, y, popup_width, popup_height);
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default().borders(Borders::ALL).title(edit_title(app));
+    let inner = block.inner(popup_area);
+    let text: Vec<Line> = rows.into_iter().map(Line::from).collect();
+    f.render_widget(Paragraph::new(text).scroll((scroll as u16, 0)).block(block), popup_area);
+
+    let cursor_x = (cursor_x as u16).min(inner.width.saturating_sub(1));
+    f.set_cursor_position((inner.x + cursor_x, inner.y + (cursor_row - scroll) as u16));
+}
+
+/// Word-wrap the edited lines to `width` columns, breaking after spaces where possible.
+/// Also returns where the textarea cursor (line, char) ends up as (wrapped row, column).
+pub fn wrap_for_editing(lines: &[String], cursor: (usize, usize), width: usize) -> (Vec<String>, (usize, usize)) {
+    let mut rows = Vec::new();
+    let mut at = (0, 0);
+    for (line_index, line) in lines.iter().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut start = 0;
+        loop {
+            let mut end = start;
+            let mut used = 0;
+            while end < chars.len() {
+                let char_width = chars[end].width().unwrap_or(0);
+                if used + char_width > width {
+                    break;
+                }
+                used += char_width;
+                end += 1;
+            }
+            if end < chars.len() {
+                if let Some(space) = chars[start..end].iter().rposition(|&c| c == ' ').filter(|&space| space > 0) {
+                    end = start + space + 1;
+                } else if end == start {
+                    end += 1; // a character wider than the box
+                }
+            }
+            let last = end >= chars.len();
+            if line_index == cursor.0 && cursor.1 >= start && (cursor.1 < end || last) {
+                at = (rows.len(), chars[start..cursor.1.min(chars.len())].iter().map(|c| c.width().unwrap_or(0)).sum());
+            }
+            rows.push(chars[start..end].iter().collect());
+            if last {
+                break;
+            }
+            start = end;
+        }
     }
-    result
+    (rows, at)
 }
 
 fn draw_sheet_select_popup(f: &mut Frame, app: &App) {
     let sheet_names = app.get_sheet_names();
-    let count = sheet_names.len();
+    let listed = app.listed_sheets();
+    let count = listed.len();
 
     // Calculate popup size
     let max_name_len = sheet_names.iter().map(|s| s.len()).max().unwrap_or(10);
-    let popup_width = (max_name_len + 6).max(20) as u16; // Add padding for border and marker
-    let popup_height = (count + 2).min(15) as u16; // +2 for border, max 15 lines
+    let popup_width = (max_name_len + 17).max(86) as u16; // Add padding for border, marker and swatch; wide enough for the title
+    let popup_height = (count.min(SHEET_LIST_ROWS) + 2) as u16; // +2 for border
 
     // Center the popup
     let area = f.area();
     let x = area.x + (area.width.saturating_sub(popup_width)) / 2;
     let y = area.y + (area.height.saturating_sub(popup_height)) / 2;
-    let popup_area = Rect::new(x, y, popup_width, popup_height);
+    let popup_area = Rect::new(x, y, popup_width.min(area.width), popup_height.min(area.height));
 
     // Clear the area behind the popup
     f.render_widget(Clear, popup_area);
 
     // Build list items
-    let items: Vec<ListItem> = sheet_names
+    let items: Vec<ListItem> = listed
         .iter()
-        .enumerate()
-        .map(|(i, name)| {
-            let marker = if i == app.current_sheet_index { "*" } else { " " };
-            let display = format!("{} {}", marker, name);
+        .map(|&i| {
+            let marker = if i == app.workbook.current_sheet_index { "* " } else { "  " };
+            let hidden = app.is_sheet_hidden(i);
+            let display = Line::from(vec![
+                Span::raw(marker),
+                tab_color_swatch(app, i),
+                Span::raw(format!("{}{}", sheet_names[i], if hidden { " (hidden)" } else { "" })),
+            ]);
             let style = if i == app.sheet_select_index {
                 Style::default().bg(Color::Blue).fg(Color::White)
+            } else if hidden {
+                Style::default().fg(Color::DarkGray)
             } else {
                 Style::default()
             };
@@ -231,10 +794,204 @@ fn draw_sheet_select_popup(f: &mut Frame, app: &App) {
         })
         .collect();
 
+    let title = if app.sheet_filter.is_empty() {
+        let reveal = if app.show_hidden_sheets { "Hide hidden" } else { "Show hidden" };
+        format!("Select Sheet (Type:Jump, Tab:{}, ^V:Hide/Unhide, ^T:Tab color, Enter, Esc)", reveal)
+    } else if app.filtered_sheet_index().is_some() {
+        format!("Select Sheet [{}] (Esc:Clear)", app.sheet_filter)
+    } else {
+        format!("Select Sheet [{}] no match (Esc:Clear)", app.sheet_filter)
+    };
+    // Stateful rendering keeps the highlighted sheet scrolled into view
+    let selected = listed.iter().position(|&i| i == app.sheet_select_index);
+    let mut block = Block::default().borders(Borders::ALL).title(title);
+    if let Some(position) = selected.filter(|_| count > SHEET_LIST_ROWS) {
+        block = block.title_bottom(format!(" {}/{} (PgUp/PgDn, Home/End) ", position + 1, count));
+    }
+    let list = List::new(items).block(block);
+    let mut state = ListState::default().with_selected(selected);
+    f.render_stateful_widget(list, popup_area, &mut state);
+
+    // A scrollbar on the right border once the list is longer than the popup
+    if count > SHEET_LIST_ROWS {
+        let mut scroll_state = ScrollbarState::new(count).position(selected.unwrap_or(0));
+        let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight).begin_symbol(None).end_symbol(None);
+        f.render_stateful_widget(scrollbar, popup_area.inner(Margin { vertical: 1, horizontal: 0 }), &mut scroll_state);
+    }
+}
+
+fn draw_format_popup(f: &mut Frame) {
+    let items: Vec<ListItem> = NUMBER_FORMATS
+        .iter()
+        .enumerate()
+        .map(|(i, (label, _))| ListItem::new(format!("{}: {}", i + 1, label)))
+        .collect();
+
+    let popup_width = 36;
+    let popup_height = (items.len() + 2) as u16; // +2 for border
+
+    // Center the popup
+    let area = f.area();
+    let x = area.x + (area.width.saturating_sub(popup_width)) / 2;
+    let y = area.y + (area.height.saturating_sub(popup_height)) / 2;
+    let popup_area = Rect::new(x, y, popup_width.min(area.width), popup_height.min(area.height));
+
+    f.render_widget(Clear, popup_area);
+
+    let list = List::new(items)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title("Number Format (1-6:Apply, Esc:Cancel)"));
+
+    f.render_widget(list, popup_area);
+}
+
+fn argb_color(argb: u32) -> Color {
+    Color::Rgb((argb >> 16) as u8, (argb >> 8) as u8, argb as u8)
+}
+
+/// Black or white, whichever reads better on the given background
+fn contrast_color(argb: u32) -> Color {
+    let (r, g, b) = ((argb >> 16) & 0xFF, (argb >> 8) & 0xFF, argb & 0xFF);
+    if r * 299 + g * 587 + b * 114 > 128_000 { Color::Black } else { Color::White }
+}
+
+/// A colored square for a sheet with a tab color, blank space otherwise
+fn tab_color_swatch(app: &App, sheet: usize) -> Span<'static> {
+    match app.sheet_tab_color(sheet) {
+        Some(argb) => Span::styled("■ ", Style::default().fg(argb_color(argb))),
+        None => Span::raw("  "),
+    }
+}
+
+fn draw_color_pick_popup(f: &mut Frame, app: &App) {
+    let items: Vec<ListItem> = COLOR_PALETTE
+        .iter()
+        .enumerate()
+        .map(|(i, &(label, argb))| {
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{:X}: ", i)),
+                Span::styled("  ", Style::default().bg(argb_color(argb))),
+                Span::raw(format!(" {}", label)),
+            ]))
+        })
+        .collect();
+
+    let popup_width = 44;
+    let popup_height = (items.len() + 2) as u16; // +2 for border
+
+    // Center the popup
+    let area = f.area();
+    let x = area.x + (area.width.saturating_sub(popup_width)) / 2;
+    let y = area.y + (area.height.saturating_sub(popup_height)) / 2;
+    let popup_area = Rect::new(x, y, popup_width.min(area.width), popup_height.min(area.height));
+
+    f.render_widget(Clear, popup_area);
+
+    let title = match app.tab_color_sheet {
+        Some(_) => "Tab Color (0-F:Apply, Del:Remove, Esc)".to_string(),
+        None => format!("{} Color (0-F:Apply, Tab:Switch, Esc)", if app.color_pick_bg { "Background" } else { "Text" }),
+    };
     let list = List::new(items)
         .block(Block::default()
             .borders(Borders::ALL)
-            .title("Select Sheet (W/S:Move, Enter:Select, Esc:Cancel)"));
+            .title(title));
 
     f.render_widget(list, popup_area);
 }
+
+fn draw_message_log_popup(f: &mut Frame, app: &App) {
+    let area = f.area();
+    let popup_width = area.width.saturating_sub(8).min(100);
+    let popup_height = area.height.saturating_sub(4).min(30);
+
+    // Newest first, so the latest messages are visible without scrolling
+    let items: Vec<ListItem> = if app.message_log.is_empty() {
+        vec![ListItem::new("No messages yet")]
+    } else {
+        app.message_log.iter().rev().map(|msg| ListItem::new(msg.clone())).collect()
+    };
+
+    // Center the popup
+    let x = area.x + (area.width.saturating_sub(popup_width)) / 2;
+    let y = area.y + (area.height.saturating_sub(popup_height)) / 2;
+    let popup_area = Rect::new(x, y, popup_width, popup_height);
+
+    f.render_widget(Clear, popup_area);
+
+    let list = List::new(items)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title("Messages, newest first (any key to close)"));
+
+    f.render_widget(list, popup_area);
+}
+
+fn draw_saving_popup(f: &mut Frame, app: &App) {
+    const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+    let Some(save) = &app.pending_save else {
+        return;
+    };
+    let frame = (save.started.elapsed().as_millis() / 250) as usize % SPINNER.len();
+    let text = format!(" {} Saving {} ...", SPINNER[frame], save.path.display());
+
+    let popup_width = (text.chars().count() as u16 + 4).min(f.area().width);
+    let popup_height = 3;
+
+    // Center the popup
+    let area = f.area();
+    let x = area.x + (area.width.saturating_sub(popup_width)) / 2;
+    let y = area.y + (area.height.saturating_sub(popup_height)) / 2;
+    let popup_area = Rect::new(x, y, popup_width, popup_height.min(area.height));
+
+    f.render_widget(Clear, popup_area);
+
+    let p = Paragraph::new(text)
+        .style(Style::default().fg(Color::Yellow))
+        .block(Block::default().borders(Borders::ALL));
+
+    f.render_widget(p, popup_area);
+}
+
+fn draw_help_popup(f: &mut Frame, app: &mut App) {
+    let key_width = KEYBINDINGS.iter()
+        .flat_map(|(_, bindings)| bindings.iter().map(|(key, _)| key.len()))
+        .max()
+        .unwrap_or(10);
+
+    let mut lines = Vec::new();
+    for (i, (section, bindings)) in KEYBINDINGS.iter().enumerate() {
+        if i > 0 {
+            lines.push(Line::from(""));
+        }
+        lines.push(Line::from(Span::styled(*section, Style::default().add_modifier(Modifier::BOLD).fg(Color::Yellow))));
+        for (key, action) in bindings.iter() {
+            lines.push(Line::from(vec![
+                Span::styled(format!("  {:<width$}  ", key, width = key_width), Style::default().fg(Color::Cyan)),
+                Span::raw(*action),
+            ]));
+        }
+    }
+
+    // Size the popup to the content, leaving a margin around it
+    let area = f.area();
+    let popup_width = ((key_width + 50) as u16).min(area.width.saturating_sub(4));
+    let popup_height = ((lines.len() + 2) as u16).min(area.height.saturating_sub(2));
+    let x = area.x + (area.width.saturating_sub(popup_width)) / 2;
+    let y = area.y + (area.height.saturating_sub(popup_height)) / 2;
+    let popup_area = Rect::new(x, y, popup_width, popup_height);
+
+    // Keep the scroll position within the content
+    let max_scroll = (lines.len() as u16).saturating_sub(popup_height.saturating_sub(2));
+    app.help_scroll = app.help_scroll.min(max_scroll);
+
+    f.render_widget(Clear, popup_area);
+
+    let help = Paragraph::new(lines)
+        .scroll((app.help_scroll, 0))
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title("Help (Up/Down:Scroll, any other key:Close)"));
+
+    f.render_widget(help, popup_area);
+}
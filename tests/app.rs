@@ -0,0 +1,1317 @@
+//! Drive `App` with synthetic key events and check the resulting state and saved files.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::layout::Rect;
+use std::path::{Path, PathBuf};
+use term_xlsx::app::{
+    infer_column_types, number_to_column, parse_cell_ref, parse_delimited, parse_input_to_cell_value, parse_r1c1_ref,
+    read_delimited_input, sheet_reference, shift_formula, truncate_to_width, unique_keys, CellType, ColumnType, EntryMove,
+    InputValue, Mode, COLOR_PALETTE, MAX_COLUMNS, MAX_ROWS, SHEET_LIST_ROWS,
+};
+use term_xlsx::grid::{self, GridLayout};
+use term_xlsx::search::SearchOptions;
+use term_xlsx::ui::wrap_for_editing;
+use term_xlsx::{App, CellMark, Selection};
+
+/// A workbook path in the temp directory, removed again when the test ends
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("term-xlsx-{}-{}.xlsx", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        TempFile(path)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn open(file: &TempFile) -> App<'static> {
+    App::new(file.path().to_path_buf(), false).expect("open workbook")
+}
+
+fn press(app: &mut App, code: KeyCode) {
+    app.on_key(KeyEvent::new(code, KeyModifiers::NONE));
+}
+
+fn press_with(app: &mut App, code: KeyCode, modifiers: KeyModifiers) {
+    app.on_key(KeyEvent::new(code, modifiers));
+}
+
+/// Type `text` into the cursor cell through edit mode and commit it with Enter
+fn enter_value(app: &mut App, text: &str) {
+    press(app, KeyCode::F(2));
+    assert_eq!(app.mode, Mode::Edit);
+    for c in text.chars() {
+        press(app, KeyCode::Char(c));
+    }
+    press(app, KeyCode::Enter);
+}
+
+fn save(app: &mut App) {
+    press_with(app, KeyCode::Char('s'), KeyModifiers::CONTROL);
+    app.wait_for_pending_save();
+    assert!(!app.workbook.dirty, "save failed: {:?}", app.status_message);
+}
+
+fn place_cursor(app: &mut App, row: u32, col: u32) {
+    app.workbook.cursor = (row, col);
+    app.workbook.selection = Selection::single(row, col);
+}
+
+#[test]
+fn move_cursor_in_every_direction() {
+    let file = TempFile::new("move");
+    let mut app = open(&file);
+    let moves = [
+        (KeyCode::Up, (4, 5)),
+        (KeyCode::Down, (6, 5)),
+        (KeyCode::Left, (5, 4)),
+        (KeyCode::Right, (5, 6)),
+        (KeyCode::Char('w'), (4, 5)),
+        (KeyCode::Char('s'), (6, 5)),
+        (KeyCode::Char('a'), (5, 4)),
+        (KeyCode::Char('d'), (5, 6)),
+    ];
+
+    for (code, expected) in moves {
+        place_cursor(&mut app, 5, 5);
+        press(&mut app, code);
+        assert_eq!(app.workbook.cursor, expected, "{:?}", code);
+        assert_eq!(app.workbook.selection, Selection::single(expected.0, expected.1), "{:?}", code);
+    }
+
+    for (code, expected) in moves {
+        // Shift turns WASD into capitals, as terminals report them
+        let code = match code {
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_uppercase()),
+            code => code,
+        };
+        place_cursor(&mut app, 5, 5);
+        press_with(&mut app, code, KeyModifiers::SHIFT);
+        assert_eq!(app.workbook.cursor, expected, "Shift+{:?}", code);
+        assert_eq!(app.workbook.selection, Selection { start: (5, 5), end: expected }, "Shift+{:?}", code);
+    }
+}
+
+#[test]
+fn home_and_end_extend_the_selection_with_shift() {
+    let file = TempFile::new("home-end");
+    let mut app = open(&file);
+    place_cursor(&mut app, 3, 4);
+    enter_value(&mut app, "last");
+    // The used range now ends at D3
+    let jumps = [
+        (KeyCode::Home, KeyModifiers::NONE, (2, 1)),
+        (KeyCode::End, KeyModifiers::NONE, (2, 4)),
+        (KeyCode::Home, KeyModifiers::CONTROL, (1, 1)),
+        (KeyCode::End, KeyModifiers::CONTROL, (3, 4)),
+    ];
+
+    for (code, modifiers, expected) in jumps {
+        place_cursor(&mut app, 2, 2);
+        press_with(&mut app, code, modifiers);
+        assert_eq!(app.workbook.cursor, expected, "{:?} {:?}", modifiers, code);
+        assert_eq!(app.workbook.selection, Selection::single(expected.0, expected.1), "{:?} {:?}", modifiers, code);
+
+        place_cursor(&mut app, 2, 2);
+        press_with(&mut app, code, modifiers | KeyModifiers::SHIFT);
+        assert_eq!(app.workbook.cursor, expected, "Shift {:?} {:?}", modifiers, code);
+        assert_eq!(app.workbook.selection, Selection { start: (2, 2), end: expected }, "Shift {:?} {:?}", modifiers, code);
+    }
+}
+
+#[test]
+fn move_cursor_stops_at_sheet_edge() {
+    let file = TempFile::new("edge");
+    let mut app = open(&file);
+    press(&mut app, KeyCode::Up);
+    press(&mut app, KeyCode::Left);
+    assert_eq!(app.workbook.cursor, (1, 1));
+    assert_eq!(app.workbook.selection, Selection::single(1, 1));
+
+    // The far corner is XFD1048576, as in XLSX
+    place_cursor(&mut app, MAX_ROWS, MAX_COLUMNS);
+    press(&mut app, KeyCode::Down);
+    press(&mut app, KeyCode::Right);
+    assert_eq!(app.workbook.cursor, (MAX_ROWS, MAX_COLUMNS));
+}
+
+#[test]
+fn column_letters_at_the_boundaries() {
+    for (number, letters) in [(1, "A"), (26, "Z"), (27, "AA"), (256, "IV"), (702, "ZZ"), (703, "AAA"), (16384, "XFD")] {
+        assert_eq!(number_to_column(number), letters);
+        assert_eq!(parse_cell_ref(&format!("{}1", letters)), Some((1, number)));
+    }
+    assert_eq!(parse_cell_ref("XFD1048576"), Some((MAX_ROWS, MAX_COLUMNS)));
+    assert_eq!(parse_cell_ref("XFE1"), None);
+    assert_eq!(parse_cell_ref("A1048577"), None);
+}
+
+#[test]
+fn r1c1_references_are_accepted_and_shown() {
+    assert_eq!(parse_r1c1_ref("R5C3"), Some((5, 3)));
+    assert_eq!(parse_r1c1_ref("r1048576c16384"), Some((MAX_ROWS, MAX_COLUMNS)));
+    assert_eq!(parse_r1c1_ref("R0C1"), None);
+    assert_eq!(parse_r1c1_ref("RC"), None);
+    assert_eq!(parse_r1c1_ref("C5"), None);
+    assert_eq!(parse_cell_ref("R5C3"), None);
+
+    let file = TempFile::new("r1c1");
+    let mut app = open(&file);
+    place_cursor(&mut app, 5, 3);
+    assert_eq!(app.cell_label(5, 3), "C5");
+    assert!(app.execute_command("goto R2C2").is_err());
+    press(&mut app, KeyCode::Char('#'));
+    assert_eq!(app.cell_label(5, 3), "R5C3");
+    assert_eq!(app.column_label(28), "28");
+
+    // Typed references follow the style on screen
+    app.execute_command("goto R2C4").unwrap();
+    assert_eq!(app.workbook.cursor, (2, 4));
+    assert!(app.execute_command("goto C5").is_err());
+}
+
+#[test]
+fn edit_and_save() {
+    let file = TempFile::new("edit");
+    let mut app = open(&file);
+    enter_value(&mut app, "hello");
+    assert_eq!(app.workbook.cursor, (2, 1));
+    assert_eq!(app.get_cell_text(1, 1), "hello");
+    assert!(app.workbook.dirty);
+    save(&mut app);
+
+    let reopened = open(&file);
+    assert_eq!(reopened.get_cell_text(1, 1), "hello");
+    assert!(!reopened.workbook.dirty);
+}
+
+#[test]
+fn copy_and_paste() {
+    let file = TempFile::new("paste");
+    let mut app = open(&file);
+    enter_value(&mut app, "a");
+    enter_value(&mut app, "b");
+
+    press_with(&mut app, KeyCode::Home, KeyModifiers::CONTROL);
+    press_with(&mut app, KeyCode::Down, KeyModifiers::SHIFT);
+    assert_eq!(app.workbook.selection.bounds(), (1, 1, 2, 1));
+    press(&mut app, KeyCode::Char('c'));
+
+    press(&mut app, KeyCode::Right);
+    press(&mut app, KeyCode::Right);
+    press(&mut app, KeyCode::Char('v'));
+    assert_eq!(app.get_cell_text(3, 1), "a");
+    assert_eq!(app.get_cell_text(3, 2), "b");
+    // The source is left alone by a copy
+    assert_eq!(app.get_cell_text(1, 1), "a");
+}
+
+#[test]
+fn marks_survive_save_and_reopen() {
+    let file = TempFile::new("marks");
+    let mut app = open(&file);
+    let marks = [
+        ('2', CellMark::YellowBg),
+        ('3', CellMark::RedText),
+        ('4', CellMark::GreenText),
+        ('5', CellMark::BlueBg),
+        ('6', CellMark::MagentaText),
+    ];
+
+    for (col, (key, mark)) in (1..).zip(marks) {
+        place_cursor(&mut app, 1, col);
+        enter_value(&mut app, "x");
+        place_cursor(&mut app, 1, col);
+        press(&mut app, KeyCode::Char(key));
+        assert_eq!(app.workbook.cell_marks.get(&(0, 1, col)), Some(&mark));
+    }
+    // Picker colors outside Excel's palette: Red as a background, Orange as text
+    let custom_bg = CellMark::Custom { argb: 0xFFE53935, is_bg: true };
+    let custom_text = CellMark::Custom { argb: 0xFFFB8C00, is_bg: false };
+    place_cursor(&mut app, 1, 6);
+    enter_value(&mut app, "x");
+    place_cursor(&mut app, 1, 6);
+    press(&mut app, KeyCode::Char('p'));
+    press(&mut app, KeyCode::Char('4'));
+    place_cursor(&mut app, 1, 7);
+    enter_value(&mut app, "x");
+    place_cursor(&mut app, 1, 7);
+    press(&mut app, KeyCode::Char('p'));
+    press(&mut app, KeyCode::Tab);
+    press(&mut app, KeyCode::Char('5'));
+    assert_eq!(app.workbook.cell_marks.get(&(0, 1, 6)), Some(&custom_bg));
+    assert_eq!(app.workbook.cell_marks.get(&(0, 1, 7)), Some(&custom_text));
+    save(&mut app);
+
+    // Palette colors are written as indexed colors, anything else as ARGB
+    let book = umya_spreadsheet::reader::xlsx::read(file.path()).unwrap();
+    let sheet = book.get_sheet(&0).unwrap();
+    let style = |col: u32| sheet.get_cell((col, 1)).unwrap().get_style();
+    let fill = |col: u32| style(col).get_fill().unwrap().get_pattern_fill().unwrap()
+        .get_foreground_color().unwrap();
+    let font = |col: u32| style(col).get_font().unwrap().get_color();
+    // Yellow fill with black text
+    assert_eq!(*fill(1).get_indexed(), 13);
+    assert_eq!(*font(1).get_indexed(), 8);
+    // Red, green and magenta text
+    assert_eq!(*font(2).get_indexed(), 10);
+    assert_eq!(*font(3).get_indexed(), 17);
+    assert_eq!(*font(5).get_indexed(), 14);
+    // Blue fill with white text
+    assert_eq!(*fill(4).get_indexed(), 12);
+    assert_eq!(*font(4).get_indexed(), 9);
+    assert_eq!(fill(6).get_argb(), "FFE53935");
+    assert_eq!(font(7).get_argb(), "FFFB8C00");
+
+    let reopened = open(&file);
+    for (col, (_, mark)) in (1..).zip(marks) {
+        assert_eq!(reopened.workbook.cell_marks.get(&(0, 1, col)), Some(&mark), "column {}", col);
+    }
+    assert_eq!(reopened.workbook.cell_marks.get(&(0, 1, 6)), Some(&custom_bg));
+    assert_eq!(reopened.workbook.cell_marks.get(&(0, 1, 7)), Some(&custom_text));
+}
+
+#[test]
+fn clearing_a_mark_removes_it() {
+    let file = TempFile::new("unmark");
+    let mut app = open(&file);
+    press(&mut app, KeyCode::Char('2'));
+    assert_eq!(app.workbook.cell_marks.get(&(0, 1, 1)), Some(&CellMark::YellowBg));
+    press(&mut app, KeyCode::Char('1'));
+    assert!(app.workbook.cell_marks.get(&(0, 1, 1)).is_none_or(|&mark| mark == CellMark::None));
+}
+
+#[test]
+fn clearing_emphasis_and_marks_leaves_other_styles_alone() {
+    let file = TempFile::new("clear-styles");
+    let mut book = umya_spreadsheet::new_file();
+    {
+        let sheet = book.get_sheet_mut(&0).unwrap();
+        let head = sheet.get_cell_mut((1, 1));
+        head.set_value("head");
+        head.get_style_mut().get_font_mut().set_bold(true);
+        let styled = sheet.get_cell_mut((2, 2));
+        styled.set_value_number(0.25);
+        let style = styled.get_style_mut();
+        style.get_font_mut().set_italic(true);
+        style.get_font_mut().set_size(14.0);
+        style.get_number_format_mut().set_format_code("0.00%");
+    }
+    umya_spreadsheet::writer::xlsx::write(&book, file.path()).unwrap();
+
+    // Bold off and marks cleared over A1:C3, then one unrelated edit
+    let mut app = open(&file);
+    press_with(&mut app, KeyCode::Down, KeyModifiers::SHIFT);
+    press_with(&mut app, KeyCode::Down, KeyModifiers::SHIFT);
+    press_with(&mut app, KeyCode::Right, KeyModifiers::SHIFT);
+    press_with(&mut app, KeyCode::Right, KeyModifiers::SHIFT);
+    press(&mut app, KeyCode::Char('b'));
+    press(&mut app, KeyCode::Char('1'));
+    place_cursor(&mut app, 5, 4);
+    enter_value(&mut app, "edited");
+    save(&mut app);
+
+    let book = umya_spreadsheet::reader::xlsx::read(file.path()).unwrap();
+    let sheet = book.get_sheet(&0).unwrap();
+    assert!(!*sheet.get_cell((1, 1)).unwrap().get_style().get_font().unwrap().get_bold());
+    let style = sheet.get_cell((2, 2)).unwrap().get_style();
+    let font = style.get_font().unwrap();
+    assert!(*font.get_italic());
+    assert!(!*font.get_bold());
+    assert_eq!(*font.get_size(), 14.0);
+    assert_eq!(style.get_number_format().unwrap().get_format_code(), "0.00%");
+    // Empty cells in the range were never given a style of their own
+    for coordinate in [(3, 1), (1, 2), (3, 3)] {
+        assert!(sheet.get_cell(coordinate).is_none(), "{:?}", coordinate);
+    }
+}
+
+#[test]
+fn untouched_styles_survive_an_edit() {
+    let file = TempFile::new("styles");
+    let mut book = umya_spreadsheet::new_file();
+    {
+        let sheet = book.get_sheet_mut(&0).unwrap();
+        let cell = sheet.get_cell_mut((2, 2));
+        cell.set_value_number(1.5);
+        let style = cell.get_style_mut();
+        style.get_font_mut().set_bold(true);
+        style.get_font_mut().set_italic(true);
+        style.get_number_format_mut().set_format_code("0.000");
+    }
+    umya_spreadsheet::writer::xlsx::write(&book, file.path()).unwrap();
+
+    let mut app = open(&file);
+    enter_value(&mut app, "edited");
+    // Clearing marks over a range that includes the styled cell must not touch it
+    press_with(&mut app, KeyCode::Home, KeyModifiers::CONTROL);
+    press_with(&mut app, KeyCode::Down, KeyModifiers::SHIFT);
+    press_with(&mut app, KeyCode::Right, KeyModifiers::SHIFT);
+    press(&mut app, KeyCode::Char('1'));
+    save(&mut app);
+
+    let book = umya_spreadsheet::reader::xlsx::read(file.path()).unwrap();
+    let sheet = book.get_sheet(&0).unwrap();
+    assert_eq!(sheet.get_cell_value((1, 1)).get_value(), "edited");
+    let style = sheet.get_cell((2, 2)).unwrap().get_style();
+    let font = style.get_font().unwrap();
+    assert!(*font.get_bold());
+    assert!(*font.get_italic());
+    assert_eq!(style.get_number_format().unwrap().get_format_code(), "0.000");
+}
+
+#[test]
+fn sheet_switching_keeps_each_sheets_cursor() {
+    let file = TempFile::new("sheets");
+    let mut book = umya_spreadsheet::new_file();
+    book.new_sheet("Second").unwrap().get_cell_mut((1, 1)).set_value("on second");
+    umya_spreadsheet::writer::xlsx::write(&book, file.path()).unwrap();
+
+    let mut app = open(&file);
+    assert_eq!(app.workbook.current_sheet_index, 0);
+    press(&mut app, KeyCode::Down);
+    press(&mut app, KeyCode::Right);
+    assert_eq!(app.workbook.cursor, (2, 2));
+
+    press_with(&mut app, KeyCode::PageDown, KeyModifiers::CONTROL);
+    assert_eq!(app.workbook.current_sheet_index, 1);
+    assert_eq!(app.get_cell_text(1, 1), "on second");
+
+    press_with(&mut app, KeyCode::PageUp, KeyModifiers::CONTROL);
+    assert_eq!(app.workbook.current_sheet_index, 0);
+    assert_eq!(app.workbook.cursor, (2, 2));
+}
+
+#[test]
+fn a_split_follows_the_sheet_switched_to() {
+    let file = TempFile::new("split-sheets");
+    let mut book = umya_spreadsheet::new_file();
+    book.new_sheet("Second").unwrap();
+    umya_spreadsheet::writer::xlsx::write(&book, file.path()).unwrap();
+
+    let mut app = open(&file);
+    press(&mut app, KeyCode::Char('|'));
+    press_with(&mut app, KeyCode::Char('o'), KeyModifiers::CONTROL);
+    for _ in 0..40 {
+        press(&mut app, KeyCode::Down);
+    }
+    press_with(&mut app, KeyCode::Char('o'), KeyModifiers::CONTROL);
+    assert_eq!(app.workbook.cursor, (1, 1));
+
+    // The other pane starts where the new sheet opens instead of keeping row 41 of the old one
+    press_with(&mut app, KeyCode::PageDown, KeyModifiers::CONTROL);
+    assert_eq!(app.workbook.current_sheet_index, 1);
+    press_with(&mut app, KeyCode::Char('o'), KeyModifiers::CONTROL);
+    assert_eq!(app.workbook.cursor, (1, 1));
+    assert_eq!(app.workbook.scroll, (0, 0));
+}
+
+#[test]
+fn clearing_removes_cells_from_the_used_range() {
+    let file = TempFile::new("clear");
+    let mut app = open(&file);
+    enter_value(&mut app, "a");
+    enter_value(&mut app, "b");
+    assert_eq!(app.last_used_row(), 2);
+
+    press(&mut app, KeyCode::Up);
+    press(&mut app, KeyCode::Delete);
+    assert!(app.is_cell_blank(1, 2));
+    assert_eq!(app.last_used_row(), 1);
+
+    // Committing an empty edit doesn't leave an empty-string cell behind
+    press(&mut app, KeyCode::F(2));
+    press(&mut app, KeyCode::Enter);
+    assert_eq!(app.last_used_row(), 1);
+}
+
+#[test]
+fn clearing_a_formatted_cell_keeps_only_its_style() {
+    let file = TempFile::new("clear-styled");
+    let mut book = umya_spreadsheet::new_file();
+    {
+        let cell = book.get_sheet_mut(&0).unwrap().get_cell_mut((1, 1));
+        cell.set_value("bold");
+        cell.get_style_mut().get_font_mut().set_bold(true);
+        cell.get_style_mut().get_font_mut().set_size(14.0);
+        cell.get_style_mut().get_alignment_mut().set_wrap_text(true);
+    }
+    umya_spreadsheet::writer::xlsx::write(&book, file.path()).unwrap();
+
+    let mut app = open(&file);
+    press(&mut app, KeyCode::Delete);
+    assert!(app.is_cell_blank(1, 1));
+    save(&mut app);
+
+    let book = umya_spreadsheet::reader::xlsx::read(file.path()).unwrap();
+    let cell = book.get_sheet(&0).unwrap().get_cell((1, 1)).unwrap();
+    assert!(*cell.get_style().get_font().unwrap().get_bold());
+    assert_eq!(*cell.get_style().get_font().unwrap().get_size(), 14.0);
+    assert!(*cell.get_style().get_alignment().unwrap().get_wrap_text());
+    assert_eq!(cell.get_value(), "");
+    assert_ne!(cell.get_data_type(), "s", "an empty shared string was written");
+}
+
+#[test]
+fn moving_a_block_relocates_its_contents() {
+    let file = TempFile::new("move-block");
+    let mut app = open(&file);
+    enter_value(&mut app, "a");
+    enter_value(&mut app, "b");
+    app.clipboard.data = vec![vec!["kept".to_string()]];
+
+    press_with(&mut app, KeyCode::Home, KeyModifiers::CONTROL);
+    press_with(&mut app, KeyCode::Down, KeyModifiers::SHIFT);
+    press(&mut app, KeyCode::Char('m'));
+    assert_eq!(app.mode, Mode::Move);
+    press(&mut app, KeyCode::Right);
+    press(&mut app, KeyCode::Down);
+    assert_eq!(app.workbook.selection.bounds(), (2, 2, 3, 2));
+    press(&mut app, KeyCode::Enter);
+
+    assert_eq!(app.mode, Mode::View);
+    assert_eq!(app.get_cell_text(2, 2), "a");
+    assert_eq!(app.get_cell_text(2, 3), "b");
+    assert!(app.is_cell_blank(1, 1));
+    assert!(app.is_cell_blank(1, 2));
+    // The move doesn't replace what was copied before
+    assert_eq!(app.clipboard.data, vec![vec!["kept".to_string()]]);
+}
+
+#[test]
+fn decimals_that_cannot_change_leave_the_workbook_clean() {
+    let file = TempFile::new("decimals");
+    let mut app = open(&file);
+    enter_value(&mut app, "1.5");
+    save(&mut app);
+
+    // General has no fixed decimals to remove
+    press(&mut app, KeyCode::Up);
+    press(&mut app, KeyCode::Char('<'));
+    assert!(!app.workbook.dirty);
+    assert_eq!(app.status_message.as_deref(), Some("Number format unchanged"));
+    assert_eq!(app.get_cell_text(1, 1), "1.5");
+
+    press(&mut app, KeyCode::Char('>'));
+    assert!(app.workbook.dirty);
+    press(&mut app, KeyCode::Char('<'));
+    press(&mut app, KeyCode::Char('<'));
+    save(&mut app);
+
+    // Already at no decimals
+    press(&mut app, KeyCode::Char('<'));
+    assert!(!app.workbook.dirty);
+    assert_eq!(app.status_message.as_deref(), Some("Number format unchanged"));
+}
+
+#[test]
+fn starting_a_move_cancels_a_pending_cut_with_a_warning() {
+    let file = TempFile::new("move-after-cut");
+    let mut app = open(&file);
+    enter_value(&mut app, "a");
+    press(&mut app, KeyCode::Up);
+    press(&mut app, KeyCode::Char('x'));
+    assert!(app.cut_source.is_some());
+
+    press(&mut app, KeyCode::Down);
+    press(&mut app, KeyCode::Char('m'));
+    assert_eq!(app.mode, Mode::Move);
+    assert!(app.cut_source.is_none());
+    assert!(app.status_message.as_deref().is_some_and(|message| message.contains("cut cancelled")));
+}
+
+#[test]
+fn cancelling_a_move_leaves_the_block_in_place() {
+    let file = TempFile::new("move-cancel");
+    let mut app = open(&file);
+    enter_value(&mut app, "a");
+    press(&mut app, KeyCode::Up);
+    press(&mut app, KeyCode::Char('m'));
+    press(&mut app, KeyCode::Right);
+    press(&mut app, KeyCode::Esc);
+
+    assert_eq!(app.mode, Mode::View);
+    assert_eq!(app.workbook.cursor, (1, 1));
+    assert_eq!(app.get_cell_text(1, 1), "a");
+    assert!(app.is_cell_blank(2, 1));
+}
+
+#[test]
+fn hidden_columns_are_skipped_and_saved() {
+    let file = TempFile::new("hide");
+    let mut app = open(&file);
+    press(&mut app, KeyCode::Char(')'));
+    assert!(app.is_column_hidden(1));
+    // The cursor leaves the hidden column and can't step back into it
+    assert_eq!(app.workbook.cursor, (1, 2));
+    press(&mut app, KeyCode::Left);
+    assert_eq!(app.workbook.cursor, (1, 2));
+    save(&mut app);
+
+    let mut reopened = open(&file);
+    assert!(reopened.is_column_hidden(1));
+    press_with(&mut reopened, KeyCode::Char(')'), KeyModifiers::ALT);
+    assert!(!reopened.is_column_hidden(1));
+}
+
+#[test]
+fn pasted_formulas_shift_relative_references() {
+    let file = TempFile::new("paste-formula");
+    let mut app = open(&file);
+    enter_value(&mut app, "1");
+    app.workbook.spreadsheet.get_sheet_mut(&0).unwrap().get_cell_mut((1, 2)).set_formula("A1+$A$1");
+    press(&mut app, KeyCode::Char('c'));
+    press(&mut app, KeyCode::Right);
+    press(&mut app, KeyCode::Char('v'));
+    assert_eq!(app.current_cell_full_value(), "=B1+$A$1");
+
+    // A reference pushed off the sheet can't be kept
+    assert_eq!(shift_formula("SUM(A2:B3)+'Q 1'!C$4", -1, 1), "SUM(B1:C2)+'Q 1'!D$4");
+    assert_eq!(shift_formula("A1*2", 0, -1), "#REF!*2");
+}
+
+#[test]
+fn enter_returns_to_the_start_of_a_tab_run() {
+    let file = TempFile::new("tab-run");
+    let mut app = open(&file);
+    app.return_to_entry_column = true;
+    place_cursor(&mut app, 1, 2);
+    for c in ['a', 'b'] {
+        press(&mut app, KeyCode::F(2));
+        press(&mut app, KeyCode::Char(c));
+        press(&mut app, KeyCode::Tab);
+    }
+    assert_eq!(app.workbook.cursor, (1, 4));
+    enter_value(&mut app, "c");
+    assert_eq!(app.workbook.cursor, (2, 2));
+
+    // Without a Tab run Enter just steps as configured
+    app.enter_moves = EntryMove::Right;
+    enter_value(&mut app, "d");
+    assert_eq!(app.workbook.cursor, (2, 3));
+}
+
+#[test]
+fn opening_at_a_cell_and_bad_locations() {
+    let file = TempFile::new("open-at");
+    let mut app = open(&file);
+    app.open_at(None, Some("c7"));
+    assert_eq!(app.workbook.cursor, (7, 3));
+
+    app.open_at(Some("No such sheet"), Some("A0"));
+    assert_eq!(app.workbook.cursor, (7, 3));
+    assert_eq!(app.workbook.current_sheet_index, 0);
+    assert!(app.status_message.as_deref().is_some_and(|msg| msg.starts_with("Warning:")));
+}
+
+#[test]
+fn peeking_shows_only_the_cursor_cells_formula() {
+    let file = TempFile::new("peek");
+    let mut app = open(&file);
+    let sheet = app.workbook.spreadsheet.get_sheet_mut(&0).unwrap();
+    sheet.get_cell_mut((1, 1)).set_formula("1+2");
+    sheet.get_cell_mut((1, 2)).set_formula("SUM(A1:A1)*100");
+    place_cursor(&mut app, 2, 1);
+    press(&mut app, KeyCode::Char('~'));
+    assert_eq!(app.get_cell_display(1, 2).text.trim(), "=SUM(A1:A1)*100");
+    assert!(app.shown_column_width(1) > app.get_column_width(1));
+    assert_ne!(app.get_cell_display(1, 1).text.trim(), "=1+2");
+
+    press(&mut app, KeyCode::Char('~'));
+    assert_eq!(app.shown_column_width(1), app.get_column_width(1));
+}
+
+#[test]
+fn typed_values_get_their_cell_types() {
+    assert_eq!(parse_input_to_cell_value("true"), InputValue::Bool(true));
+    assert_eq!(parse_input_to_cell_value("-1.5e3"), InputValue::Number(-1500.0));
+    assert_eq!(parse_input_to_cell_value("#n/a"), InputValue::Error("#N/A".to_string()));
+    assert_eq!(parse_input_to_cell_value("'123"), InputValue::Text("123".to_string()));
+    assert_eq!(parse_input_to_cell_value("inf"), InputValue::Text("inf".to_string()));
+
+    let file = TempFile::new("types");
+    let mut app = open(&file);
+    enter_value(&mut app, "false");
+    enter_value(&mut app, "#DIV/0!");
+    save(&mut app);
+
+    let reopened = open(&file);
+    assert_eq!(reopened.cell_type(1, 1), CellType::Boolean);
+    assert_eq!(reopened.get_cell_text(1, 1), "FALSE");
+    assert_eq!(reopened.cell_type(1, 2), CellType::Error);
+}
+
+#[test]
+fn numbers_and_forced_text_round_trip() {
+    let file = TempFile::new("numbers");
+    let mut app = open(&file);
+    enter_value(&mut app, "42");
+    enter_value(&mut app, "3.25");
+    enter_value(&mut app, "'0123");
+    save(&mut app);
+
+    let mut reopened = open(&file);
+    assert_eq!(reopened.cell_type(1, 1), CellType::Number);
+    assert_eq!(reopened.cell_type(1, 2), CellType::Number);
+    assert_eq!(reopened.cell_type(1, 3), CellType::Text);
+    assert_eq!(reopened.get_cell_text(1, 3), "0123");
+
+    // Editing the zip code brings its apostrophe back, so committing it unchanged keeps it text
+    place_cursor(&mut reopened, 3, 1);
+    press(&mut reopened, KeyCode::F(2));
+    assert_eq!(reopened.textarea.lines(), ["'0123"]);
+    press(&mut reopened, KeyCode::Enter);
+    assert_eq!(reopened.cell_type(1, 3), CellType::Text);
+}
+
+#[test]
+fn hidden_sheets_are_never_left_current() {
+    let file = TempFile::new("hidden-sheets");
+    let mut book = umya_spreadsheet::new_file();
+    book.get_sheet_mut(&0).unwrap().set_sheet_state("hidden".to_string());
+    book.new_sheet("Second").unwrap();
+    book.new_sheet("Third").unwrap();
+    umya_spreadsheet::writer::xlsx::write(&book, file.path()).unwrap();
+
+    // The file opens on its first visible sheet
+    let mut app = open(&file);
+    assert_eq!(app.workbook.current_sheet_index, 1);
+
+    // Hiding the current sheet moves on to the next visible one
+    press(&mut app, KeyCode::F(4));
+    press_with(&mut app, KeyCode::Char('v'), KeyModifiers::CONTROL);
+    assert!(app.is_sheet_hidden(1));
+    assert_eq!(app.workbook.current_sheet_index, 2);
+
+    // The last visible sheet stays visible
+    app.sheet_select_index = 2;
+    press_with(&mut app, KeyCode::Char('v'), KeyModifiers::CONTROL);
+    assert!(!app.is_sheet_hidden(2));
+    assert_eq!(app.workbook.current_sheet_index, 2);
+}
+
+#[test]
+fn tab_color_is_set_from_the_sheet_selector() {
+    let file = TempFile::new("tab-color");
+    let mut app = open(&file);
+    press(&mut app, KeyCode::F(4));
+    press_with(&mut app, KeyCode::Char('t'), KeyModifiers::CONTROL);
+    assert_eq!(app.mode, Mode::ColorPick);
+    press(&mut app, KeyCode::Char('4'));
+    assert_eq!(app.mode, Mode::SheetSelect);
+    assert_eq!(app.sheet_tab_color(0), Some(COLOR_PALETTE[4].1));
+    press(&mut app, KeyCode::Esc);
+    save(&mut app);
+
+    let mut reopened = open(&file);
+    assert_eq!(reopened.sheet_tab_color(0), Some(COLOR_PALETTE[4].1));
+    press(&mut reopened, KeyCode::F(4));
+    press_with(&mut reopened, KeyCode::Char('t'), KeyModifiers::CONTROL);
+    press(&mut reopened, KeyCode::Delete);
+    assert_eq!(reopened.sheet_tab_color(0), None);
+}
+
+#[test]
+fn a_white_fill_from_the_picker_survives_a_reload() {
+    let file = TempFile::new("white-fill");
+    let mut app = open(&file);
+    press(&mut app, KeyCode::Char('p'));
+    press(&mut app, KeyCode::Char('0'));
+    let white = CellMark::Custom { argb: COLOR_PALETTE[0].1, is_bg: true };
+    assert_eq!(app.get_cell_mark(1, 1), white);
+    save(&mut app);
+
+    let reopened = open(&file);
+    assert_eq!(reopened.get_cell_mark(1, 1), white);
+}
+
+#[test]
+fn fill_down_continues_a_series() {
+    let file = TempFile::new("series");
+    let mut app = open(&file);
+    enter_value(&mut app, "1");
+    enter_value(&mut app, "3");
+    place_cursor(&mut app, 1, 1);
+    for _ in 0..4 {
+        press_with(&mut app, KeyCode::Down, KeyModifiers::SHIFT);
+    }
+    press_with(&mut app, KeyCode::Char('d'), KeyModifiers::CONTROL);
+    assert_eq!(app.get_cell_text(1, 5), "9");
+    assert_eq!(app.cell_type(1, 5), CellType::Number);
+
+    // Alt forces a plain copy of the first cell
+    press_with(&mut app, KeyCode::Char('d'), KeyModifiers::CONTROL | KeyModifiers::ALT);
+    assert_eq!(app.get_cell_text(1, 2), "1");
+    assert_eq!(app.get_cell_text(1, 5), "1");
+}
+
+#[test]
+fn a_fill_is_undone_as_one_step() {
+    let file = TempFile::new("undo-fill");
+    let mut app = open(&file);
+    enter_value(&mut app, "top");
+    enter_value(&mut app, "kept");
+    save(&mut app);
+
+    place_cursor(&mut app, 1, 1);
+    press_with(&mut app, KeyCode::Down, KeyModifiers::SHIFT);
+    press_with(&mut app, KeyCode::Down, KeyModifiers::SHIFT);
+    press_with(&mut app, KeyCode::Char('d'), KeyModifiers::CONTROL);
+    assert_eq!(app.get_cell_text(1, 2), "top");
+    assert_eq!(app.get_cell_text(1, 3), "top");
+
+    press_with(&mut app, KeyCode::Char('z'), KeyModifiers::CONTROL);
+    assert_eq!(app.get_cell_text(1, 2), "kept");
+    assert!(app.is_cell_blank(1, 3));
+    assert_eq!(app.last_used_row(), 2);
+
+    press_with(&mut app, KeyCode::Char('z'), KeyModifiers::CONTROL);
+    assert_eq!(app.status_message.as_deref(), Some("Nothing to undo"));
+}
+
+#[test]
+fn series_fill_stays_exact_for_large_and_decimal_steps() {
+    let file = TempFile::new("series-precision");
+    let mut app = open(&file);
+    enter_value(&mut app, "1000000000");
+    enter_value(&mut app, "1000000001");
+    place_cursor(&mut app, 1, 2);
+    enter_value(&mut app, "0.1");
+    enter_value(&mut app, "0.2");
+    place_cursor(&mut app, 1, 1);
+    press_with(&mut app, KeyCode::Right, KeyModifiers::SHIFT);
+    for _ in 0..9 {
+        press_with(&mut app, KeyCode::Down, KeyModifiers::SHIFT);
+    }
+    press_with(&mut app, KeyCode::Char('d'), KeyModifiers::CONTROL);
+
+    for row in 3..=10 {
+        assert_eq!(app.get_cell_text(1, row), (999_999_999 + row).to_string());
+    }
+    assert_eq!(app.get_cell_text(2, 3), "0.3");
+    assert_eq!(app.get_cell_text(2, 10), "1");
+}
+
+#[test]
+fn typing_in_the_sheet_selector_jumps_to_a_sheet() {
+    let file = TempFile::new("sheet-type-ahead");
+    let mut app = open(&file);
+    for name in ["Summary", "Data 2024", "Drafts"] {
+        app.workbook.spreadsheet.new_sheet(name).unwrap();
+    }
+    press(&mut app, KeyCode::F(4));
+
+    // A prefix match wins over one further into a name, case-insensitively
+    press(&mut app, KeyCode::Char('D'));
+    assert_eq!(app.sheet_select_index, 2);
+    press(&mut app, KeyCode::Char('r'));
+    assert_eq!(app.sheet_select_index, 3);
+    press(&mut app, KeyCode::Backspace);
+    assert_eq!(app.sheet_filter, "D");
+    assert_eq!(app.sheet_select_index, 2);
+
+    // Without a prefix match any name containing the text is taken; no match leaves the highlight
+    press(&mut app, KeyCode::Esc);
+    assert_eq!(app.mode, Mode::SheetSelect);
+    for c in "2024".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    assert_eq!(app.sheet_select_index, 2);
+    press(&mut app, KeyCode::Char('x'));
+    assert_eq!(app.filtered_sheet_index(), None);
+    assert_eq!(app.sheet_select_index, 2);
+
+    press(&mut app, KeyCode::Enter);
+    assert_eq!(app.mode, Mode::View);
+    assert_eq!(app.workbook.current_sheet_index, 2);
+}
+
+#[test]
+fn sheet_selector_pages_through_long_lists() {
+    let file = TempFile::new("many-sheets");
+    let mut app = open(&file);
+    for i in 2..=30 {
+        app.workbook.spreadsheet.new_sheet(format!("Sheet{}", i)).unwrap();
+    }
+    press(&mut app, KeyCode::F(4));
+    press(&mut app, KeyCode::PageDown);
+    assert_eq!(app.sheet_select_index, SHEET_LIST_ROWS);
+    press(&mut app, KeyCode::End);
+    assert_eq!(app.sheet_select_index, 29);
+    press(&mut app, KeyCode::PageDown);
+    assert_eq!(app.sheet_select_index, 29);
+    press(&mut app, KeyCode::Home);
+    assert_eq!(app.sheet_select_index, 0);
+}
+
+#[test]
+fn sheet_references_quote_names_when_needed() {
+    let cell = Selection::single(5, 3);
+    let range = Selection { start: (9, 5), end: (5, 3) };
+    assert_eq!(sheet_reference("Sheet1", cell), "Sheet1!C5");
+    assert_eq!(sheet_reference("Sheet1", range), "Sheet1!C5:E9");
+    assert_eq!(sheet_reference("Q1 Sales", cell), "'Q1 Sales'!C5");
+    assert_eq!(sheet_reference("Bob's", cell), "'Bob''s'!C5");
+    assert_eq!(sheet_reference("AB12", cell), "'AB12'!C5");
+}
+
+#[test]
+fn protected_locked_cells_refuse_changes() {
+    let file = TempFile::new("locked");
+    let mut app = open(&file);
+    enter_value(&mut app, "keep");
+    press(&mut app, KeyCode::Up);
+    press(&mut app, KeyCode::Char('l'));
+    press(&mut app, KeyCode::Char('L'));
+    assert!(app.protect_locked);
+
+    press(&mut app, KeyCode::Delete);
+    press(&mut app, KeyCode::F(2));
+    assert_eq!(app.mode, Mode::View);
+    assert_eq!(app.get_cell_text(1, 1), "keep");
+    save(&mut app);
+
+    // The lock is saved; protection is not
+    let mut reopened = open(&file);
+    assert!(reopened.is_cell_locked(1, 1));
+    assert!(!reopened.protect_locked);
+    press(&mut reopened, KeyCode::Delete);
+    assert!(reopened.is_cell_blank(1, 1));
+}
+
+#[test]
+fn right_to_left_grid_mirrors_horizontal_keys() {
+    let file = TempFile::new("rtl");
+    let mut app = open(&file);
+    app.right_to_left = true;
+    place_cursor(&mut app, 1, 2);
+
+    // Column A is on the right, so Right moves toward it and Left away from it
+    press(&mut app, KeyCode::Right);
+    assert_eq!(app.workbook.cursor, (1, 1));
+    press(&mut app, KeyCode::Left);
+    press(&mut app, KeyCode::Char('a'));
+    assert_eq!(app.workbook.cursor, (1, 3));
+    press(&mut app, KeyCode::Char('d'));
+    assert_eq!(app.workbook.cursor, (1, 2));
+}
+
+#[test]
+fn duplicates_are_marked() {
+    let file = TempFile::new("dups");
+    let mut app = open(&file);
+    for value in ["apple", "Apple", "pear", "2", "2.0", "3"] {
+        enter_value(&mut app, value);
+    }
+
+    let message = app.execute_command("dups").unwrap();
+    assert!(message.contains("2 duplicated value(s) in 4 cell(s)"), "{}", message);
+    for row in [1, 2, 4, 5] {
+        assert_eq!(app.workbook.cell_marks.get(&(0, row, 1)), Some(&CellMark::YellowBg));
+    }
+    assert!(!app.workbook.cell_marks.contains_key(&(0, 3, 1)));
+
+    // Case-sensitive, "apple" and "Apple" no longer match
+    app.execute_command("dups case none").unwrap();
+    let message = app.execute_command("dups case red").unwrap();
+    assert!(message.contains("1 duplicated value(s) in 2 cell(s)"), "{}", message);
+}
+
+#[test]
+fn saving_over_outside_changes_asks_first() {
+    let file = TempFile::new("changed");
+    let mut app = open(&file);
+    enter_value(&mut app, "mine");
+    save(&mut app);
+    assert!(!app.changed_on_disk());
+
+    // Another program rewrites the file
+    let mut other = open(&file);
+    press(&mut other, KeyCode::Up);
+    enter_value(&mut other, "theirs");
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    save(&mut other);
+    assert!(app.changed_on_disk());
+
+    enter_value(&mut app, "more");
+    assert!(app.execute_command("w").is_err());
+    press_with(&mut app, KeyCode::Char('s'), KeyModifiers::CONTROL);
+    assert_eq!(app.mode, Mode::ConfirmDiskChange);
+
+    // r reloads the file as the other program left it
+    press(&mut app, KeyCode::Char('r'));
+    assert_eq!(app.mode, Mode::View);
+    assert_eq!(app.get_cell_text(1, 1), "theirs");
+    assert!(!app.workbook.dirty);
+    assert!(!app.changed_on_disk());
+}
+
+#[test]
+fn reload_discards_changes_only_when_told_to() {
+    let file = TempFile::new("reload");
+    let mut app = open(&file);
+    enter_value(&mut app, "saved");
+    save(&mut app);
+    enter_value(&mut app, "unsaved");
+    assert!(app.workbook.dirty);
+
+    assert!(app.execute_command("reload").is_err());
+    assert_eq!(app.get_cell_text(1, 2), "unsaved");
+    app.execute_command("reload!").unwrap();
+    assert!(!app.workbook.dirty);
+    assert_eq!(app.workbook.cursor, (1, 1));
+    assert_eq!(app.get_cell_text(1, 1), "saved");
+    assert!(app.is_cell_blank(1, 2));
+
+    // A deleted file keeps what is on screen
+    std::fs::remove_file(file.path()).unwrap();
+    assert!(app.execute_command("reload!").is_err());
+    assert_eq!(app.get_cell_text(1, 1), "saved");
+}
+
+#[test]
+fn piped_columns_are_typed_when_every_value_fits() {
+    let rows: Vec<Vec<String>> = [
+        ["id", "price", "date", "zip", "note"],
+        ["1", "2.5", "2024-03-01", "01234", "x"],
+        ["2", "-3", "2024-03-02 14:30:00", "98765", "7"],
+    ]
+    .iter()
+    .map(|row| row.iter().map(|value| value.to_string()).collect())
+    .collect();
+    assert_eq!(
+        infer_column_types(&rows),
+        [ColumnType::Number, ColumnType::Number, ColumnType::Date, ColumnType::Text, ColumnType::Text]
+    );
+
+    let file = TempFile::new("typed-csv");
+    let mut app = open(&file);
+    app.load_delimited_text("id,when\n7,2024-03-01\n8,2024-03-02\n", true);
+    assert_eq!(app.cell_type(1, 1), CellType::Text);
+    assert_eq!(app.cell_type(1, 2), CellType::Number);
+    assert_eq!(app.cell_type(2, 3), CellType::Date);
+
+    let mut app = open(&file);
+    app.load_delimited_text("id\n7\n", false);
+    assert_eq!(app.cell_type(1, 2), CellType::Text);
+}
+
+#[test]
+fn marks_are_tallied_per_sheet() {
+    let file = TempFile::new("mark-counts");
+    let mut app = open(&file);
+    assert!(app.execute_command("marks").unwrap().starts_with("No marked cells"));
+
+    press(&mut app, KeyCode::Char('3'));
+    press_with(&mut app, KeyCode::Down, KeyModifiers::SHIFT);
+    press(&mut app, KeyCode::Char('2'));
+    place_cursor(&mut app, 5, 5);
+    press(&mut app, KeyCode::Char('2'));
+
+    assert_eq!(app.mark_counts(), [(CellMark::YellowBg, 3)]);
+    place_cursor(&mut app, 1, 1);
+    press(&mut app, KeyCode::Char('3'));
+    let message = app.execute_command("marks").unwrap();
+    assert_eq!(message, "Marks on Sheet1: yellow bg: 2, red text: 1 (3 cell(s))");
+}
+
+#[test]
+fn a_cut_does_not_follow_into_another_workbook() {
+    let first = TempFile::new("cut-first");
+    let second = TempFile::new("cut-second");
+    let mut other = open(&second);
+    enter_value(&mut other, "keep");
+    save(&mut other);
+
+    let mut app = open(&first);
+    enter_value(&mut app, "moved");
+    app.open_workbook(second.path().to_path_buf()).unwrap();
+    place_cursor(&mut app, 1, 1);
+    press(&mut app, KeyCode::Char('x'));
+
+    // Pasting in the second workbook copies and leaves its own A1 alone
+    press(&mut app, KeyCode::F(7));
+    place_cursor(&mut app, 1, 2);
+    press(&mut app, KeyCode::Char('v'));
+    assert_eq!(app.get_cell_text(1, 1), "keep");
+    assert_eq!(app.get_cell_text(2, 1), "moved");
+
+    press(&mut app, KeyCode::F(7));
+    assert_eq!(app.get_cell_text(1, 1), "moved");
+}
+
+#[test]
+fn a_pasted_cut_removes_its_source_cells() {
+    let file = TempFile::new("cut-cleanup");
+    let mut app = open(&file);
+    place_cursor(&mut app, 5, 1);
+    enter_value(&mut app, "low");
+    place_cursor(&mut app, 5, 1);
+    press(&mut app, KeyCode::Char('x'));
+    place_cursor(&mut app, 1, 1);
+    press(&mut app, KeyCode::Char('v'));
+    assert_eq!(app.get_cell_text(1, 1), "low");
+    assert_eq!(app.last_used_row(), 1);
+}
+
+#[test]
+fn read_only_workbooks_are_never_written() {
+    let file = TempFile::new("read-only-pipe");
+    let mut app = open(&file);
+    app.read_only = true;
+    app.load_delimited_text("a,b\n1,2\n", true);
+    assert!(app.workbook.dirty);
+
+    assert!(app.execute_command("w!").is_err());
+    assert!(app.execute_command("wq").is_err());
+    press_with(&mut app, KeyCode::Char('w'), KeyModifiers::CONTROL);
+    assert_eq!(app.mode, Mode::ConfirmQuit);
+    press(&mut app, KeyCode::Char('s'));
+    assert!(!app.should_quit);
+    assert!(!file.path().exists());
+}
+
+#[test]
+fn piped_input_is_read_only_up_to_the_row_limit() {
+    let text = "a\n\"multi\nline\"\nb\nc\n";
+    let read = read_delimited_input(text.as_bytes(), 2).unwrap();
+    assert_eq!(read, "a\n\"multi\nline\"\nb\n");
+    let (rows, truncated) = parse_delimited(&read, ',', 2, 10);
+    assert_eq!(rows.len(), 2);
+    assert!(truncated);
+}
+
+#[test]
+fn visible_columns_fit_the_available_width() {
+    let ten = |_: u32| 10;
+    let shown = |_: u32| false;
+    // Narrower than a single column still shows the first one
+    assert_eq!(grid::visible_columns(0, 0, MAX_COLUMNS, 5, ten, shown), [1]);
+    assert_eq!(grid::visible_columns(0, 0, MAX_COLUMNS, 33, ten, shown), [1, 2, 3]);
+
+    // A column wider than the viewport stops the row, or stands alone when scrolled to
+    let wide = |col: u32| if col == 2 { 100 } else { 10 };
+    assert_eq!(grid::visible_columns(0, 0, MAX_COLUMNS, 30, wide, shown), [1]);
+    assert_eq!(grid::visible_columns(0, 1, MAX_COLUMNS, 30, wide, shown), [2]);
+
+    // Frozen columns come first, then the scrolled ones, skipping hidden columns
+    assert_eq!(grid::visible_columns(1, 3, MAX_COLUMNS, 33, ten, shown), [1, 5, 6]);
+    assert_eq!(grid::visible_columns(1, 3, MAX_COLUMNS, 33, ten, |col| col == 5), [1, 6, 7]);
+
+    // Scrolled to the last column, nothing runs past it
+    assert_eq!(grid::visible_columns(0, MAX_COLUMNS - 1, MAX_COLUMNS, 100, ten, shown), [MAX_COLUMNS]);
+}
+
+#[test]
+fn grid_positions_map_to_cells_and_back() {
+    // Frozen column 1 and row 1, scrolled to column E and row 7
+    let layout = GridLayout {
+        origin: (0, 0),
+        row_number_width: 4,
+        row_height: 1,
+        columns: vec![(1, 10), (5, 10)],
+        rows: vec![1, 7],
+        row_numbers_right: false,
+    };
+    // Column letters and row numbers aren't cells
+    assert_eq!(grid::cell_at_screen(5, 0, &layout), None);
+    assert_eq!(grid::cell_at_screen(3, 1, &layout), None);
+    assert_eq!(grid::cell_at_screen(5, 1, &layout), Some((1, 1)));
+    assert_eq!(grid::cell_at_screen(15, 1, &layout), Some((1, 1))); // the spacing column
+    assert_eq!(grid::cell_at_screen(16, 2, &layout), Some((7, 5)));
+    assert_eq!(grid::cell_at_screen(27, 2, &layout), None);
+    assert_eq!(grid::cell_at_screen(16, 3, &layout), None);
+
+    assert_eq!(grid::screen_rect_for_cell(7, 5, &layout), Some(Rect::new(16, 2, 10, 1)));
+    assert_eq!(grid::screen_rect_for_cell(2, 5, &layout), None);
+    assert_eq!(grid::screen_rect_for_cell(7, 2, &layout), None);
+
+    // Taller rows and row numbers on the right (--rtl)
+    let layout = GridLayout { row_height: 2, row_numbers_right: true, columns: vec![(5, 10), (1, 10)], ..layout };
+    assert_eq!(grid::cell_at_screen(0, 4, &layout), Some((7, 5)));
+    assert_eq!(grid::cell_at_screen(11, 2, &layout), Some((1, 1)));
+    assert_eq!(grid::screen_rect_for_cell(1, 1, &layout), Some(Rect::new(11, 1, 10, 2)));
+}
+
+#[test]
+fn replace_and_text_commands_skip_locked_cells() {
+    let file = TempFile::new("locked-replace");
+    let mut app = open(&file);
+    enter_value(&mut app, "cat");
+    enter_value(&mut app, "cat");
+    place_cursor(&mut app, 1, 1);
+    press(&mut app, KeyCode::Char('l'));
+    press(&mut app, KeyCode::Char('L'));
+
+    press_with(&mut app, KeyCode::Char('f'), KeyModifiers::CONTROL);
+    for c in "cat".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    press(&mut app, KeyCode::Tab);
+    for c in "dog".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    press_with(&mut app, KeyCode::Char('a'), KeyModifiers::CONTROL);
+    assert_eq!(app.get_cell_text(1, 1), "cat");
+    assert_eq!(app.get_cell_text(1, 2), "dog");
+    assert!(app.status_message.as_deref().unwrap_or_default().contains("1 locked cell(s) skipped"));
+
+    let message = app.execute_command("upper all").unwrap();
+    assert_eq!(message, "Changed 1 cell(s) (1 locked cell(s) skipped)");
+    assert_eq!(app.get_cell_text(1, 1), "cat");
+    assert_eq!(app.get_cell_text(1, 2), "DOG");
+}
+
+#[test]
+fn replace_all_is_undone_as_one_step() {
+    let file = TempFile::new("undo-replace");
+    let mut app = open(&file);
+    for value in ["cat", "black cat", "dog"] {
+        enter_value(&mut app, value);
+    }
+    press_with(&mut app, KeyCode::Char('f'), KeyModifiers::CONTROL);
+    for c in "cat".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    press(&mut app, KeyCode::Tab);
+    for c in "owl".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    press_with(&mut app, KeyCode::Char('a'), KeyModifiers::CONTROL);
+    assert_eq!(app.get_cell_text(1, 1), "owl");
+    assert_eq!(app.get_cell_text(1, 2), "black owl");
+
+    press_with(&mut app, KeyCode::Char('z'), KeyModifiers::CONTROL);
+    assert_eq!(app.get_cell_text(1, 1), "cat");
+    assert_eq!(app.get_cell_text(1, 2), "black cat");
+    assert_eq!(app.get_cell_text(1, 3), "dog");
+    assert_eq!(app.status_message.as_deref(), Some("Undid replace all (2 cell(s))"));
+}
+
+#[test]
+fn truncation_keeps_wide_and_combined_characters_whole() {
+    use unicode_segmentation::UnicodeSegmentation;
+    use unicode_width::UnicodeWidthStr;
+
+    let texts = [
+        "日本語のテキスト",
+        "表計算ABC表",
+        "👍🎉🚀 launch",
+        "👨‍👩‍👧 family 🇯🇵",
+        "cafe\u{301} re\u{301}sume\u{301}",
+        "a\u{300}\u{301}\u{302}b\u{303}c",
+    ];
+    for text in texts {
+        let graphemes: Vec<&str> = text.graphemes(true).collect();
+        for width in 0..=text.width() + 1 {
+            let truncated = truncate_to_width(text, width);
+            assert!(truncated.width() <= width, "{:?} at width {} gave {:?}", text, width, truncated);
+            if text.width() <= width {
+                assert_eq!(truncated, text);
+                continue;
+            }
+            // Everything before the ellipsis is a run of the original's graphemes
+            let kept = truncated.strip_suffix('~').unwrap_or(&truncated);
+            let kept: Vec<&str> = kept.graphemes(true).collect();
+            assert_eq!(kept, graphemes[..kept.len()], "{:?} at width {} gave {:?}", text, width, truncated);
+        }
+    }
+    // A double-width character that would overrun an odd width is dropped, not split
+    assert_eq!(truncate_to_width("日本語", 4), "日~");
+    assert_eq!(truncate_to_width("👍👍👍", 3), "👍~");
+    assert_eq!(truncate_to_width("e\u{301}e\u{301}e\u{301}", 2), "e\u{301}~");
+}
+
+#[test]
+fn edited_text_wraps_at_spaces_and_tracks_the_cursor() {
+    let lines = |text: &[&str]| text.iter().map(|line| line.to_string()).collect::<Vec<_>>();
+    let wrap = |text: &[&str], cursor, width| wrap_for_editing(&lines(text), cursor, width);
+
+    // The break comes after the last space that fits; a word longer than the box is split
+    assert_eq!(wrap(&["hello world"], (0, 0), 8), (lines(&["hello ", "world"]), (0, 0)));
+    assert_eq!(wrap(&["abcdefghij"], (0, 9), 4), (lines(&["abcd", "efgh", "ij"]), (2, 1)));
+
+    // A character wider than the box still gets a row of its own
+    assert_eq!(wrap(&["日本"], (0, 1), 1), (lines(&["日", "本"]), (1, 0)));
+    assert_eq!(wrap(&["a日"], (0, 2), 2), (lines(&["a", "日"]), (1, 2)));
+
+    // Empty lines keep their row and can hold the cursor
+    assert_eq!(wrap(&["a", "", "b"], (1, 0), 5), (lines(&["a", "", "b"]), (1, 0)));
+    assert_eq!(wrap(&[""], (0, 0), 5), (lines(&[""]), (0, 0)));
+
+    // At a wrap boundary the cursor starts the next row; at the very end it stays on the last one
+    assert_eq!(wrap(&["hello world"], (0, 5), 8).1, (0, 5));
+    assert_eq!(wrap(&["hello world"], (0, 6), 8).1, (1, 0));
+    assert_eq!(wrap(&["hello world"], (0, 11), 8).1, (1, 5));
+    assert_eq!(wrap(&["abcd"], (0, 4), 4), (lines(&["abcd"]), (0, 4)));
+}
+
+#[test]
+fn repeated_headers_become_distinct_json_keys() {
+    let names = |list: &[&str]| list.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+    assert_eq!(unique_keys(names(&["id", "name", "email"])), names(&["id", "name", "email"]));
+    assert_eq!(
+        unique_keys(names(&["name", "name", "total", "name"])),
+        names(&["name", "name_2", "total", "name_3"])
+    );
+    // A suffixed key never collides with a header that already has that name
+    assert_eq!(unique_keys(names(&["a", "a_2", "a"])), names(&["a", "a_2", "a_3"]));
+}
+
+#[test]
+fn search_respects_case_and_whole_cell_options() {
+    let loose = SearchOptions::default();
+    assert!(loose.matches("Total Sales", "sales"));
+    assert_eq!(loose.replace("Sales and SALES", "sales", "x"), ("x and x".to_string(), 2));
+
+    let exact_case = SearchOptions { case_sensitive: true, ..Default::default() };
+    assert!(!exact_case.matches("Total Sales", "sales"));
+    assert_eq!(exact_case.replace("Sales and sales", "sales", "x"), ("Sales and x".to_string(), 1));
+
+    let whole = SearchOptions { whole_cell: true, ..Default::default() };
+    assert!(whole.matches("Sales", "sales"));
+    assert!(!whole.matches("Total Sales", "sales"));
+    assert_eq!(whole.replace("Sales", "sales", "Revenue"), ("Revenue".to_string(), 1));
+    assert_eq!(whole.replace("Total Sales", "sales", "Revenue"), ("Total Sales".to_string(), 0));
+}
+
+#[test]
+fn search_can_ignore_accents() {
+    let plain = SearchOptions::default();
+    assert!(!plain.matches("café", "cafe"));
+
+    let folded = SearchOptions { ignore_accents: true, ..Default::default() };
+    // Precomposed and combining-mark spellings both match the bare letters, and the other way round
+    assert!(folded.matches("café", "cafe"));
+    assert!(folded.matches("cafe\u{301}", "cafe"));
+    assert!(folded.matches("cafe", "CAFÉ"));
+    assert!(SearchOptions { whole_cell: true, ..folded }.matches("Ångström", "angstrom"));
+
+    // A replaced match takes its trailing combining marks with it, and nothing else
+    assert_eq!(folded.replace("cafe\u{301}s", "cafe", "tea"), ("teas".to_string(), 1));
+    assert_eq!(folded.replace("e\u{301}\u{308}x e\u{301}", "e", "a"), ("ax a".to_string(), 2));
+    assert_eq!(folded.replace("日本e\u{301}語", "e", "-"), ("日本-語".to_string(), 1));
+}
+
+#[test]
+fn a_query_of_only_combining_marks_matches_nothing() {
+    let folded = SearchOptions { ignore_accents: true, ..Default::default() };
+    assert!(!folded.matches("café", "\u{301}"));
+    assert!(!folded.matches("", "\u{301}"));
+    assert!(!SearchOptions { whole_cell: true, ..folded }.matches("", "\u{301}\u{308}"));
+    assert_eq!(folded.replace("cafe\u{301}", "\u{301}", "x"), ("cafe\u{301}".to_string(), 0));
+    assert!(!folded.matches("anything", ""));
+
+    // Without folding the mark is an ordinary character
+    assert_eq!(SearchOptions::default().replace("cafe\u{301}", "\u{301}", ""), ("cafe".to_string(), 1));
+}